@@ -0,0 +1,4 @@
+// Runs the emulator of a container with a `data-worker` attribute, off the
+// main thread of the page.
+importScripts('./chiphuit.js');
+wasm_bindgen('./chiphuit_bg.wasm');
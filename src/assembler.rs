@@ -0,0 +1,955 @@
+//! # A module to assemble Octo source code into a ROM.
+//!
+//! The assembler understands the statements of the Octo language, which
+//! mirror the mnemonics of `disassembler::Syntax::Octo`, along with:
+//! - labels (`: name`), called by their bare name, and the `main` entry point,
+//! - `:const name value` and `:alias name register`,
+//! - `:macro name arguments { body }`,
+//! - `:org address`, `:byte value` and `:call address`,
+//! - `if ... then`, `if ... begin ... else ... end` and
+//!   `loop ... while ... again` blocks.
+//!
+//! The comparison pseudo-operators (`<`, `>`, ...), `:calc`, `:unpack`,
+//! `:next` and `:stringmode` are not supported.
+//!
+//! The resulting ROM starts at address 512, and can be loaded as is with
+//! `Emulator::load_rom`. A `SourceMap` links the instructions of the ROM to
+//! the lines of the source code, and their addresses to the labels, for
+//! debuggers.
+use core::fmt;
+use std::collections::{HashMap, VecDeque};
+
+/// Address the ROM is loaded at.
+const ROM_START: usize = 0x200;
+
+/// Macros expanded within each other beyond this depth are reported as
+/// recursive.
+const MAX_MACRO_DEPTH: usize = 64;
+
+/// A mistake in the assembled source code.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct AssemblerError {
+    /// Line of the source code, starting at 1.
+    pub line: usize,
+    pub message: String,
+}
+
+/// Display Trait to report an `AssemblerError` to the user.
+impl fmt::Display for AssemblerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for AssemblerError {}
+
+/// Assembles Octo `source` code into the bytes of a ROM.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssemblerError> {
+    assemble_with_source_map(source).map(|(rom, _)| rom)
+}
+
+/// Assembles Octo `source` code into the bytes of a ROM, along with the
+/// `SourceMap` of its instructions.
+pub fn assemble_with_source_map(source: &str) -> Result<(Vec<u8>, SourceMap), AssemblerError> {
+    let mut assembler = Assembler::new(source);
+    assembler.run()?;
+
+    let mut labels: Vec<(u16, String)> = assembler
+        .labels
+        .into_iter()
+        .map(|(name, address)| (address as u16, name))
+        .collect();
+    labels.sort();
+    let source_map = SourceMap {
+        instructions: assembler.instructions,
+        labels,
+    };
+    Ok((assembler.rom, source_map))
+}
+
+/// Returns an `Emulator` which loaded the ROM assembled from `source`, for
+/// the tests running Octo programs.
+#[cfg(test)]
+pub(crate) fn assembled_emulator(source: &str) -> crate::cpu::Emulator {
+    let mut emulator = crate::cpu::Emulator::new();
+    emulator.load_rom(&assemble(source).unwrap()).unwrap();
+    emulator
+}
+
+/// The lines of the source code the instructions of a ROM were assembled
+/// from, and the addresses of its labels.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct SourceMap {
+    /// Address and line, starting at 1, of each instruction, in the order
+    /// they were assembled.
+    instructions: Vec<(u16, usize)>,
+    /// Labels sorted by address.
+    labels: Vec<(u16, String)>,
+}
+
+impl SourceMap {
+    /// Returns the line of the instruction at `address`, if any.
+    pub fn line(&self, address: u16) -> Option<usize> {
+        self.instructions
+            .iter()
+            .rev()
+            .find(|&&(start, _)| start == address)
+            .map(|&(_, line)| line)
+    }
+
+    /// Returns the first line with instructions from `line` on, and the
+    /// address of its first instruction.
+    pub fn resolve(&self, line: usize) -> Option<(usize, u16)> {
+        let line = self
+            .instructions
+            .iter()
+            .map(|&(_, other)| other)
+            .filter(|&other| other >= line)
+            .min()?;
+        self.instructions
+            .iter()
+            .filter(|&&(_, other)| other == line)
+            .map(|&(address, _)| (line, address))
+            .min_by_key(|&(_, address)| address)
+    }
+
+    /// Returns a `SourceMap` of the labels of a symbol file, which has an
+    /// address in hexadecimal and a name per line, such as `0x0200 main`.
+    /// Empty lines and lines starting with `#` are skipped.
+    pub fn from_symbols(symbols: &str) -> Result<SourceMap, AssemblerError> {
+        let mut labels = Vec::new();
+        for (index, line) in symbols.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: &str| AssemblerError {
+                line: index + 1,
+                message: message.to_string(),
+            };
+            let mut words = line.split_whitespace();
+            let address = words.next().unwrap();
+            let address = address.strip_prefix("0x").unwrap_or(address);
+            let address =
+                u16::from_str_radix(address, 16).map_err(|_| error("invalid symbol address"))?;
+            let name = match (words.next(), words.next()) {
+                (Some(name), None) => name,
+                _ => return Err(error("expected an address and a name")),
+            };
+            labels.push((address, name.to_string()));
+        }
+        labels.sort();
+        Ok(SourceMap {
+            instructions: Vec::new(),
+            labels,
+        })
+    }
+
+    /// Returns the name of the closest label at or before `address`, if
+    /// any.
+    pub fn label(&self, address: u16) -> Option<&str> {
+        self.labels
+            .iter()
+            .rev()
+            .find(|&&(start, _)| start <= address)
+            .map(|(_, name)| name.as_str())
+    }
+
+    /// Returns `address` relative to the closest label at or before it, such
+    /// as `main+0x4`, if any.
+    pub fn symbol(&self, address: u16) -> Option<String> {
+        let (start, name) = self
+            .labels
+            .iter()
+            .rev()
+            .find(|&&(start, _)| start <= address)?;
+        Some(match address - start {
+            0 => name.clone(),
+            offset => format!("{}+{:#X}", name, offset),
+        })
+    }
+}
+
+/// A word of the source code, the line it was read on, and the number of
+/// macros it was expanded from.
+#[derive(Clone)]
+struct Token {
+    text: String,
+    line: usize,
+    depth: usize,
+}
+
+/// An address of the ROM to patch once `label` is defined.
+struct Fixup {
+    address: usize,
+    label: String,
+    line: usize,
+    /// Whether the address is a 16 bits word, rather than the 12 lowest bits
+    /// of an instruction.
+    long: bool,
+}
+
+/// A control flow block waiting for its closing statement, holding the
+/// addresses of the jumps to patch.
+enum Block {
+    If(usize),
+    Else(usize),
+    Loop { start: usize, whiles: Vec<usize> },
+}
+
+struct Assembler {
+    tokens: VecDeque<Token>,
+    line: usize,
+    /// Macro depth of the last token consumed.
+    depth: usize,
+    rom: Vec<u8>,
+    here: usize,
+    labels: HashMap<String, usize>,
+    constants: HashMap<String, i32>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, (Vec<String>, Vec<Token>)>,
+    fixups: Vec<Fixup>,
+    blocks: Vec<Block>,
+    /// Address and line of each assembled instruction.
+    instructions: Vec<(u16, usize)>,
+}
+
+impl Assembler {
+    /// Splits `source` into tokens, dropping the `#` comments.
+    fn new(source: &str) -> Assembler {
+        let tokens = source
+            .lines()
+            .enumerate()
+            .flat_map(|(index, line)| {
+                line.split('#')
+                    .next()
+                    .unwrap_or("")
+                    .split_whitespace()
+                    .map(move |text| Token {
+                        text: text.to_string(),
+                        line: index + 1,
+                        depth: 0,
+                    })
+            })
+            .collect();
+
+        Assembler {
+            tokens,
+            line: 1,
+            depth: 0,
+            rom: Vec::new(),
+            here: ROM_START,
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            fixups: Vec::new(),
+            blocks: Vec::new(),
+            instructions: Vec::new(),
+        }
+    }
+
+    /// Returns an `AssemblerError` at the current line.
+    fn error<T>(&self, message: String) -> Result<T, AssemblerError> {
+        Err(AssemblerError {
+            line: self.line,
+            message,
+        })
+    }
+
+    /// Consumes the next token.
+    fn next(&mut self) -> Result<String, AssemblerError> {
+        match self.tokens.pop_front() {
+            Some(token) => {
+                self.line = token.line;
+                self.depth = token.depth;
+                Ok(token.text)
+            }
+            None => self.error("unexpected end of file".to_string()),
+        }
+    }
+
+    /// Returns the next token without consuming it.
+    fn peek(&self) -> Option<&str> {
+        self.tokens.front().map(|token| token.text.as_str())
+    }
+
+    /// Consumes the next token, which must be `expected`.
+    fn expect(&mut self, expected: &str) -> Result<(), AssemblerError> {
+        let token = self.next()?;
+        if token != expected {
+            return self.error(format!("expected '{}', found '{}'", expected, token));
+        }
+        Ok(())
+    }
+
+    /// Writes `byte` at the current address.
+    fn emit(&mut self, byte: u8) -> Result<(), AssemblerError> {
+        let index = self.here - ROM_START;
+        if self.here > 0xFFFF {
+            return self.error("the program doesn't fit in memory".to_string());
+        }
+        if index >= self.rom.len() {
+            self.rom.resize(index + 1, 0);
+        }
+        self.rom[index] = byte;
+        self.here += 1;
+        Ok(())
+    }
+
+    /// Writes a 2 bytes instruction at the current address.
+    fn instruction(&mut self, opcode: u16) -> Result<(), AssemblerError> {
+        self.instructions.push((self.here as u16, self.line));
+        self.emit((opcode >> 8) as u8)?;
+        self.emit(opcode as u8)
+    }
+
+    /// Points the 12 bits address of the instruction at `address` to
+    /// `target`, which must be in its reach.
+    fn patch(&mut self, address: usize, target: usize) -> Result<(), AssemblerError> {
+        if target > 0xFFF {
+            return self.error(format!("jump target is out of reach: {:#X}", target));
+        }
+        let index = address - ROM_START;
+        self.rom[index] = self.rom[index] & 0xF0 | (target >> 8) as u8;
+        self.rom[index + 1] = target as u8;
+        Ok(())
+    }
+
+    /// Writes the instruction `opcode` with the address `token`, deferring
+    /// the address if it is a label yet to be defined.
+    fn address_instruction(&mut self, opcode: u16, token: &str) -> Result<(), AssemblerError> {
+        let address = self.here;
+        match self.value(token) {
+            Ok(value) if (0..=0xFFF).contains(&value) => self.instruction(opcode | value as u16),
+            Ok(value) => self.error(format!("address out of range: {:#X}", value)),
+            Err(_) if is_identifier(token) => {
+                self.fixups.push(Fixup {
+                    address,
+                    label: token.to_string(),
+                    line: self.line,
+                    long: false,
+                });
+                self.instruction(opcode)
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Returns the value of a number literal, a constant or a defined label.
+    fn value(&self, token: &str) -> Result<i32, AssemblerError> {
+        if let Some(&value) = self.constants.get(token) {
+            return Ok(value);
+        }
+        if let Some(&address) = self.labels.get(token) {
+            return Ok(address as i32);
+        }
+        let (negative, digits) = match token.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, token),
+        };
+        let value = if let Some(hex) = digits.strip_prefix("0x") {
+            i32::from_str_radix(hex, 16)
+        } else if let Some(binary) = digits.strip_prefix("0b") {
+            i32::from_str_radix(binary, 2)
+        } else {
+            digits.parse()
+        };
+        match value {
+            Ok(value) if negative => Ok(-value),
+            Ok(value) => Ok(value),
+            Err(_) => self.error(format!("undefined name: {}", token)),
+        }
+    }
+
+    /// Reads a value in `range`.
+    fn bounded(
+        &mut self,
+        range: core::ops::RangeInclusive<i32>,
+        name: &str,
+    ) -> Result<i32, AssemblerError> {
+        let token = self.next()?;
+        let value = self.value(&token)?;
+        if !range.contains(&value) {
+            return self.error(format!("{} out of range: {}", name, value));
+        }
+        Ok(value)
+    }
+
+    /// Reads a byte, negative values being stored in two's complement.
+    fn byte(&mut self) -> Result<u16, AssemblerError> {
+        Ok(self.bounded(-128..=255, "byte")? as u8 as u16)
+    }
+
+    /// Reads a nibble.
+    fn nibble(&mut self) -> Result<u16, AssemblerError> {
+        Ok(self.bounded(0..=15, "nibble")? as u16)
+    }
+
+    /// Returns the index of the register named `token`, if it is one.
+    fn register_index(&self, token: &str) -> Option<u16> {
+        if let Some(&register) = self.aliases.get(token) {
+            return Some(register as u16);
+        }
+        let lowercase = token.to_ascii_lowercase();
+        let digit = lowercase.strip_prefix('v')?;
+        match digit.len() {
+            1 => u16::from_str_radix(digit, 16).ok(),
+            _ => None,
+        }
+    }
+
+    /// Reads a register.
+    fn register(&mut self) -> Result<u16, AssemblerError> {
+        let token = self.next()?;
+        match self.register_index(&token) {
+            Some(register) => Ok(register),
+            None => self.error(format!("expected a register, found '{}'", token)),
+        }
+    }
+
+    /// Assembles the whole source, then resolves the forward references.
+    fn run(&mut self) -> Result<(), AssemblerError> {
+        // Reserved for a jump to `main`, which is dropped if `main` directly
+        // follows.
+        self.instruction(0x1000)?;
+        self.fixups.push(Fixup {
+            address: ROM_START,
+            label: "main".to_string(),
+            line: 1,
+            long: false,
+        });
+        // The jump isn't part of the source code.
+        self.instructions.clear();
+
+        while !self.tokens.is_empty() {
+            self.statement()?;
+        }
+
+        if let Some(block) = self.blocks.last() {
+            let statement = match block {
+                Block::If(_) | Block::Else(_) => "end",
+                Block::Loop { .. } => "again",
+            };
+            return self.error(format!("missing '{}'", statement));
+        }
+        if !self.labels.contains_key("main") {
+            return self.error("missing the 'main' label".to_string());
+        }
+
+        for fixup in std::mem::take(&mut self.fixups) {
+            let target = match self.labels.get(&fixup.label) {
+                Some(&target) => target,
+                None => {
+                    return Err(AssemblerError {
+                        line: fixup.line,
+                        message: format!("undefined label: {}", fixup.label),
+                    })
+                }
+            };
+            let index = fixup.address - ROM_START;
+            if fixup.long {
+                self.rom[index] = (target >> 8) as u8;
+                self.rom[index + 1] = target as u8;
+            } else if target > 0xFFF {
+                return Err(AssemblerError {
+                    line: fixup.line,
+                    message: format!("label {} is out of reach: {:#X}", fixup.label, target),
+                });
+            } else {
+                self.patch(fixup.address, target)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Assembles a single statement.
+    fn statement(&mut self) -> Result<(), AssemblerError> {
+        let token = self.next()?;
+
+        if let Some(x) = self.register_index(&token) {
+            return self.register_statement(x);
+        }
+        if let Some((parameters, body)) = self.macros.get(&token).cloned() {
+            return self.expand(parameters, body);
+        }
+
+        match token.as_str() {
+            ":" => {
+                let name = self.next()?;
+                if name == "main" && self.here == ROM_START + 2 && self.rom.len() == 2 {
+                    self.rom.clear();
+                    self.here = ROM_START;
+                    self.fixups.retain(|fixup| fixup.label != "main");
+                } else if name == "main" {
+                    // The jump to `main` belongs to its label.
+                    self.instructions.insert(0, (ROM_START as u16, self.line));
+                }
+                if !is_identifier(&name) || self.labels.contains_key(&name) {
+                    return self.error(format!("invalid or duplicate label: {}", name));
+                }
+                self.labels.insert(name, self.here);
+            }
+            ":const" => {
+                let name = self.next()?;
+                let value = self.next()?;
+                let value = self.value(&value)?;
+                self.constants.insert(name, value);
+            }
+            ":alias" => {
+                let name = self.next()?;
+                let register = self.register()?;
+                self.aliases.insert(name, register as u8);
+            }
+            ":org" => {
+                let address = self.bounded(ROM_START as i32..=0xFFFF, "address")?;
+                self.here = address as usize;
+            }
+            ":macro" => self.define_macro()?,
+            ":byte" => {
+                let byte = self.byte()?;
+                self.emit(byte as u8)?;
+            }
+            ":call" => {
+                let target = self.next()?;
+                self.address_instruction(0x2000, &target)?;
+            }
+            "clear" => self.instruction(0x00E0)?,
+            "return" | ";" => self.instruction(0x00EE)?,
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.instruction(0x00C0 | n)?;
+            }
+            "scroll-up" => {
+                let n = self.nibble()?;
+                self.instruction(0x00D0 | n)?;
+            }
+            "scroll-right" => self.instruction(0x00FB)?,
+            "scroll-left" => self.instruction(0x00FC)?,
+            "exit" => self.instruction(0x00FD)?,
+            "lores" => self.instruction(0x00FE)?,
+            "hires" => self.instruction(0x00FF)?,
+            "jump" => {
+                let target = self.next()?;
+                self.address_instruction(0x1000, &target)?;
+            }
+            "jump0" => {
+                let target = self.next()?;
+                self.address_instruction(0xB000, &target)?;
+            }
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let n = self.nibble()?;
+                self.instruction(0xD000 | x << 8 | y << 4 | n)?;
+            }
+            "bcd" => {
+                let x = self.register()?;
+                self.instruction(0xF033 | x << 8)?;
+            }
+            "save" | "load" => {
+                let x = self.register()?;
+                if self.peek() == Some("-") {
+                    self.next()?;
+                    let y = self.register()?;
+                    let opcode = if token == "save" { 0x5002 } else { 0x5003 };
+                    self.instruction(opcode | x << 8 | y << 4)?;
+                } else {
+                    let opcode = if token == "save" { 0xF055 } else { 0xF065 };
+                    self.instruction(opcode | x << 8)?;
+                }
+            }
+            "saveflags" => {
+                let x = self.register()?;
+                self.instruction(0xF075 | x << 8)?;
+            }
+            "loadflags" => {
+                let x = self.register()?;
+                self.instruction(0xF085 | x << 8)?;
+            }
+            "plane" => {
+                let n = self.bounded(0..=3, "plane")? as u16;
+                self.instruction(0xF001 | n << 8)?;
+            }
+            "audio" => self.instruction(0xF002)?,
+            "i" => self.index_statement()?,
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                let opcode = match token.as_str() {
+                    "delay" => 0xF015,
+                    "buzzer" => 0xF018,
+                    _ => 0xF03A,
+                };
+                self.instruction(opcode | x << 8)?;
+            }
+            "if" => {
+                let skip = self.condition()?;
+                match self.next()?.as_str() {
+                    "then" => self.instruction(skip)?,
+                    "begin" => {
+                        self.instruction(negate(skip))?;
+                        self.blocks.push(Block::If(self.here));
+                        self.instruction(0x1000)?;
+                    }
+                    other => {
+                        return self.error(format!("expected 'then' or 'begin', found '{}'", other))
+                    }
+                }
+            }
+            "else" => match self.blocks.pop() {
+                Some(Block::If(jump)) => {
+                    let exit = self.here;
+                    self.instruction(0x1000)?;
+                    self.patch(jump, self.here)?;
+                    self.blocks.push(Block::Else(exit));
+                }
+                _ => return self.error("'else' without 'if ... begin'".to_string()),
+            },
+            "end" => match self.blocks.pop() {
+                Some(Block::If(jump)) | Some(Block::Else(jump)) => self.patch(jump, self.here)?,
+                _ => return self.error("'end' without 'if ... begin'".to_string()),
+            },
+            "loop" => self.blocks.push(Block::Loop {
+                start: self.here,
+                whiles: Vec::new(),
+            }),
+            "while" => {
+                let skip = self.condition()?;
+                self.instruction(negate(skip))?;
+                let exit = self.here;
+                match self.blocks.iter_mut().rev().find_map(|block| match block {
+                    Block::Loop { whiles, .. } => Some(whiles),
+                    _ => None,
+                }) {
+                    Some(whiles) => whiles.push(exit),
+                    None => return self.error("'while' without 'loop'".to_string()),
+                }
+                self.instruction(0x1000)?;
+            }
+            "again" => match self.blocks.pop() {
+                Some(Block::Loop { start, whiles }) => {
+                    if start > 0xFFF {
+                        return self.error(format!("jump target is out of reach: {:#X}", start));
+                    }
+                    self.instruction(0x1000 | start as u16)?;
+                    for exit in whiles {
+                        self.patch(exit, self.here)?;
+                    }
+                }
+                _ => return self.error("'again' without 'loop'".to_string()),
+            },
+            _ if self.labels.contains_key(&token) => self.address_instruction(0x2000, &token)?,
+            _ => match self.value(&token) {
+                Ok(value) if (-128..=255).contains(&value) => self.emit(value as u8)?,
+                Ok(value) => return self.error(format!("byte out of range: {}", value)),
+                Err(_) if is_identifier(&token) => self.address_instruction(0x2000, &token)?,
+                Err(_) => return self.error(format!("unsupported statement: {}", token)),
+            },
+        }
+        Ok(())
+    }
+
+    /// Assembles a statement assigning the register `x`.
+    fn register_statement(&mut self, x: u16) -> Result<(), AssemblerError> {
+        let operator = self.next()?;
+        let operand = self.next()?;
+        let x = x << 8;
+
+        if let Some(y) = self.register_index(&operand) {
+            let y = y << 4;
+            let opcode = match operator.as_str() {
+                ":=" => 0x8000,
+                "|=" => 0x8001,
+                "&=" => 0x8002,
+                "^=" => 0x8003,
+                "+=" => 0x8004,
+                "-=" => 0x8005,
+                ">>=" => 0x8006,
+                "=-" => 0x8007,
+                "<<=" => 0x800E,
+                _ => return self.error(format!("unsupported operator: {}", operator)),
+            };
+            return self.instruction(opcode | x | y);
+        }
+
+        match (operator.as_str(), operand.as_str()) {
+            (":=", "delay") => self.instruction(0xF007 | x),
+            (":=", "key") => self.instruction(0xF00A | x),
+            (":=", "random") => {
+                let mask = self.byte()?;
+                self.instruction(0xC000 | x | mask)
+            }
+            (":=", _) | ("+=", _) | ("-=", _) => {
+                self.tokens.push_front(Token {
+                    text: operand,
+                    line: self.line,
+                    depth: self.depth,
+                });
+                let byte = self.byte()?;
+                match operator.as_str() {
+                    ":=" => self.instruction(0x6000 | x | byte),
+                    "+=" => self.instruction(0x7000 | x | byte),
+                    _ => self.instruction(0x7000 | x | (byte as u8).wrapping_neg() as u16),
+                }
+            }
+            _ => self.error(format!("unsupported operator: {}", operator)),
+        }
+    }
+
+    /// Assembles a statement assigning the index register.
+    fn index_statement(&mut self) -> Result<(), AssemblerError> {
+        let operator = self.next()?;
+        let operand = self.next()?;
+
+        match (operator.as_str(), operand.as_str()) {
+            ("+=", _) => {
+                self.tokens.push_front(Token {
+                    text: operand,
+                    line: self.line,
+                    depth: self.depth,
+                });
+                let x = self.register()?;
+                self.instruction(0xF01E | x << 8)
+            }
+            (":=", "hex") => {
+                let x = self.register()?;
+                self.instruction(0xF029 | x << 8)
+            }
+            (":=", "bighex") => {
+                let x = self.register()?;
+                self.instruction(0xF030 | x << 8)
+            }
+            (":=", "long") => {
+                let target = self.next()?;
+                self.instruction(0xF000)?;
+                match self.value(&target) {
+                    Ok(value) if (0..=0xFFFF).contains(&value) => self.instruction(value as u16),
+                    Ok(value) => self.error(format!("address out of range: {:#X}", value)),
+                    Err(_) if is_identifier(&target) => {
+                        self.fixups.push(Fixup {
+                            address: self.here,
+                            label: target,
+                            line: self.line,
+                            long: true,
+                        });
+                        self.instruction(0)
+                    }
+                    Err(error) => Err(error),
+                }
+            }
+            (":=", _) => self.address_instruction(0xA000, &operand),
+            _ => self.error(format!("unsupported operator: {}", operator)),
+        }
+    }
+
+    /// Reads a condition, and returns the instruction skipping the next one
+    /// when the condition is false.
+    fn condition(&mut self) -> Result<u16, AssemblerError> {
+        let x = self.register()? << 8;
+        let operator = self.next()?;
+
+        match operator.as_str() {
+            "key" => return Ok(0xE0A1 | x),
+            "-key" => return Ok(0xE09E | x),
+            "==" | "!=" => {}
+            _ => return self.error(format!("unsupported comparison: {}", operator)),
+        }
+
+        let operand = self.next()?;
+        if let Some(y) = self.register_index(&operand) {
+            let opcode = if operator == "==" { 0x9000 } else { 0x5000 };
+            return Ok(opcode | x | y << 4);
+        }
+        self.tokens.push_front(Token {
+            text: operand,
+            line: self.line,
+            depth: self.depth,
+        });
+        let byte = self.byte()?;
+        let opcode = if operator == "==" { 0x4000 } else { 0x3000 };
+        Ok(opcode | x | byte)
+    }
+
+    /// Reads the parameters and the body of a macro.
+    fn define_macro(&mut self) -> Result<(), AssemblerError> {
+        let name = self.next()?;
+        let mut parameters = Vec::new();
+        loop {
+            match self.next()? {
+                brace if brace == "{" => break,
+                parameter => parameters.push(parameter),
+            }
+        }
+
+        let mut body = Vec::new();
+        let mut depth = 1;
+        while depth > 0 {
+            let token = match self.tokens.pop_front() {
+                Some(token) => token,
+                None => return self.error(format!("unterminated macro: {}", name)),
+            };
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" => depth -= 1,
+                _ => {}
+            }
+            if depth > 0 {
+                body.push(token);
+            }
+        }
+        self.macros.insert(name, (parameters, body));
+        Ok(())
+    }
+
+    /// Replaces the invocation of a macro by its body, with its parameters
+    /// substituted by the arguments that follow.
+    fn expand(&mut self, parameters: Vec<String>, body: Vec<Token>) -> Result<(), AssemblerError> {
+        let depth = self.depth + 1;
+        if depth > MAX_MACRO_DEPTH {
+            return self.error("macros expanded too deep, are they recursive?".to_string());
+        }
+        let mut arguments = HashMap::new();
+        for parameter in parameters {
+            let argument = self.next()?;
+            arguments.insert(parameter, argument);
+        }
+        for token in body.into_iter().rev() {
+            self.tokens.push_front(Token {
+                text: arguments.get(&token.text).cloned().unwrap_or(token.text),
+                line: self.line,
+                depth,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Returns whether `token` can name a label.
+fn is_identifier(token: &str) -> bool {
+    matches!(token.chars().next(), Some(first) if first.is_ascii_alphabetic() || first == '_')
+        && token
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Returns the skip instruction testing the opposite condition of `skip`.
+fn negate(skip: u16) -> u16 {
+    match skip & 0xF00F {
+        0xE001 => skip & 0xFF00 | 0x9E,
+        0xE00E => skip & 0xFF00 | 0xA1,
+        _ => match skip >> 12 {
+            3 => skip & 0x0FFF | 0x4000,
+            4 => skip & 0x0FFF | 0x3000,
+            5 => skip & 0x0FFF | 0x9000,
+            _ => skip & 0x0FFF | 0x5000,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::StepOutcome;
+    use crate::disassembler::{disassemble, Syntax};
+
+    const SOURCE: &str = "
+        : main
+            v0 := 0
+            i := counter
+            loop
+                v0 += 1
+                count
+                if v0 != 5 then
+            again
+            exit
+
+        : count
+            v1 := 2
+            v2 += v1
+            return
+
+        : counter
+            :byte 0x12
+    ";
+
+    /// Returns the Octo mnemonics of `rom`.
+    fn mnemonics(rom: &[u8]) -> Vec<String> {
+        let mut memory = vec![0; ROM_START];
+        memory.extend(rom);
+        disassemble(&memory, ROM_START..memory.len(), Syntax::Octo)
+            .into_iter()
+            .map(|instruction| instruction.mnemonic)
+            .collect()
+    }
+
+    #[test]
+    fn disassembles_assembled_source() {
+        let rom = assemble(SOURCE).unwrap();
+        assert_eq!(
+            mnemonics(&rom),
+            [
+                "v0 := 0x00",
+                "i := 0x214",
+                "v0 += 0x01",
+                ":call 0x20E",
+                "if v0 != 0x05 then",
+                "jump 0x204",
+                "exit",
+                "v1 := 0x02",
+                "v2 += v1",
+                "return",
+                "0x12",
+            ]
+        );
+    }
+
+    #[test]
+    fn reassembles_disassembled_rom() {
+        let rom = assemble(SOURCE).unwrap();
+        let source = format!(": main\n{}", mnemonics(&rom).join("\n"));
+        assert_eq!(assemble(&source).unwrap(), rom);
+    }
+
+    #[test]
+    fn runs_assembled_rom() {
+        let mut emulator = assembled_emulator(SOURCE);
+        let mut cycles = 0;
+        while emulator.cycle().unwrap() != StepOutcome::Exited {
+            cycles += 1;
+            assert!(cycles < 100, "the ROM should exit");
+        }
+        let registers = emulator.registers();
+        assert_eq!((registers[0], registers[1], registers[2]), (5, 2, 10));
+        assert_eq!(emulator.index_register(), 0x214);
+        assert_eq!(emulator.memory()[0x214], 0x12);
+    }
+
+    #[test]
+    fn maps_instructions_to_lines_and_labels() {
+        let (_, source_map) = assemble_with_source_map(SOURCE).unwrap();
+        assert_eq!(source_map.line(0x200), Some(3));
+        assert_eq!(source_map.resolve(12), Some((13, 0x20E)));
+        assert_eq!(source_map.label(0x210), Some("count"));
+        assert_eq!(source_map.symbol(0x210).as_deref(), Some("count+0x2"));
+    }
+
+    #[test]
+    fn rejects_jumps_out_of_reach() {
+        let sources = [
+            ": main :org 0x1000 loop again",
+            ": main :org 0x1000 if v0 == 1 begin end",
+            ": main jump far :org 0x1000 : far",
+        ];
+        for source in sources.iter() {
+            let error = assemble(source).unwrap_err();
+            assert!(error.message.contains("out of reach"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn rejects_recursive_macros() {
+        let error = assemble(":macro forever { forever } : main forever").unwrap_err();
+        assert!(error.message.contains("too deep"), "{}", error);
+    }
+}
@@ -1,6 +1,12 @@
 use crate::cpu;
+use serde::{Deserialize, Serialize};
 use wasm_bindgen::prelude::*;
-use web_sys::{AudioContext, GainNode, OscillatorNode, OscillatorType};
+use web_sys::{AudioBufferSourceNode, AudioContext, GainNode, OscillatorNode, OscillatorType};
+
+/// Sample rate of the buffers built from XO-CHIP audio patterns, the pitch is
+/// applied through the playback rate of their source.
+const PATTERN_SAMPLE_RATE: f32 = 8000.0;
+
 pub struct FmOsc {
     _ctx: AudioContext,
     _primary: OscillatorNode,
@@ -9,6 +15,8 @@ pub struct FmOsc {
     _fm_osc: OscillatorNode,
     _fm_freq_ratio: f32,
     _fm_gain_ratio: f32,
+    pattern_gain: GainNode,
+    pattern_source: Option<(AudioBufferSourceNode, [u8; 16], u8)>,
 }
 
 impl FmOsc {
@@ -19,6 +27,7 @@ impl FmOsc {
         let _fm_osc = _ctx.create_oscillator()?;
         let gain = _ctx.create_gain()?;
         let _fm_gain = _ctx.create_gain()?;
+        let pattern_gain = _ctx.create_gain()?;
 
         _primary.set_type(OscillatorType::Sine);
         _primary.frequency().set_value(880.0);
@@ -26,11 +35,13 @@ impl FmOsc {
         _fm_gain.gain().set_value(800.0);
         _fm_osc.set_type(OscillatorType::Sine);
         _fm_osc.frequency().set_value(50.0);
+        pattern_gain.gain().set_value(0.04);
 
         _primary.connect_with_audio_node(&gain)?;
         gain.connect_with_audio_node(&_ctx.destination())?;
         _fm_osc.connect_with_audio_node(&_fm_gain)?;
         _fm_gain.connect_with_audio_param(&_primary.frequency())?;
+        pattern_gain.connect_with_audio_node(&_ctx.destination())?;
 
         _primary.start()?;
         _fm_osc.start()?;
@@ -43,16 +54,86 @@ impl FmOsc {
             _fm_osc,
             _fm_freq_ratio: 10.0,
             _fm_gain_ratio: 10.0,
+            pattern_gain,
+            pattern_source: None,
         })
     }
+
+    /// Loops the 128 1-bit samples of an XO-CHIP audio pattern at the rate
+    /// given by the pitch register, restarting the playback only when the
+    /// pattern or the pitch changed.
+    fn play_pattern(&mut self, pattern: [u8; 16], pitch: u8) -> Result<(), JsValue> {
+        if let Some((_, playing_pattern, playing_pitch)) = &self.pattern_source {
+            if *playing_pattern == pattern && *playing_pitch == pitch {
+                return Ok(());
+            }
+        }
+        self.stop_pattern();
+
+        let samples: Vec<f32> = pattern
+            .iter()
+            .flat_map(|byte| {
+                (0..8)
+                    .rev()
+                    .map(move |bit| (byte >> bit & 1) as f32 * 2.0 - 1.0)
+            })
+            .collect();
+
+        let buffer = self
+            ._ctx
+            .create_buffer(1, samples.len() as u32, PATTERN_SAMPLE_RATE)?;
+        buffer.copy_to_channel(&samples, 0)?;
+
+        let source = self._ctx.create_buffer_source()?;
+        source.set_buffer(Some(&buffer));
+        source.set_loop(true);
+        let rate = 4000.0 * 2f32.powf((pitch as f32 - 64.0) / 48.0);
+        source.playback_rate().set_value(rate / PATTERN_SAMPLE_RATE);
+        source.connect_with_audio_node(&self.pattern_gain)?;
+        source.start()?;
+
+        self.pattern_source = Some((source, pattern, pitch));
+        Ok(())
+    }
+
+    /// Stops the playback of the XO-CHIP audio pattern, if any.
+    fn stop_pattern(&mut self) {
+        if let Some((source, _, _)) = self.pattern_source.take() {
+            source.stop().unwrap();
+        }
+    }
+}
+
+/// What the buzzer of an `Emulator` plays during a frame.
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
+pub struct Buzzer {
+    pub on: bool,
+    /// The XO-CHIP audio pattern and its pitch, if any.
+    pub pattern: Option<([u8; 16], u8)>,
+}
+
+impl Buzzer {
+    pub fn new(emulator: &cpu::Emulator) -> Buzzer {
+        Buzzer {
+            on: emulator.sound_timer > 0,
+            pattern: emulator
+                .audio_pattern()
+                .map(|pattern| (pattern, emulator.pitch())),
+        }
+    }
 }
 
-pub fn sound(emulator: &mut cpu::Emulator, audio_context: &FmOsc) {
-    match emulator.sound_timer {
-        0 => audio_context.gain.gain().set_value(0.0),
-        _ => {
-            audio_context.gain.gain().set_value(0.04);
-            emulator.sound_timer -= 1;
+/// Plays the buzzer while the sound timer of the `Emulator` is set, to be
+/// called once per frame.
+pub fn sound(buzzer: &Buzzer, audio_context: &mut FmOsc) {
+    match buzzer.on {
+        false => {
+            audio_context.gain.gain().set_value(0.0);
+            audio_context.stop_pattern();
         }
+        true => match buzzer.pattern {
+            Some((pattern, pitch)) => audio_context.play_pattern(pattern, pitch).unwrap(),
+            None => audio_context.gain.gain().set_value(0.04),
+        },
     }
 }
@@ -0,0 +1,25 @@
+//! # `chiphuit asm`, assembles Octo source code into a ROM.
+//!
+//! usage: `chiphuit asm <source> [--output FILE]`
+//!
+//! The ROM is written next to the source with the `.ch8` extension, or to
+//! `--output`, `-` meaning stdout.
+use crate::{output, Options};
+use chiphuit::assembler;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+pub fn asm(options: &Options) -> Result<(), Box<dyn Error>> {
+    let path = options.positional(0, "source")?;
+    let source = fs::read_to_string(path)?;
+    let rom = assembler::assemble(&source).map_err(|error| format!("{}: {}", path, error))?;
+
+    match options.flag("output") {
+        Some(output_path) => output(output_path, &rom),
+        None => output(
+            &Path::new(path).with_extension("ch8").to_string_lossy(),
+            &rom,
+        ),
+    }
+}
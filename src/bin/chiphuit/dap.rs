@@ -0,0 +1,593 @@
+//! # `chiphuit dap`, debugs a ROM from an editor with the Debug Adapter
+//! Protocol.
+//!
+//! usage: `chiphuit dap [--port N]`
+//!
+//! The adapter speaks the protocol over stdin and stdout, as editors launch
+//! it, or over a single connection to `127.0.0.1` on `--port`. The `launch`
+//! request names the `program` to debug: Octo source code (`.8o`), assembled
+//! with a `SourceMap` to set breakpoints on its lines and to step line by
+//! line, or a ROM, stepped instruction by instruction. Its optional
+//! `stopOnEntry`, `speed`, `quirks` and `seed` arguments work as for
+//! `chiphuit run`.
+//!
+//! The `Emulator` is a single thread, with a stack frame per subroutine
+//! called, built from `Emulator::stack`, and its variables are the ones
+//! shown by the debugger of the browser, from `Print::printables`. Running
+//! ROMs can't read the keypad.
+use crate::Options;
+use chiphuit::assembler::{self, SourceMap};
+use chiphuit::breakpoint::{Breakpoint, Breakpoints, Location, Stop};
+use chiphuit::cpu::{Emulator, FaultPolicy, Print, StepOutcome, EMULATOR_VARIABLES};
+use chiphuit::quirks::Quirks;
+use chiphuit::scheduler::FRAME_RATE;
+use serde_json::{json, Value};
+use std::error::Error;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Id of the only thread of the `Emulator`.
+const THREAD_ID: u64 = 1;
+
+/// Reference of the variables of `Print::printables`.
+const VARIABLES_REFERENCE: u64 = 1;
+
+pub fn dap(options: &Options) -> Result<(), Box<dyn Error>> {
+    match options.flag("port") {
+        Some(_) => {
+            let port: u16 = options.parsed("port", 0)?;
+            let listener = TcpListener::bind(("127.0.0.1", port))?;
+            eprintln!("waiting for an editor on 127.0.0.1:{}", port);
+            let (stream, _) = listener.accept()?;
+            serve(stream.try_clone()?, stream)
+        }
+        None => serve(io::stdin(), io::stdout()),
+    }
+}
+
+/// Answers the requests read from `input` until the editor disconnects.
+/// The requests are read by another thread, so that they are received while
+/// the `Emulator` runs.
+fn serve(input: impl Read + Send + 'static, output: impl Write) -> Result<(), Box<dyn Error>> {
+    let (sender, requests) = mpsc::channel();
+    thread::spawn(move || {
+        let mut input = BufReader::new(input);
+        loop {
+            let request = match receive(&mut input) {
+                Ok(Some(request)) => request,
+                Ok(None) => break,
+                // A malformed message is skipped, the next one is read after
+                // its body.
+                Err(error) if error.kind() == io::ErrorKind::InvalidData => {
+                    eprintln!("skipping a malformed message: {}", error);
+                    continue;
+                }
+                Err(error) => {
+                    eprintln!("failed to read a message: {}", error);
+                    break;
+                }
+            };
+            if sender.send(request).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut adapter = Adapter {
+        output,
+        requests,
+        seq: 0,
+        session: None,
+    };
+    adapter.serve()?;
+    Ok(())
+}
+
+/// Reads a message, a `Content-Length` header followed by a JSON body, or
+/// returns `None` at the end of `input`. Fails with `InvalidData` if the
+/// header is missing or the body is not JSON.
+fn receive(input: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse().ok();
+        }
+    }
+    let length: usize = length.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length header")
+    })?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+/// Why the `Emulator` stopped running.
+enum Halt {
+    /// Paused, for a DAP `reason` such as `breakpoint` or `step`.
+    Stopped {
+        reason: &'static str,
+        description: Option<String>,
+    },
+    /// The ROM exited with `00FD`.
+    Exited,
+}
+
+/// A step of the editor, completed once the `Emulator` reaches another line
+/// of the source code.
+#[derive(Clone, Copy)]
+struct LineStep {
+    line: Option<usize>,
+    stack_pointer: usize,
+    over: bool,
+}
+
+/// The `Emulator` launched by the editor.
+struct Session {
+    emulator: Emulator,
+    breakpoints: Breakpoints,
+    /// The path of the source code of the ROM and its `SourceMap`, if the
+    /// program is Octo source code.
+    source: Option<(String, SourceMap)>,
+    speed: usize,
+    stop_on_entry: bool,
+    running: bool,
+    step: Option<LineStep>,
+}
+
+impl Session {
+    /// Loads the `program` of the `launch` request.
+    fn launch(arguments: &Value) -> Result<Session, Box<dyn Error>> {
+        let program = arguments["program"]
+            .as_str()
+            .ok_or("missing program to launch")?;
+        let (rom, source) = match Path::new(program).extension() {
+            Some(extension) if extension == "8o" => {
+                let (rom, source_map) =
+                    assembler::assemble_with_source_map(&fs::read_to_string(program)?)
+                        .map_err(|error| format!("{}: {}", program, error))?;
+                (rom, Some((program.to_string(), source_map)))
+            }
+            _ => (fs::read(program)?, None),
+        };
+        let quirks = match arguments["quirks"].as_str() {
+            Some(name) => {
+                Quirks::from_name(name).ok_or_else(|| format!("unknown quirks: {}", name))?
+            }
+            None => Quirks::default(),
+        };
+        let speed = arguments["speed"].as_u64().unwrap_or(10) as usize;
+        if speed == 0 {
+            return Err("speed must be at least 1".into());
+        }
+
+        let mut emulator = Emulator::with_quirks(quirks);
+        emulator.load_font();
+        emulator.load_rom(&rom)?;
+        emulator.set_fault_policy(FaultPolicy::Break);
+        if let Some(seed) = arguments["seed"].as_u64() {
+            emulator.seed_rng(seed);
+        }
+
+        Ok(Session {
+            emulator,
+            breakpoints: Breakpoints::new(),
+            source,
+            speed,
+            stop_on_entry: arguments["stopOnEntry"].as_bool().unwrap_or(false),
+            running: false,
+            step: None,
+        })
+    }
+
+    /// Returns the line of the source code of the instruction at `address`.
+    fn line(&self, address: u16) -> Option<usize> {
+        self.source
+            .as_ref()
+            .and_then(|(_, source_map)| source_map.line(address))
+    }
+
+    /// Starts a step of the editor, over subroutines if `over`, and by
+    /// instruction rather than by line if `instruction`.
+    fn step(&mut self, over: bool, instruction: bool) {
+        self.step_instruction(over);
+        self.step = match instruction || self.source.is_none() {
+            true => None,
+            false => Some(LineStep {
+                line: self.line(self.emulator.program_counter()),
+                stack_pointer: self.emulator.stack_pointer(),
+                over,
+            }),
+        };
+        self.running = true;
+    }
+
+    fn step_instruction(&mut self, over: bool) {
+        match over {
+            true => self.breakpoints.step_over(&self.emulator),
+            false => self.breakpoints.step_into(),
+        }
+    }
+
+    /// Returns whether the step in progress is complete once the
+    /// `Breakpoints` completed a step, on another line or out of the
+    /// subroutine it started in. Steps another instruction otherwise.
+    fn step_complete(&mut self) -> bool {
+        let step = match self.step {
+            Some(step) => step,
+            None => return true,
+        };
+        let line = self.line(self.emulator.program_counter());
+        let complete = self.emulator.stack_pointer() < step.stack_pointer
+            || line.is_some() && line != step.line;
+        if !complete {
+            self.step_instruction(step.over);
+        }
+        complete
+    }
+
+    /// Runs a frame of `speed` instructions, and returns why the `Emulator`
+    /// stopped, if it did.
+    fn frame(&mut self) -> Option<Halt> {
+        for _ in 0..self.speed {
+            let halt = match self.emulator.cycle() {
+                Ok(StepOutcome::Executed) | Ok(StepOutcome::Ignored(_)) => None,
+                Ok(StepOutcome::Exited) => Some(Halt::Exited),
+                Ok(StepOutcome::Watchpoint(hit)) => Some(Halt::Stopped {
+                    reason: "data breakpoint",
+                    description: Some(format!("{:?} of {:#X}", hit.access, hit.address)),
+                }),
+                Ok(StepOutcome::Break(error)) | Err(error) => Some(Halt::Stopped {
+                    reason: "exception",
+                    description: Some(error.to_string()),
+                }),
+            };
+            let halt = halt.or_else(|| match self.breakpoints.check(&self.emulator) {
+                Some(Stop::Breakpoint(_)) => Some(Halt::Stopped {
+                    reason: "breakpoint",
+                    description: None,
+                }),
+                Some(Stop::Step) if self.step_complete() => Some(Halt::Stopped {
+                    reason: "step",
+                    description: None,
+                }),
+                _ => None,
+            });
+            if halt.is_some() {
+                self.running = false;
+                self.step = None;
+                return halt;
+            }
+        }
+        self.emulator.vblank();
+        None
+    }
+
+    /// Returns the stack frames, from the program counter to the callers of
+    /// the subroutines in the stack.
+    fn stack_frames(&self) -> Vec<Value> {
+        self.emulator
+            .call_stack()
+            .into_iter()
+            .enumerate()
+            .map(|(id, address)| {
+                let label = self
+                    .source
+                    .as_ref()
+                    .and_then(|(_, source_map)| source_map.label(address));
+                let mut frame = json!({
+                    "id": id,
+                    "name": label.map_or_else(|| format!("{:#05X}", address), str::to_string),
+                    "line": 0,
+                    "column": 0,
+                    "instructionPointerReference": format!("{:#X}", address),
+                });
+                if let (Some((path, _)), Some(line)) = (&self.source, self.line(address)) {
+                    frame["source"] = json!({ "path": path });
+                    frame["line"] = json!(line);
+                    frame["column"] = json!(1);
+                }
+                frame
+            })
+            .collect()
+    }
+
+    /// Replaces the breakpoints by the ones of the `setBreakpoints` request,
+    /// moved to the next line with instructions, and returns them.
+    fn set_breakpoints(&mut self, arguments: &Value) -> Vec<Value> {
+        let lines: Vec<usize> = arguments["breakpoints"]
+            .as_array()
+            .map(|breakpoints| {
+                breakpoints
+                    .iter()
+                    .filter_map(|breakpoint| breakpoint["line"].as_u64())
+                    .map(|line| line as usize)
+                    .collect()
+            })
+            .unwrap_or_default();
+        let source_map = match (&self.source, arguments["source"]["path"].as_str()) {
+            (Some((path, source_map)), Some(other)) if same_file(path, other) => source_map,
+            _ => {
+                return lines
+                    .iter()
+                    .map(|&line| {
+                        json!({
+                            "verified": false,
+                            "line": line,
+                            "message": "not the source code of the program",
+                        })
+                    })
+                    .collect()
+            }
+        };
+
+        let mut breakpoints = Vec::new();
+        let replies = lines
+            .iter()
+            .map(|&line| match source_map.resolve(line) {
+                Some((line, address)) => {
+                    breakpoints.push(Breakpoint {
+                        location: Location::Address(address),
+                        condition: None,
+                    });
+                    json!({ "verified": true, "line": line })
+                }
+                None => json!({
+                    "verified": false,
+                    "line": line,
+                    "message": "no instruction from this line on",
+                }),
+            })
+            .collect();
+        self.breakpoints.set(&breakpoints);
+        replies
+    }
+
+    /// Returns the variables of the `Emulator`.
+    fn variables(&self) -> Vec<Value> {
+        EMULATOR_VARIABLES
+            .iter()
+            .zip(self.emulator.printables())
+            .map(|(name, value)| {
+                json!({
+                    "name": name,
+                    "value": value.trim_end_matches(',').trim(),
+                    "variablesReference": 0,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Returns whether the paths `a` and `b` name the same file.
+fn same_file(a: &str, b: &str) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+struct Adapter<W> {
+    output: W,
+    requests: Receiver<Value>,
+    /// Sequence number of the last message sent.
+    seq: u64,
+    session: Option<Session>,
+}
+
+impl<W: Write> Adapter<W> {
+    /// Answers the requests of the editor, running the `Emulator` frame after
+    /// frame at 60 frames per second in between.
+    fn serve(&mut self) -> io::Result<()> {
+        let frame = Duration::from_secs_f64(1.0 / FRAME_RATE);
+        let mut next_frame = Instant::now();
+
+        loop {
+            let running = matches!(&self.session, Some(session) if session.running);
+            let request = match running {
+                true => match self.requests.try_recv() {
+                    Ok(request) => Some(request),
+                    Err(TryRecvError::Empty) => None,
+                    Err(TryRecvError::Disconnected) => return Ok(()),
+                },
+                false => match self.requests.recv() {
+                    Ok(request) => Some(request),
+                    Err(_) => return Ok(()),
+                },
+            };
+            if let Some(request) = request {
+                if !self.handle(&request)? {
+                    return Ok(());
+                }
+                continue;
+            }
+
+            if let Some(halt) = self.session.as_mut().and_then(Session::frame) {
+                self.halt(halt)?;
+            }
+            next_frame += frame;
+            match next_frame.checked_duration_since(Instant::now()) {
+                Some(delay) => thread::sleep(delay),
+                None => next_frame = Instant::now(),
+            }
+        }
+    }
+
+    /// Answers a request, and returns false once the editor disconnects.
+    fn handle(&mut self, request: &Value) -> io::Result<bool> {
+        let command = request["command"].as_str().unwrap_or("");
+        let arguments = &request["arguments"];
+
+        let body = match (command, self.session.as_mut()) {
+            ("initialize", _) => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsSteppingGranularity": true,
+                "supportsTerminateRequest": true,
+            })),
+            ("launch", _) => match Session::launch(arguments) {
+                Ok(session) => {
+                    self.session = Some(session);
+                    self.respond(request, Ok(json!({})))?;
+                    self.event("initialized", json!({}))?;
+                    return Ok(true);
+                }
+                Err(error) => Err(error.to_string()),
+            },
+            ("disconnect", _) | ("terminate", _) => {
+                self.respond(request, Ok(json!({})))?;
+                return Ok(false);
+            }
+            ("threads", _) => Ok(json!({
+                "threads": [{ "id": THREAD_ID, "name": "chip8" }],
+            })),
+            ("setExceptionBreakpoints", _) => Ok(json!({})),
+            (_, None) => Err("no program launched".to_string()),
+            ("configurationDone", Some(session)) => {
+                // A breakpoint on the first instruction stops before it, as
+                // the breakpoints are otherwise only checked after a cycle.
+                let reason = match session.stop_on_entry {
+                    true => Some("entry"),
+                    false if session.breakpoints.hits(&session.emulator) => Some("breakpoint"),
+                    false => None,
+                };
+                match reason {
+                    Some(reason) => {
+                        self.respond(request, Ok(json!({})))?;
+                        self.halt(Halt::Stopped {
+                            reason,
+                            description: None,
+                        })?;
+                        return Ok(true);
+                    }
+                    None => session.running = true,
+                }
+                Ok(json!({}))
+            }
+            ("setBreakpoints", Some(session)) => Ok(json!({
+                "breakpoints": session.set_breakpoints(arguments),
+            })),
+            ("stackTrace", Some(session)) => {
+                let frames = session.stack_frames();
+                Ok(json!({ "stackFrames": frames, "totalFrames": frames.len() }))
+            }
+            ("scopes", Some(_)) => Ok(json!({
+                "scopes": [{
+                    "name": "Emulator",
+                    "variablesReference": VARIABLES_REFERENCE,
+                    "expensive": false,
+                }],
+            })),
+            ("variables", Some(session)) => {
+                let variables = match arguments["variablesReference"].as_u64() {
+                    Some(VARIABLES_REFERENCE) => session.variables(),
+                    _ => Vec::new(),
+                };
+                Ok(json!({ "variables": variables }))
+            }
+            ("continue", Some(session)) => {
+                session.running = true;
+                session.step = None;
+                Ok(json!({ "allThreadsContinued": true }))
+            }
+            ("next", Some(session)) | ("stepIn", Some(session)) => {
+                let instruction = arguments["granularity"] == "instruction";
+                session.step(command == "next", instruction);
+                Ok(json!({}))
+            }
+            ("stepOut", Some(session)) => {
+                session.breakpoints.step_out(&session.emulator);
+                session.step = None;
+                session.running = true;
+                Ok(json!({}))
+            }
+            ("pause", Some(session)) => {
+                let running = session.running;
+                session.running = false;
+                session.step = None;
+                self.respond(request, Ok(json!({})))?;
+                if running {
+                    self.halt(Halt::Stopped {
+                        reason: "pause",
+                        description: None,
+                    })?;
+                }
+                return Ok(true);
+            }
+            _ => Err(format!("unsupported request: {}", command)),
+        };
+        self.respond(request, body)?;
+        Ok(true)
+    }
+
+    /// Tells the editor why the `Emulator` stopped.
+    fn halt(&mut self, halt: Halt) -> io::Result<()> {
+        match halt {
+            Halt::Stopped {
+                reason,
+                description,
+            } => {
+                if let Some(session) = self.session.as_mut() {
+                    session.running = false;
+                }
+                let mut body = json!({
+                    "reason": reason,
+                    "threadId": THREAD_ID,
+                    "allThreadsStopped": true,
+                });
+                if let Some(description) = description {
+                    body["description"] = json!(description);
+                }
+                self.event("stopped", body)
+            }
+            Halt::Exited => {
+                self.event("exited", json!({ "exitCode": 0 }))?;
+                self.event("terminated", json!({}))
+            }
+        }
+    }
+
+    fn respond(&mut self, request: &Value, body: Result<Value, String>) -> io::Result<()> {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": body.is_ok(),
+        });
+        match body {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        self.send(response)
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        self.send(json!({ "type": "event", "event": event, "body": body }))
+    }
+
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        let body = message.to_string();
+        write!(
+            self.output,
+            "Content-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )?;
+        self.output.flush()
+    }
+}
@@ -0,0 +1,609 @@
+//! # `chiphuit gdb`, debugs a ROM from a GDB Remote Serial Protocol client.
+//!
+//! usage: `chiphuit gdb <rom> [--port N] [--speed N] [--quirks NAME]
+//! [--seed N]`
+//!
+//! The `Emulator` waits, paused on the first instruction of the ROM, for a
+//! debugger to connect to `127.0.0.1` on `--port` (1234 by default), such as
+//! GDB with `target remote :1234`. The session ends when the debugger
+//! detaches or disconnects.
+//!
+//! The register file, described to the debugger by `target.xml`, holds `V0`
+//! to `VF`, `I`, `PC`, `SP`, `DT` and `ST`, in this order. `I` and `PC` are
+//! 16 bits wide and big-endian like the chip8 memory, the others 8 bits wide.
+//! The 64K of memory are the address space of the target.
+//!
+//! Besides register and memory reads and writes, the stub supports software
+//! breakpoints (`Z0`, or `Z1`), watchpoints on writes, reads or any access
+//! (`Z2`, `Z3` and `Z4`), single-stepping and continuing. A continued
+//! `Emulator` runs `--speed` instructions (10 by default) per frame at 60
+//! frames per second until a breakpoint, a watchpoint, a fault, the end of
+//! the ROM or an interruption of the debugger.
+use crate::Options;
+use chiphuit::breakpoint::{Breakpoint, Breakpoints, Location};
+use chiphuit::cpu::{Emulator, FaultPolicy, StepOutcome};
+use chiphuit::error::EmulatorError;
+use chiphuit::quirks::Quirks;
+use chiphuit::scheduler::FRAME_RATE;
+use chiphuit::watchpoint::{Access, Watchpoint, WatchpointHit};
+use std::error::Error;
+use std::fs;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Signals reported to the debugger when the `Emulator` stops.
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+/// Interrupt sent by the debugger to stop a continued `Emulator`.
+const INTERRUPT: u8 = 0x03;
+
+/// Number of registers in the register file.
+const REGISTERS: usize = 21;
+
+/// Description of the register file, read by the debugger.
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.chiphuit.chip8">
+    <reg name="v0" bitsize="8" regnum="0"/>
+    <reg name="v1" bitsize="8"/>
+    <reg name="v2" bitsize="8"/>
+    <reg name="v3" bitsize="8"/>
+    <reg name="v4" bitsize="8"/>
+    <reg name="v5" bitsize="8"/>
+    <reg name="v6" bitsize="8"/>
+    <reg name="v7" bitsize="8"/>
+    <reg name="v8" bitsize="8"/>
+    <reg name="v9" bitsize="8"/>
+    <reg name="va" bitsize="8"/>
+    <reg name="vb" bitsize="8"/>
+    <reg name="vc" bitsize="8"/>
+    <reg name="vd" bitsize="8"/>
+    <reg name="ve" bitsize="8"/>
+    <reg name="vf" bitsize="8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="8"/>
+    <reg name="dt" bitsize="8"/>
+    <reg name="st" bitsize="8"/>
+  </feature>
+</target>
+"#;
+
+pub fn gdb(options: &Options) -> Result<(), Box<dyn Error>> {
+    let rom = fs::read(options.positional(0, "rom")?)?;
+    let quirks = match options.flag("quirks") {
+        Some(name) => Quirks::from_name(name).ok_or_else(|| format!("unknown quirks: {}", name))?,
+        None => Quirks::default(),
+    };
+    let speed: usize = options.parsed("speed", 10)?;
+    if speed == 0 {
+        return Err("--speed must be at least 1".into());
+    }
+    let port: u16 = options.parsed("port", 1234)?;
+
+    let mut emulator = Emulator::with_quirks(quirks);
+    emulator.load_font();
+    emulator.load_rom(&rom)?;
+    emulator.set_fault_policy(FaultPolicy::Break);
+    if options.flag("seed").is_some() {
+        emulator.seed_rng(options.parsed("seed", 0)?);
+    }
+
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    eprintln!("waiting for a debugger on 127.0.0.1:{}", port);
+    let (stream, address) = listener.accept()?;
+    eprintln!("debugger connected from {}", address);
+
+    let mut stub = Stub {
+        connection: Connection::new(stream),
+        emulator,
+        breakpoints: Breakpoints::new(),
+        speed,
+        stop: format!("S{:02x}", SIGTRAP),
+    };
+    stub.serve()?;
+    eprintln!("debugger disconnected");
+    Ok(())
+}
+
+/// A message of the debugger.
+enum Packet {
+    Command(String),
+    Interrupt,
+}
+
+/// The connection to the debugger, framing packets as `$data#checksum`.
+struct Connection {
+    stream: TcpStream,
+    buffer: Vec<u8>,
+    /// Whether packets are acknowledged with `+`, until `QStartNoAckMode`.
+    ack: bool,
+}
+
+impl Connection {
+    fn new(stream: TcpStream) -> Connection {
+        Connection {
+            stream,
+            buffer: Vec::new(),
+            ack: true,
+        }
+    }
+
+    /// Returns the next packet of the debugger, or `None` once it
+    /// disconnected.
+    fn receive(&mut self) -> io::Result<Option<Packet>> {
+        loop {
+            if let Some(packet) = self.parse()? {
+                return Ok(Some(packet));
+            }
+            let mut chunk = [0; 4096];
+            match self.stream.read(&mut chunk)? {
+                0 => return Ok(None),
+                read => self.buffer.extend_from_slice(&chunk[..read]),
+            }
+        }
+    }
+
+    /// Takes the first packet out of the buffer, if it is complete.
+    fn parse(&mut self) -> io::Result<Option<Packet>> {
+        while let Some(&byte) = self.buffer.first() {
+            match byte {
+                INTERRUPT => {
+                    self.buffer.remove(0);
+                    return Ok(Some(Packet::Interrupt));
+                }
+                b'$' => break,
+                // Acknowledgements, and noise between packets.
+                _ => {
+                    self.buffer.remove(0);
+                }
+            }
+        }
+        let end = match self.buffer.iter().position(|&byte| byte == b'#') {
+            Some(end) if self.buffer.len() >= end + 3 => end,
+            _ => return Ok(None),
+        };
+        let packet: Vec<u8> = self.buffer.drain(..end + 3).collect();
+        let data = &packet[1..end];
+        let checksum = std::str::from_utf8(&packet[end + 1..])
+            .ok()
+            .and_then(|checksum| u8::from_str_radix(checksum, 16).ok());
+
+        if checksum != Some(checksum_of(data)) {
+            if self.ack {
+                self.stream.write_all(b"-")?;
+            }
+            return Ok(None);
+        }
+        if self.ack {
+            self.stream.write_all(b"+")?;
+        }
+        Ok(Some(Packet::Command(
+            String::from_utf8_lossy(&unescape(data)).into_owned(),
+        )))
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let packet = format!("${}#{:02x}", data, checksum_of(data.as_bytes()));
+        self.stream.write_all(packet.as_bytes())
+    }
+
+    /// Returns whether the debugger interrupted the `Emulator`, without
+    /// waiting for it.
+    fn interrupted(&mut self) -> io::Result<bool> {
+        self.stream.set_nonblocking(true)?;
+        let mut chunk = [0; 4096];
+        let read = loop {
+            match self.stream.read(&mut chunk) {
+                Ok(read) => break Ok(read),
+                Err(error) if error.kind() == ErrorKind::WouldBlock => break Ok(0),
+                Err(error) if error.kind() == ErrorKind::Interrupted => {}
+                Err(error) => break Err(error),
+            }
+        };
+        self.stream.set_nonblocking(false)?;
+        self.buffer.extend_from_slice(&chunk[..read?]);
+
+        match self.buffer.iter().position(|&byte| byte == INTERRUPT) {
+            Some(index) => {
+                self.buffer.remove(index);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+/// Returns the checksum of a packet, the sum of its bytes modulo 256.
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, &byte| sum.wrapping_add(byte))
+}
+
+/// Removes the escaping of the binary data of a packet, `}` followed by the
+/// escaped byte xor 0x20.
+fn unescape(data: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(data.len());
+    let mut escaped = false;
+    for &byte in data {
+        match (escaped, byte) {
+            (false, b'}') => escaped = true,
+            (true, _) => {
+                bytes.push(byte ^ 0x20);
+                escaped = false;
+            }
+            (false, _) => bytes.push(byte),
+        }
+    }
+    bytes
+}
+
+/// An `Emulator` driven by a debugger.
+struct Stub {
+    connection: Connection,
+    emulator: Emulator,
+    breakpoints: Breakpoints,
+    /// Instructions run per frame while the `Emulator` is continued.
+    speed: usize,
+    /// The reply to the last stop of the `Emulator`, sent again on `?`.
+    stop: String,
+}
+
+impl Stub {
+    /// Answers the packets of the debugger until it detaches or
+    /// disconnects.
+    fn serve(&mut self) -> io::Result<()> {
+        while let Some(packet) = self.connection.receive()? {
+            let command = match packet {
+                Packet::Command(command) => command,
+                // The `Emulator` is already stopped.
+                Packet::Interrupt => continue,
+            };
+            let reply = match command.as_str() {
+                "D" => {
+                    self.connection.send("OK")?;
+                    return Ok(());
+                }
+                "k" => return Ok(()),
+                "QStartNoAckMode" => {
+                    self.connection.send("OK")?;
+                    self.connection.ack = false;
+                    continue;
+                }
+                _ => self.handle(&command)?,
+            };
+            self.connection.send(&reply)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the reply to `command`, an empty one for the commands not
+    /// supported.
+    fn handle(&mut self, command: &str) -> io::Result<String> {
+        let arguments = command.get(1..).unwrap_or("");
+        let reply = match (command.as_bytes().first(), arguments) {
+            (Some(b'?'), _) => self.stop.clone(),
+            (Some(b'g'), _) => hex(&self.registers()),
+            (Some(b'G'), values) => match unhex(values) {
+                Some(values) if values.len() == register_bytes() => {
+                    self.set_registers(&values);
+                    "OK".to_string()
+                }
+                _ => "E01".to_string(),
+            },
+            (Some(b'p'), number) => match usize::from_str_radix(number, 16) {
+                Ok(number) if number < REGISTERS => hex(&self.register(number)),
+                _ => "E01".to_string(),
+            },
+            (Some(b'P'), assignment) => match self.write_register(assignment) {
+                Some(()) => "OK".to_string(),
+                None => "E01".to_string(),
+            },
+            (Some(b'm'), arguments) => match self.read_memory(arguments) {
+                Some(bytes) => hex(&bytes),
+                None => "E01".to_string(),
+            },
+            (Some(b'M'), arguments) => match self.write_memory(arguments) {
+                Some(()) => "OK".to_string(),
+                None => "E01".to_string(),
+            },
+            (Some(b'Z'), arguments) | (Some(b'z'), arguments) => {
+                match self.set_point(command.starts_with('Z'), arguments) {
+                    Some(true) => "OK".to_string(),
+                    Some(false) => String::new(),
+                    None => "E01".to_string(),
+                }
+            }
+            (Some(b's'), address) => {
+                self.resume_at(address);
+                self.step()
+            }
+            (Some(b'c'), address) => {
+                self.resume_at(address);
+                self.resume()?
+            }
+            (Some(b'H'), _) | (Some(b'T'), _) => "OK".to_string(),
+            _ => match command {
+                "qSupported" => {
+                    "PacketSize=4000;qXfer:features:read+;QStartNoAckMode+;vContSupported+"
+                        .to_string()
+                }
+                "qAttached" => "1".to_string(),
+                "qC" => "QC1".to_string(),
+                "qfThreadInfo" => "m1".to_string(),
+                "qsThreadInfo" => "l".to_string(),
+                "vCont?" => "vCont;c;C;s;S".to_string(),
+                _ if command.starts_with("qSupported:") => self.handle("qSupported")?,
+                _ if command.starts_with("vCont;s") || command.starts_with("vCont;S") => {
+                    self.step()
+                }
+                _ if command.starts_with("vCont;c") || command.starts_with("vCont;C") => {
+                    self.resume()?
+                }
+                _ => match command.strip_prefix("qXfer:features:read:target.xml:") {
+                    Some(range) => read_target_xml(range),
+                    None => String::new(),
+                },
+            },
+        };
+        Ok(reply)
+    }
+
+    /// Returns the value of the register `number` in target byte order.
+    fn register(&self, number: usize) -> Vec<u8> {
+        let emulator = &self.emulator;
+        match number {
+            0..=15 => vec![emulator.registers()[number]],
+            16 => emulator.index_register().to_be_bytes().to_vec(),
+            17 => emulator.program_counter().to_be_bytes().to_vec(),
+            18 => vec![emulator.stack_pointer() as u8],
+            19 => vec![emulator.delay_timer()],
+            _ => vec![emulator.sound_timer],
+        }
+    }
+
+    fn registers(&self) -> Vec<u8> {
+        (0..REGISTERS)
+            .flat_map(|number| self.register(number))
+            .collect()
+    }
+
+    /// Sets the register `number` to `value`, in target byte order.
+    fn set_register(&mut self, number: usize, value: &[u8]) {
+        let word = || u16::from_be_bytes([value[0], value[1]]);
+        let emulator = &mut self.emulator;
+        match number {
+            0..=15 => emulator.set_register(number, value[0]),
+            16 => emulator.set_index_register(word()),
+            17 => emulator.set_program_counter(word()),
+            18 => emulator.set_stack_pointer(value[0] as usize),
+            19 => emulator.set_delay_timer(value[0]),
+            _ => emulator.sound_timer = value[0],
+        }
+    }
+
+    fn set_registers(&mut self, values: &[u8]) {
+        let mut offset = 0;
+        for number in 0..REGISTERS {
+            let size = register_size(number);
+            self.set_register(number, &values[offset..offset + size]);
+            offset += size;
+        }
+    }
+
+    /// Writes a register from `P` arguments, `number=value`.
+    fn write_register(&mut self, assignment: &str) -> Option<()> {
+        let (number, value) = assignment.split_once('=')?;
+        let number = usize::from_str_radix(number, 16).ok()?;
+        let value = unhex(value)?;
+        if number >= REGISTERS || value.len() != register_size(number) {
+            return None;
+        }
+        self.set_register(number, &value);
+        Some(())
+    }
+
+    /// Reads memory from `m` arguments, `address,length`, up to the end of
+    /// the address space.
+    fn read_memory(&self, arguments: &str) -> Option<Vec<u8>> {
+        let (address, length) = parse_range(arguments)?;
+        let memory = self.emulator.memory();
+        let end = address.checked_add(length)?.min(memory.len());
+        memory.get(address..end).map(<[u8]>::to_vec)
+    }
+
+    /// Writes memory from `M` arguments, `address,length:bytes`.
+    fn write_memory(&mut self, arguments: &str) -> Option<()> {
+        let (range, bytes) = arguments.split_once(':')?;
+        let (address, length) = parse_range(range)?;
+        let bytes = unhex(bytes)?;
+        if bytes.len() != length || !self.emulator.set_memory(address, &bytes) {
+            return None;
+        }
+        Some(())
+    }
+
+    /// Inserts or removes a breakpoint or a watchpoint from `Z` or `z`
+    /// arguments, `type,address,kind`. Returns false for the unsupported
+    /// types.
+    fn set_point(&mut self, insert: bool, arguments: &str) -> Option<bool> {
+        let mut arguments = arguments.splitn(3, ',');
+        let kind = arguments.next()?;
+        let address = u16::from_str_radix(arguments.next()?, 16).ok()?;
+        let length = usize::from_str_radix(arguments.next()?, 16).ok()?;
+
+        let (read, write) = match kind {
+            "0" | "1" => {
+                let breakpoint = Breakpoint {
+                    location: Location::Address(address),
+                    condition: None,
+                };
+                match insert {
+                    true => self.breakpoints.add(breakpoint),
+                    false => {
+                        if let Some(index) = self
+                            .breakpoints
+                            .breakpoints()
+                            .iter()
+                            .position(|&other| other == breakpoint)
+                        {
+                            self.breakpoints.remove(index);
+                        }
+                    }
+                }
+                return Some(true);
+            }
+            "2" => (false, true),
+            "3" => (true, false),
+            "4" => (true, true),
+            _ => return Some(false),
+        };
+        let end = (address as usize).saturating_add(length.max(1) - 1);
+        let watchpoint = Watchpoint {
+            start: address,
+            end: end.min(0xFFFF) as u16,
+            read,
+            write,
+            execute: false,
+        };
+        let mut watchpoints = self.emulator.watchpoints().to_vec();
+        match insert {
+            true => watchpoints.push(watchpoint),
+            false => {
+                if let Some(index) = watchpoints.iter().position(|&other| other == watchpoint) {
+                    watchpoints.remove(index);
+                }
+            }
+        }
+        self.emulator.set_watchpoints(watchpoints);
+        Some(true)
+    }
+
+    /// Moves the program counter to the address argument of `s` or `c`, if
+    /// any.
+    fn resume_at(&mut self, address: &str) {
+        if let Ok(address) = u16::from_str_radix(address, 16) {
+            self.emulator.set_program_counter(address);
+        }
+    }
+
+    /// Runs a single instruction, and returns the stop reply.
+    fn step(&mut self) -> String {
+        let reply = self.cycle().unwrap_or_else(|| format!("S{:02x}", SIGTRAP));
+        self.stop = reply.clone();
+        reply
+    }
+
+    /// Runs the `Emulator` frame after frame until it stops, and returns the
+    /// stop reply.
+    fn resume(&mut self) -> io::Result<String> {
+        let frame = Duration::from_secs_f64(1.0 / FRAME_RATE);
+        let mut next_frame = Instant::now();
+        let reply = 'frames: loop {
+            for _ in 0..self.speed {
+                if let Some(reply) = self.cycle() {
+                    break 'frames reply;
+                }
+                if self.breakpoints.hits(&self.emulator) {
+                    break 'frames format!("S{:02x}", SIGTRAP);
+                }
+            }
+            self.emulator.vblank();
+
+            if self.connection.interrupted()? {
+                break format!("S{:02x}", SIGINT);
+            }
+            next_frame += frame;
+            match next_frame.checked_duration_since(Instant::now()) {
+                Some(delay) => thread::sleep(delay),
+                None => next_frame = Instant::now(),
+            }
+        };
+        self.stop = reply.clone();
+        Ok(reply)
+    }
+
+    /// Runs an instruction, and returns the stop reply if the `Emulator`
+    /// stopped on a fault, a watchpoint or the end of the ROM.
+    fn cycle(&mut self) -> Option<String> {
+        match self.emulator.cycle() {
+            Ok(StepOutcome::Executed) | Ok(StepOutcome::Ignored(_)) => None,
+            Ok(StepOutcome::Exited) => Some("W00".to_string()),
+            Ok(StepOutcome::Watchpoint(hit)) => Some(watch_reply(&hit)),
+            Ok(StepOutcome::Break(error)) | Err(error) => {
+                Some(format!("S{:02x}", fault_signal(&error)))
+            }
+        }
+    }
+}
+
+/// Returns the stop reply of a watchpoint, naming the accessed address.
+fn watch_reply(hit: &WatchpointHit) -> String {
+    let kind = match hit.access {
+        Access::Write => "watch",
+        Access::Read => "rwatch",
+        Access::Execute => "awatch",
+    };
+    format!("T{:02x}{}:{:x};", SIGTRAP, kind, hit.address)
+}
+
+/// Returns the signal reported for a fault of the `Emulator`.
+fn fault_signal(error: &EmulatorError) -> u8 {
+    match error {
+        EmulatorError::UnknownOpcode { .. } => SIGILL,
+        _ => SIGSEGV,
+    }
+}
+
+/// Returns the size in bytes of the register `number`.
+fn register_size(number: usize) -> usize {
+    match number {
+        16 | 17 => 2,
+        _ => 1,
+    }
+}
+
+fn register_bytes() -> usize {
+    (0..REGISTERS).map(register_size).sum()
+}
+
+/// Returns the part of `target.xml` requested by `offset,length`.
+fn read_target_xml(range: &str) -> String {
+    let (offset, length) = match parse_range(range) {
+        Some(range) => range,
+        None => return "E01".to_string(),
+    };
+    let xml = TARGET_XML.as_bytes();
+    let start = offset.min(xml.len());
+    let end = offset.saturating_add(length).min(xml.len());
+    let prefix = if end == xml.len() { 'l' } else { 'm' };
+    format!("{}{}", prefix, String::from_utf8_lossy(&xml[start..end]))
+}
+
+/// Parses `address,length` in hexadecimal.
+fn parse_range(range: &str) -> Option<(usize, usize)> {
+    let (address, length) = range.split_once(',')?;
+    Some((
+        usize::from_str_radix(address, 16).ok()?,
+        usize::from_str_radix(length, 16).ok()?,
+    ))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn unhex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(text.get(index..index + 2)?, 16).ok())
+        .collect()
+}
@@ -0,0 +1,107 @@
+//! # `chiphuit` command-line tool, running the emulator core natively.
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+use std::process;
+use std::str::FromStr;
+
+mod asm;
+mod dap;
+mod gdb;
+mod run;
+#[cfg(feature = "tui")]
+mod tui;
+
+const USAGE: &str = "usage: chiphuit <command> [options]
+
+commands:
+    asm <source> assemble Octo source code into a ROM
+    dap          debug a ROM or Octo source code from an editor, over the
+                 debug adapter protocol
+    gdb <rom>    debug a ROM from GDB over its remote serial protocol
+    run <rom>    run a ROM headlessly and dump its final state
+    tui <rom>    play a ROM in the terminal (requires the `tui` feature)";
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+
+    let result = match args.next().as_deref() {
+        Some("asm") => Options::parse(args).and_then(|options| asm::asm(&options)),
+        Some("dap") => Options::parse(args).and_then(|options| dap::dap(&options)),
+        Some("gdb") => Options::parse(args).and_then(|options| gdb::gdb(&options)),
+        Some("run") => Options::parse(args).and_then(|options| run::run(&options)),
+        #[cfg(feature = "tui")]
+        Some("tui") => Options::parse(args).and_then(|options| tui::tui(&options)),
+        _ => Err(USAGE.into()),
+    };
+
+    if let Err(error) = result {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+}
+
+/// Options of a command: positional arguments, and `--name value` flags.
+pub struct Options {
+    positional: Vec<String>,
+    flags: HashMap<String, String>,
+}
+
+impl Options {
+    /// Splits the arguments of a command into positional arguments and flags.
+    fn parse(args: impl Iterator<Item = String>) -> Result<Options, Box<dyn Error>> {
+        let mut options = Options {
+            positional: Vec::new(),
+            flags: HashMap::new(),
+        };
+        let mut args = args.peekable();
+
+        while let Some(arg) = args.next() {
+            match arg.strip_prefix("--") {
+                Some(name) => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| format!("missing value for --{}", name))?;
+                    options.flags.insert(name.to_string(), value);
+                }
+                None => options.positional.push(arg),
+            }
+        }
+        Ok(options)
+    }
+
+    /// Returns the positional argument at `index`, named `name` in the error
+    /// if it is missing.
+    pub fn positional(&self, index: usize, name: &str) -> Result<&str, Box<dyn Error>> {
+        self.positional
+            .get(index)
+            .map(String::as_str)
+            .ok_or_else(|| format!("missing <{}> argument", name).into())
+    }
+
+    /// Returns the value of the `--name` flag, if any.
+    pub fn flag(&self, name: &str) -> Option<&str> {
+        self.flags.get(name).map(String::as_str)
+    }
+
+    /// Returns the value of the `--name` flag parsed as a `T`, or `default`
+    /// if the flag is missing.
+    pub fn parsed<T: FromStr>(&self, name: &str, default: T) -> Result<T, Box<dyn Error>> {
+        match self.flag(name) {
+            Some(value) => value
+                .parse()
+                .map_err(|_| format!("invalid value for --{}: {}", name, value).into()),
+            None => Ok(default),
+        }
+    }
+}
+
+/// Writes `content` to the file at `path`, or to stdout if `path` is `-`.
+pub fn output(path: &str, content: impl AsRef<[u8]>) -> Result<(), Box<dyn Error>> {
+    match path {
+        "-" => io::stdout().write_all(content.as_ref())?,
+        _ => fs::write(path, content)?,
+    }
+    Ok(())
+}
@@ -0,0 +1,149 @@
+//! # `chiphuit run`, runs a ROM headlessly and dumps its final state.
+//!
+//! usage: `chiphuit run <rom> [--frames N | --cycles N | --movie FILE]
+//! [--speed N] [--quirks NAME] [--seed N] [--load-state FILE] [--screen FILE]
+//! [--registers FILE] [--snapshot FILE] [--save-state FILE]`
+//!
+//! The ROM runs for `--frames` frames (60 by default) of `--speed`
+//! instructions (10 by default), or for exactly `--cycles` instructions. With
+//! `--movie`, the inputs of a recorded session are replayed instead, along
+//! with its quirks and seed, until the end of the session. `--seed` makes the
+//! random numbers of `CXNN` reproducible otherwise. `--load-state` resumes
+//! the ROM from a save state, or from a JSON snapshot of the debugger. The
+//! final screen, registers, JSON snapshot and save state are written to the
+//! given files, `-` meaning stdout. Without any of these flags, the screen
+//! and the registers are printed to stdout. A fault of the `Emulator` makes
+//! the command fail once the outputs are written.
+use crate::{output, Options};
+use chiphuit::cpu::{Emulator, StepOutcome};
+use chiphuit::movie::{Movie, Player};
+use chiphuit::quirks::Quirks;
+use chiphuit::savestate;
+use std::error::Error;
+use std::fs;
+
+/// Characters of the pixels according to their lit bitplanes.
+const PIXELS: [char; 4] = ['.', '#', '+', '@'];
+
+pub fn run(options: &Options) -> Result<(), Box<dyn Error>> {
+    let rom = fs::read(options.positional(0, "rom")?)?;
+    let quirks = match options.flag("quirks") {
+        Some(name) => Quirks::from_name(name).ok_or_else(|| format!("unknown quirks: {}", name))?,
+        None => Quirks::default(),
+    };
+    let speed: usize = options.parsed("speed", 10)?;
+    if speed == 0 {
+        return Err("--speed must be at least 1".into());
+    }
+    let cycles = match options.flag("cycles") {
+        Some(_) => options.parsed("cycles", 0)?,
+        None => options.parsed::<usize>("frames", 60)? * speed,
+    };
+
+    let mut emulator = Emulator::with_quirks(quirks);
+    emulator.load_font();
+    emulator.load_rom(&rom)?;
+    if options.flag("seed").is_some() {
+        emulator.seed_rng(options.parsed("seed", 0)?);
+    }
+    if let Some(path) = options.flag("load-state") {
+        let state = savestate::migrate(&fs::read(path)?, &rom)?;
+        savestate::load(&mut emulator, &rom, &state)?;
+    }
+    let mut player = match options.flag("movie") {
+        Some(_) if options.flag("load-state").is_some() => {
+            return Err("--load-state and --movie can't be combined".into())
+        }
+        Some(path) => {
+            let movie: Movie = serde_json::from_str(&fs::read_to_string(path)?)?;
+            Some(Player::new(movie, &mut emulator))
+        }
+        None => None,
+    };
+
+    let mut fault = None;
+    for cycle in 1.. {
+        let over = match &mut player {
+            Some(player) => !player.play(&mut emulator),
+            None => cycle > cycles,
+        };
+        if over {
+            break;
+        }
+        match emulator.cycle() {
+            Ok(StepOutcome::Exited) => break,
+            Ok(_) => {}
+            Err(error) => {
+                fault = Some(error);
+                break;
+            }
+        }
+        if player.is_none() && cycle % speed == 0 {
+            emulator.vblank();
+        }
+    }
+
+    let (screen_path, registers_path, snapshot_path, state_path) = (
+        options.flag("screen"),
+        options.flag("registers"),
+        options.flag("snapshot"),
+        options.flag("save-state"),
+    );
+    if screen_path.is_none()
+        && registers_path.is_none()
+        && snapshot_path.is_none()
+        && state_path.is_none()
+    {
+        output("-", screen(&emulator) + "\n" + &registers(&emulator))?;
+    }
+    if let Some(path) = screen_path {
+        output(path, screen(&emulator))?;
+    }
+    if let Some(path) = registers_path {
+        output(path, registers(&emulator))?;
+    }
+    if let Some(path) = snapshot_path {
+        output(path, serde_json::to_string(&emulator)? + "\n")?;
+    }
+    if let Some(path) = state_path {
+        output(path, savestate::save(&emulator, &rom))?;
+    }
+
+    match fault {
+        Some(error) => Err(error.into()),
+        None => Ok(()),
+    }
+}
+
+/// Renders the screen as text, one line per row of pixels.
+fn screen(emulator: &Emulator) -> String {
+    emulator
+        .screen
+        .chunks(emulator.width())
+        .map(|row| {
+            row.iter()
+                .map(|&planes| PIXELS[(planes & 0b11) as usize])
+                .chain(Some('\n'))
+                .collect::<String>()
+        })
+        .collect()
+}
+
+/// Renders the registers, timers and active stack frames as text.
+fn registers(emulator: &Emulator) -> String {
+    let mut text = format!(
+        "PC {:#06X}\nI  {:#06X}\nDT {}\nST {}\nSP {}\n",
+        emulator.program_counter(),
+        emulator.index_register(),
+        emulator.delay_timer(),
+        emulator.sound_timer,
+        emulator.stack_pointer(),
+    );
+    for (index, value) in emulator.registers().iter().enumerate() {
+        text += &format!("V{:X} {:#04X}\n", index, value);
+    }
+    for address in &emulator.stack()[..emulator.stack_pointer()] {
+        text += &format!("stack {:#06X}\n", address);
+    }
+    text
+}
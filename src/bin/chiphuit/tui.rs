@@ -0,0 +1,461 @@
+//! # `chiphuit tui`, plays a ROM in the terminal.
+//!
+//! usage: `chiphuit tui <rom> [--speed N] [--quirks NAME]
+//! [--render half|braille] [--seed N] [--record FILE | --movie FILE]`
+//!
+//! The screen is drawn with Unicode half-blocks, two pixels per character
+//! coloured after their bitplanes, or with braille patterns, eight pixels per
+//! character. The keypad is mapped on the `1234`, `QWER`, `ASDF` and `ZXCV`
+//! keys like in the browser, and a side panel shows the `Emulator` variables
+//! of the debugger. `Space` pauses, `Enter` steps one instruction while
+//! paused, `Backspace` rewinds while held, and `Esc` quits. `F2` saves the
+//! state of the `Emulator` in the selected slot, `F4` loads it back, and `F3`
+//! selects the next slot.
+//!
+//! With `--record`, the session is saved as a movie when quitting, up to the
+//! first rewind or loaded state. With `--movie`, a recorded session is
+//! replayed in place of the keyboard. `--seed` seeds the random numbers of
+//! `CXNN`, which are otherwise different on every run.
+//!
+//! Most terminals only report key presses, a pressed key is then held for
+//! `HOLD_FRAMES` frames or until it repeats. Terminals supporting the kitty
+//! keyboard protocol also report releases, which are used instead.
+use crate::{output, Options};
+use chiphuit::cpu::{Emulator, Print, StepOutcome, EMULATOR_VARIABLES};
+use chiphuit::movie::{Movie, Player, Recorder};
+use chiphuit::quirks::Quirks;
+use chiphuit::rewind::Rewind;
+use chiphuit::savestate::{Slots, SLOTS};
+use crossterm::event::{
+    self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::style::{self, Color, Colors, SetColors};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{cursor, execute, queue};
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Keyboard keys of the keypad, in the order of the `Emulator` keypad.
+const KEYS: [char; 16] = [
+    '1', '2', '3', '4', 'q', 'w', 'e', 'r', 'a', 's', 'd', 'f', 'z', 'x', 'c', 'v',
+];
+
+/// Frames a key stays pressed when the terminal doesn't report releases.
+const HOLD_FRAMES: u8 = 6;
+
+/// Colors of the pixels according to their lit bitplanes, as in the browser.
+const PALETTE: [Color; 4] = [
+    Color::Reset,
+    Color::White,
+    Color::Rgb {
+        r: 139,
+        g: 0,
+        b: 139,
+    },
+    Color::Rgb {
+        r: 85,
+        g: 85,
+        b: 85,
+    },
+];
+
+/// Bits of the braille dots of a character, indexed by `[y][x]`.
+const BRAILLE_DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+/// How the screen of the `Emulator` is drawn in the terminal.
+#[derive(Clone, Copy, PartialEq)]
+enum Render {
+    HalfBlock,
+    Braille,
+}
+
+impl Render {
+    /// Returns the pixels drawn by a character, as (columns, rows).
+    fn cell(self) -> (usize, usize) {
+        match self {
+            Render::HalfBlock => (1, 2),
+            Render::Braille => (2, 4),
+        }
+    }
+}
+
+/// The terminal in raw mode on the alternate screen, restored when dropped.
+struct Terminal {
+    key_releases: bool,
+}
+
+impl Terminal {
+    fn enter() -> io::Result<Terminal> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), EnterAlternateScreen, cursor::Hide)?;
+        let key_releases = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if key_releases {
+            execute!(
+                io::stdout(),
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+            )?;
+        }
+        Ok(Terminal { key_releases })
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        if self.key_releases {
+            let _ = execute!(io::stdout(), PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(io::stdout(), cursor::Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// A quick save or quick load of the selected slot.
+#[derive(Clone, Copy)]
+enum SlotRequest {
+    Save,
+    Load,
+}
+
+/// State of the terminal frontend between two frames.
+struct Tui {
+    /// Frames each key of the keypad stays pressed.
+    held: [u8; 16],
+    /// Frames the rewind key stays pressed.
+    rewinding: u8,
+    rewind: Rewind,
+    /// Records the session, until the first rewind.
+    recorder: Option<Recorder>,
+    /// The session recorded before a rewind.
+    recorded: Option<Movie>,
+    /// Replays a recorded session in place of the keyboard.
+    player: Option<Player>,
+    slots: Slots,
+    slot: usize,
+    slot_request: Option<SlotRequest>,
+    key_releases: bool,
+    running: bool,
+    step: bool,
+    quit: bool,
+    status: String,
+}
+
+impl Tui {
+    /// Updates the state with a terminal event.
+    fn handle_event(&mut self, event: Event) {
+        let KeyEvent {
+            code,
+            modifiers,
+            kind,
+            ..
+        } = match event {
+            Event::Key(key) => key,
+            _ => return,
+        };
+        let pressed = kind != KeyEventKind::Release;
+
+        match code {
+            KeyCode::Esc => self.quit = true,
+            KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => self.quit = true,
+            KeyCode::Char(' ') if pressed => {
+                self.running = !self.running;
+                self.status.clear();
+            }
+            KeyCode::Enter if pressed && !self.running => self.step = true,
+            KeyCode::Backspace => self.rewinding = self.hold(pressed),
+            KeyCode::F(2) if pressed => self.slot_request = Some(SlotRequest::Save),
+            KeyCode::F(3) if pressed => {
+                self.slot = (self.slot + 1) % SLOTS;
+                self.status = format!("slot {}", self.slot);
+            }
+            KeyCode::F(4) if pressed => self.slot_request = Some(SlotRequest::Load),
+            KeyCode::Char(key) => {
+                if let Some(index) = KEYS.iter().position(|&k| k == key.to_ascii_lowercase()) {
+                    self.held[index] = self.hold(pressed);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns the frames a key stays pressed after an event.
+    fn hold(&self, pressed: bool) -> u8 {
+        match (pressed, self.key_releases) {
+            (false, _) => 0,
+            (true, true) => u8::MAX,
+            (true, false) => HOLD_FRAMES,
+        }
+    }
+
+    /// Steps back `cycles` instructions of the `Emulator` while the rewind
+    /// key is held, and returns whether it is.
+    fn rewind(&mut self, emulator: &mut Emulator, cycles: usize) -> bool {
+        if self.rewinding == 0 {
+            return false;
+        }
+        if !self.key_releases {
+            self.rewinding -= 1;
+        }
+        self.stop_movie();
+        for _ in 0..cycles {
+            if !self.rewind.step_back(emulator) {
+                self.status = "no more history".to_string();
+                break;
+            }
+        }
+        true
+    }
+
+    /// Saves the state of the `Emulator`, running `rom`, in the selected slot,
+    /// or restores it from there.
+    fn handle_slots(&mut self, emulator: &mut Emulator, rom: &[u8]) {
+        match self.slot_request.take() {
+            Some(SlotRequest::Save) => {
+                self.slots.save(self.slot, emulator, rom);
+                self.status = format!("saved slot {}", self.slot);
+            }
+            Some(SlotRequest::Load) => match self.slots.load(self.slot, emulator, rom) {
+                Ok(()) => {
+                    self.stop_movie();
+                    self.rewind.clear();
+                    self.status = format!("loaded slot {}", self.slot);
+                }
+                Err(error) => self.status = error.to_string(),
+            },
+            None => {}
+        }
+    }
+
+    /// Stops recording or replaying the session, whose inputs don't follow
+    /// an `Emulator` moved back in time.
+    fn stop_movie(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            self.recorded = Some(recorder.finish());
+        }
+        self.player = None;
+    }
+
+    /// Returns the keypad pressed during this frame, and releases the keys
+    /// held for long enough.
+    fn keypad(&mut self) -> [bool; 16] {
+        let mut keypad = [false; 16];
+        for (key, held) in keypad.iter_mut().zip(self.held.iter_mut()) {
+            *key = *held > 0;
+            if !self.key_releases {
+                *held = held.saturating_sub(1);
+            }
+        }
+        keypad
+    }
+
+    /// Signals the vertical blank interrupt to the `Emulator`, unless it
+    /// comes from the replayed session.
+    fn vblank(&mut self, emulator: &mut Emulator) {
+        if self.player.is_some() {
+            return;
+        }
+        if let Some(recorder) = &mut self.recorder {
+            recorder.vblank();
+        }
+        emulator.vblank();
+    }
+
+    /// Runs `cycles` instructions of the `Emulator`, pausing it on faults and
+    /// at the end of the replayed session.
+    fn run(&mut self, emulator: &mut Emulator, cycles: usize) {
+        for _ in 0..cycles {
+            if let Some(player) = &mut self.player {
+                if !player.play(emulator) {
+                    self.player = None;
+                    self.running = false;
+                    self.status = "replay finished".to_string();
+                    break;
+                }
+            } else if let Some(recorder) = &mut self.recorder {
+                recorder.record(emulator);
+            }
+            match self.rewind.cycle(emulator) {
+                Ok(StepOutcome::Executed) => {}
+                Ok(StepOutcome::Exited) => {
+                    self.running = false;
+                    self.status = "exited".to_string();
+                }
+                Ok(StepOutcome::Ignored(error)) => self.status = error.to_string(),
+                Ok(StepOutcome::Watchpoint(hit)) => {
+                    self.running = false;
+                    self.status = hit.to_string();
+                }
+                Ok(StepOutcome::Break(error)) | Err(error) => {
+                    self.running = false;
+                    self.status = error.to_string();
+                }
+            }
+            if !self.running {
+                break;
+            }
+        }
+    }
+}
+
+pub fn tui(options: &Options) -> Result<(), Box<dyn Error>> {
+    let rom = fs::read(options.positional(0, "rom")?)?;
+    let quirks = match options.flag("quirks") {
+        Some(name) => Quirks::from_name(name).ok_or_else(|| format!("unknown quirks: {}", name))?,
+        None => Quirks::default(),
+    };
+    let speed: usize = options.parsed("speed", 10)?;
+    if speed == 0 {
+        return Err("--speed must be at least 1".into());
+    }
+    let render = match options.flag("render") {
+        None | Some("half") => Render::HalfBlock,
+        Some("braille") => Render::Braille,
+        Some(name) => return Err(format!("unknown render: {}", name).into()),
+    };
+
+    let mut emulator = Emulator::with_quirks(quirks);
+    emulator.load_font();
+    emulator.load_rom(&rom)?;
+
+    let seed = match options.flag("seed") {
+        Some(_) => options.parsed("seed", 0)?,
+        None => rand::random(),
+    };
+    emulator.seed_rng(seed);
+    let player = match options.flag("movie") {
+        Some(path) => {
+            let movie: Movie = serde_json::from_str(&fs::read_to_string(path)?)?;
+            Some(Player::new(movie, &mut emulator))
+        }
+        None => None,
+    };
+    let recorder = match (options.flag("record"), &player) {
+        (Some(_), Some(_)) => return Err("--record and --movie can't be combined".into()),
+        (Some(_), None) => Some(Recorder::new(&mut emulator, seed)),
+        (None, _) => None,
+    };
+
+    let terminal = Terminal::enter()?;
+    let mut tui = Tui {
+        held: [0; 16],
+        rewinding: 0,
+        rewind: Rewind::new(),
+        recorder,
+        recorded: None,
+        player,
+        slots: Slots::new(),
+        slot: 0,
+        slot_request: None,
+        key_releases: terminal.key_releases,
+        running: true,
+        step: false,
+        quit: false,
+        status: String::new(),
+    };
+    let frame = Duration::from_secs(1) / 60;
+    let mut stdout = io::stdout();
+    queue!(stdout, Clear(ClearType::All))?;
+
+    while !tui.quit {
+        let start = Instant::now();
+        while event::poll(Duration::from_secs(0))? {
+            tui.handle_event(event::read()?);
+        }
+
+        let resolution = (emulator.width(), emulator.height());
+        let keypad = tui.keypad();
+        if tui.player.is_none() {
+            emulator.keypad = keypad;
+        }
+        tui.handle_slots(&mut emulator, &rom);
+        let rewound = tui.rewind(&mut emulator, speed);
+        if rewound {
+            tui.step = false;
+        } else if tui.running {
+            tui.run(&mut emulator, speed);
+            tui.vblank(&mut emulator);
+        } else if tui.step {
+            tui.step = false;
+            tui.run(&mut emulator, 1);
+        }
+        if resolution != (emulator.width(), emulator.height()) {
+            queue!(stdout, Clear(ClearType::All))?;
+        }
+
+        draw(&mut stdout, &emulator, render, &tui)?;
+        stdout.flush()?;
+        thread::sleep(frame.saturating_sub(start.elapsed()));
+    }
+
+    drop(terminal);
+    let movie = tui.recorder.take().map(Recorder::finish).or(tui.recorded);
+    if let (Some(path), Some(movie)) = (options.flag("record"), movie) {
+        output(path, serde_json::to_string(&movie)? + "\n")?;
+    }
+    Ok(())
+}
+
+/// Draws the screen of the `Emulator`, its variables on the right, and the
+/// status and controls below.
+fn draw(stdout: &mut impl Write, emulator: &Emulator, render: Render, tui: &Tui) -> io::Result<()> {
+    let (width, height) = (emulator.width(), emulator.height());
+    let (cell_width, cell_height) = render.cell();
+    let pixel = |x: usize, y: usize| (emulator.screen[y * width + x] & 0b11) as usize;
+
+    for row in 0..height / cell_height {
+        queue!(stdout, cursor::MoveTo(0, row as u16))?;
+        for column in 0..width / cell_width {
+            let (x, y) = (column * cell_width, row * cell_height);
+            match render {
+                Render::HalfBlock => queue!(
+                    stdout,
+                    SetColors(Colors::new(PALETTE[pixel(x, y)], PALETTE[pixel(x, y + 1)])),
+                    style::Print('▀')
+                )?,
+                Render::Braille => {
+                    let mut dots = 0;
+                    for (dy, row_dots) in BRAILLE_DOTS.iter().enumerate() {
+                        for (dx, dot) in row_dots.iter().enumerate() {
+                            if pixel(x + dx, y + dy) != 0 {
+                                dots |= dot;
+                            }
+                        }
+                    }
+                    let character = core::char::from_u32(0x2800 + dots).unwrap_or(' ');
+                    queue!(stdout, style::Print(character))?
+                }
+            }
+        }
+        queue!(stdout, style::ResetColor)?;
+    }
+
+    let panel = (width / cell_width + 2) as u16;
+    let running = if tui.running { "true" } else { "false" };
+    let values = emulator.printables();
+    let rows = EMULATOR_VARIABLES
+        .iter()
+        .zip(values.iter().map(String::as_str))
+        .chain(Some((&"running", running)));
+    for (line, (variable, value)) in rows.enumerate() {
+        queue!(
+            stdout,
+            cursor::MoveTo(panel, line as u16),
+            style::Print(format!("{:<16}{}", variable, value)),
+            Clear(ClearType::UntilNewLine)
+        )?;
+    }
+
+    let bottom = (height / cell_height).max(EMULATOR_VARIABLES.len() + 1) as u16 + 1;
+    queue!(
+        stdout,
+        cursor::MoveTo(0, bottom),
+        style::Print(&tui.status),
+        Clear(ClearType::UntilNewLine),
+        cursor::MoveTo(0, bottom + 1),
+        style::Print("keypad: 1234 QWER ASDF ZXCV   space: pause   enter: step   backspace: rewind   F2/F4: save/load   F3: slot   esc: quit"),
+        Clear(ClearType::UntilNewLine)
+    )
+}
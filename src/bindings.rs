@@ -0,0 +1,203 @@
+//! # A module to embed the `Emulator` in other pages from JavaScript.
+//!
+//! The `Chip8` class exported by `wasm-bindgen` wraps an `Emulator` without
+//! any of the built-in UI: the page loads a ROM, feeds the keypad, runs the
+//! `Emulator` one instruction or one frame at a time, and draws the
+//! framebuffer itself. Callbacks report the frames, the buzzer, faults and the
+//! end of the ROM.
+//!
+//! Building with `--no-default-features --features bindings` only exports
+//! this class, leaving out the built-in UI started with the page.
+use crate::cpu::{Emulator, StepOutcome};
+use crate::quirks::Quirks;
+use crate::savestate;
+use crate::scheduler::DEFAULT_INSTRUCTIONS_PER_FRAME;
+use js_sys::Function;
+use wasm_bindgen::prelude::*;
+
+/// A chip8 emulator, driven from JavaScript.
+#[wasm_bindgen]
+pub struct Chip8 {
+    emulator: Emulator,
+    rom: Vec<u8>,
+    instructions_per_frame: usize,
+    /// Whether a fault or the end of the ROM stopped the `Emulator`.
+    stopped: bool,
+    buzzing: bool,
+    on_frame: Option<Function>,
+    on_sound: Option<Function>,
+    on_fault: Option<Function>,
+    on_exit: Option<Function>,
+}
+
+impl Default for Chip8 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[wasm_bindgen]
+impl Chip8 {
+    /// Returns a `Chip8` with no ROM, following the modern quirks.
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Chip8 {
+        let mut emulator = Emulator::new();
+        emulator.load_font();
+        Chip8 {
+            emulator,
+            rom: Vec::new(),
+            instructions_per_frame: DEFAULT_INSTRUCTIONS_PER_FRAME,
+            stopped: false,
+            buzzing: false,
+            on_frame: None,
+            on_sound: None,
+            on_fault: None,
+            on_exit: None,
+        }
+    }
+
+    /// Restarts the `Emulator` with `rom`, throwing if it doesn't fit in
+    /// memory.
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), JsValue> {
+        self.emulator.reset();
+        self.emulator
+            .load_rom(rom)
+            .map_err(|error| JsValue::from_str(&error.to_string()))?;
+        self.rom = rom.to_vec();
+        self.stopped = false;
+        Ok(())
+    }
+
+    /// Follows the quirks of `vip`, `chip48`, `schip`, `xochip` or `modern`
+    /// interpreters.
+    pub fn set_quirks(&mut self, name: &str) -> Result<(), JsValue> {
+        let quirks = Quirks::from_name(name)
+            .ok_or_else(|| JsValue::from_str(&format!("Unknown quirks: {}.", name)))?;
+        self.emulator.set_quirks(quirks);
+        Ok(())
+    }
+
+    /// Seeds the random numbers of `CXNN`, to reproduce a run.
+    pub fn seed(&mut self, seed: u64) {
+        self.emulator.seed_rng(seed);
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn instructions_per_frame(&self) -> usize {
+        self.instructions_per_frame
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_instructions_per_frame(&mut self, instructions_per_frame: usize) {
+        self.instructions_per_frame = instructions_per_frame;
+    }
+
+    /// Presses or releases the key `key` of the keypad, from 0 to 15.
+    pub fn set_key(&mut self, key: usize, pressed: bool) {
+        self.emulator.keypad[key & 0xF] = pressed;
+    }
+
+    /// Runs one instruction, and returns false once a fault or the end of the
+    /// ROM stopped the `Emulator`.
+    pub fn step(&mut self) -> bool {
+        if self.stopped {
+            return false;
+        }
+        match self.emulator.cycle() {
+            Ok(StepOutcome::Exited) => {
+                self.stopped = true;
+                call(&self.on_exit, &JsValue::UNDEFINED);
+            }
+            Ok(StepOutcome::Break(error)) | Err(error) => {
+                self.stopped = true;
+                call(&self.on_fault, &JsValue::from_str(&error.to_string()));
+            }
+            Ok(_) => {}
+        }
+        !self.stopped
+    }
+
+    /// Runs the instructions of a frame, then signals the vertical blank
+    /// interrupt which decrements the timers. To be called 60 times per
+    /// second. Returns false once the `Emulator` is stopped.
+    pub fn run_frame(&mut self) -> bool {
+        for _ in 0..self.instructions_per_frame {
+            if !self.step() {
+                return false;
+            }
+        }
+        self.emulator.vblank();
+
+        let buzzing = self.sound();
+        if buzzing != self.buzzing {
+            self.buzzing = buzzing;
+            call(&self.on_sound, &JsValue::from_bool(buzzing));
+        }
+        call(&self.on_frame, &JsValue::UNDEFINED);
+        true
+    }
+
+    /// Returns whether the buzzer sounds.
+    pub fn sound(&self) -> bool {
+        self.emulator.sound_timer > 0
+    }
+
+    /// Returns the pixels of the screen, row by row, bit `n` of each being
+    /// set when it is lit on bitplane `n`.
+    pub fn framebuffer(&self) -> Vec<u8> {
+        self.emulator.screen.clone()
+    }
+
+    /// Returns the width of the screen, 64 or 128 pixels in high resolution.
+    pub fn width(&self) -> usize {
+        self.emulator.width()
+    }
+
+    /// Returns the height of the screen, 32 or 64 pixels in high resolution.
+    pub fn height(&self) -> usize {
+        self.emulator.height()
+    }
+
+    /// Returns the save state of the `Emulator`.
+    pub fn save_state(&self) -> Vec<u8> {
+        savestate::save(&self.emulator, &self.rom)
+    }
+
+    /// Restores a save state of the ROM running, throwing if it is invalid or
+    /// was saved from another ROM.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), JsValue> {
+        savestate::load(&mut self.emulator, &self.rom, state)
+            .map_err(|error| JsValue::from_str(&error.to_string()))?;
+        self.stopped = false;
+        Ok(())
+    }
+
+    /// Calls `callback` after each frame.
+    pub fn on_frame(&mut self, callback: Function) {
+        self.on_frame = Some(callback);
+    }
+
+    /// Calls `callback` with true when the buzzer starts, and with false when
+    /// it stops.
+    pub fn on_sound(&mut self, callback: Function) {
+        self.on_sound = Some(callback);
+    }
+
+    /// Calls `callback` with the message of the fault stopping the
+    /// `Emulator`.
+    pub fn on_fault(&mut self, callback: Function) {
+        self.on_fault = Some(callback);
+    }
+
+    /// Calls `callback` when the ROM exits the interpreter with `00FD`.
+    pub fn on_exit(&mut self, callback: Function) {
+        self.on_exit = Some(callback);
+    }
+}
+
+/// Calls a JavaScript callback, if set, with `argument`.
+fn call(callback: &Option<Function>, argument: &JsValue) {
+    if let Some(callback) = callback {
+        let _ = callback.call1(&JsValue::NULL, argument);
+    }
+}
@@ -0,0 +1,362 @@
+//! # A module to pause the `Emulator` on breakpoints and to step through code.
+//!
+//! `Breakpoints` are checked after each `Emulator::cycle`, against the
+//! instruction about to be executed: the `Emulator` stops *before* running the
+//! instruction a breakpoint points at. Resuming then runs that instruction
+//! without hitting the breakpoint again.
+//!
+//! Breakpoints can be written as text, an address or an opcode pattern
+//! followed by an optional condition:
+//! - `0x2A4` stops at the address 0x2A4,
+//! - `DXYN` stops at any sprite drawing, `X`, `Y` and `N` matching any nibble,
+//! - `0x2A4 if V3 == 5` or `00EE if I >= 0x300` only stop when the condition
+//!   holds, `V0` to `VF`, `I`, `DT` and `ST` being available.
+use crate::cpu::Emulator;
+use core::fmt;
+use core::str::FromStr;
+use serde::{Deserialize, Serialize};
+
+/// A value of the `Emulator` a `Condition` can test.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Operand {
+    Register(u8),
+    Index,
+    DelayTimer,
+    SoundTimer,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+/// A comparison between a value of the `Emulator` and a constant.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Condition {
+    pub operand: Operand,
+    pub comparison: Comparison,
+    pub value: u16,
+}
+
+impl Condition {
+    /// Returns whether the condition holds for `emulator`.
+    pub fn holds(&self, emulator: &Emulator) -> bool {
+        let operand = match self.operand {
+            Operand::Register(index) => emulator.registers()[index as usize & 0xF] as u16,
+            Operand::Index => emulator.index_register(),
+            Operand::DelayTimer => emulator.delay_timer() as u16,
+            Operand::SoundTimer => emulator.sound_timer as u16,
+        };
+        match self.comparison {
+            Comparison::Equal => operand == self.value,
+            Comparison::NotEqual => operand != self.value,
+            Comparison::Less => operand < self.value,
+            Comparison::LessOrEqual => operand <= self.value,
+            Comparison::Greater => operand > self.value,
+            Comparison::GreaterOrEqual => operand >= self.value,
+        }
+    }
+}
+
+/// Where a `Breakpoint` stops the `Emulator`.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Location {
+    /// Stops when the program counter reaches the address.
+    Address(u16),
+    /// Stops before any opcode equal to `pattern` on the bits of `mask`.
+    Opcode { pattern: u16, mask: u16 },
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Breakpoint {
+    pub location: Location,
+    pub condition: Option<Condition>,
+}
+
+impl Breakpoint {
+    /// Returns whether the breakpoint stops `emulator` before its next
+    /// instruction.
+    pub fn hits(&self, emulator: &Emulator) -> bool {
+        let program_counter = emulator.program_counter();
+        let location = match self.location {
+            Location::Address(address) => program_counter == address,
+            Location::Opcode { pattern, mask } => next_opcode(emulator) & mask == pattern,
+        };
+        location
+            && self
+                .condition
+                .iter()
+                .all(|condition| condition.holds(emulator))
+    }
+}
+
+/// Parses a `Breakpoint` written as `location [if operand comparison value]`.
+impl FromStr for Breakpoint {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        let location = match words.first() {
+            Some(word) => parse_location(word)?,
+            None => return Err("empty breakpoint".to_string()),
+        };
+        let condition = match words[1..] {
+            [] => None,
+            ["if", operand, comparison, value] => Some(Condition {
+                operand: parse_operand(operand)?,
+                comparison: parse_comparison(comparison)?,
+                value: parse_value(value)?,
+            }),
+            _ => return Err(format!("invalid condition: {}", words[1..].join(" "))),
+        };
+        Ok(Breakpoint {
+            location,
+            condition,
+        })
+    }
+}
+
+/// Display Trait to write a `Breakpoint` back in the syntax it is parsed from.
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.location {
+            Location::Address(address) => write!(f, "{:#05X}", address)?,
+            Location::Opcode { pattern, mask } => {
+                for (nibble, wildcard) in (0..4).rev().zip(['X', 'X', 'Y', 'N'].iter()) {
+                    match mask >> (nibble * 4) & 0xF {
+                        0 => write!(f, "{}", wildcard)?,
+                        _ => write!(f, "{:X}", pattern >> (nibble * 4) & 0xF)?,
+                    }
+                }
+            }
+        }
+        if let Some(condition) = self.condition {
+            let operand = match condition.operand {
+                Operand::Register(index) => format!("V{:X}", index),
+                Operand::Index => "I".to_string(),
+                Operand::DelayTimer => "DT".to_string(),
+                Operand::SoundTimer => "ST".to_string(),
+            };
+            let comparison = match condition.comparison {
+                Comparison::Equal => "==",
+                Comparison::NotEqual => "!=",
+                Comparison::Less => "<",
+                Comparison::LessOrEqual => "<=",
+                Comparison::Greater => ">",
+                Comparison::GreaterOrEqual => ">=",
+            };
+            write!(f, " if {} {} {:#X}", operand, comparison, condition.value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Why `Breakpoints::check` stopped the `Emulator`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Stop {
+    /// The breakpoint at this index was hit.
+    Breakpoint(usize),
+    /// The requested step is complete.
+    Step,
+}
+
+/// A step requested by the user, waiting for its completion.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Step {
+    Into,
+    /// Completes once the stack is back to `stack_pointer`, at
+    /// `return_address`.
+    Over {
+        return_address: u16,
+        stack_pointer: usize,
+    },
+    /// Completes once the stack pointer drops below `stack_pointer`.
+    Out {
+        stack_pointer: usize,
+    },
+}
+
+/// The breakpoints set on an `Emulator`, and the step in progress.
+#[derive(Default)]
+pub struct Breakpoints {
+    breakpoints: Vec<Breakpoint>,
+    step: Option<Step>,
+}
+
+impl Breakpoints {
+    pub fn new() -> Breakpoints {
+        Breakpoints::default()
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    pub fn add(&mut self, breakpoint: Breakpoint) {
+        if !self.breakpoints.contains(&breakpoint) {
+            self.breakpoints.push(breakpoint);
+        }
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        if index < self.breakpoints.len() {
+            self.breakpoints.remove(index);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    /// Replaces the breakpoints, keeping the step in progress.
+    pub fn set(&mut self, breakpoints: &[Breakpoint]) {
+        if self.breakpoints != breakpoints {
+            self.breakpoints = breakpoints.to_vec();
+        }
+    }
+
+    /// Stops after the next instruction.
+    pub fn step_into(&mut self) {
+        self.step = Some(Step::Into);
+    }
+
+    /// Stops after the next instruction, running a subroutine called by
+    /// `2NNN` up to its return.
+    pub fn step_over(&mut self, emulator: &Emulator) {
+        self.step = Some(match next_opcode(emulator) >> 12 {
+            2 => Step::Over {
+                return_address: emulator.program_counter().wrapping_add(2),
+                stack_pointer: emulator.stack_pointer(),
+            },
+            _ => Step::Into,
+        });
+    }
+
+    /// Runs up to the return of the current subroutine by `00EE`, or steps
+    /// into the next instruction outside of any subroutine.
+    pub fn step_out(&mut self, emulator: &Emulator) {
+        self.step = Some(match emulator.stack_pointer() {
+            0 => Step::Into,
+            stack_pointer => Step::Out { stack_pointer },
+        });
+    }
+
+    /// Returns whether any breakpoint stops `emulator` before its next
+    /// instruction, without affecting the step in progress.
+    pub fn hits(&self, emulator: &Emulator) -> bool {
+        self.breakpoints
+            .iter()
+            .any(|breakpoint| breakpoint.hits(emulator))
+    }
+
+    /// Returns whether a step is in progress.
+    pub fn is_stepping(&self) -> bool {
+        self.step.is_some()
+    }
+
+    /// Checks the breakpoints and the step in progress after a cycle of
+    /// `emulator`, and returns why it should stop, if it should.
+    pub fn check(&mut self, emulator: &Emulator) -> Option<Stop> {
+        if let Some(index) = self
+            .breakpoints
+            .iter()
+            .position(|breakpoint| breakpoint.hits(emulator))
+        {
+            self.step = None;
+            return Some(Stop::Breakpoint(index));
+        }
+
+        let complete = match self.step? {
+            Step::Into => true,
+            Step::Over {
+                return_address,
+                stack_pointer,
+            } => {
+                emulator.program_counter() == return_address
+                    && emulator.stack_pointer() == stack_pointer
+            }
+            Step::Out { stack_pointer } => emulator.stack_pointer() < stack_pointer,
+        };
+        if complete {
+            self.step = None;
+            return Some(Stop::Step);
+        }
+        None
+    }
+}
+
+/// Returns the opcode at the program counter of `emulator`.
+fn next_opcode(emulator: &Emulator) -> u16 {
+    let memory = emulator.memory();
+    let program_counter = emulator.program_counter() as usize;
+    let byte = |address: usize| memory.get(address).copied().unwrap_or(0) as u16;
+    byte(program_counter) << 8 | byte(program_counter + 1)
+}
+
+/// Parses an address written `0xNNN`, or an opcode pattern of 4 hexadecimal
+/// digits or `X`, `Y`, `N` wildcards.
+fn parse_location(word: &str) -> Result<Location, String> {
+    if word.starts_with("0x") {
+        return Ok(Location::Address(parse_value(word)?));
+    }
+    if word.len() != 4 {
+        return Err(format!("invalid breakpoint location: {}", word));
+    }
+
+    let (mut pattern, mut mask) = (0, 0);
+    for character in word.chars() {
+        pattern <<= 4;
+        mask <<= 4;
+        match character.to_ascii_uppercase() {
+            'X' | 'Y' | 'N' => {}
+            digit => match digit.to_digit(16) {
+                Some(value) => {
+                    pattern |= value as u16;
+                    mask |= 0xF;
+                }
+                None => return Err(format!("invalid opcode pattern: {}", word)),
+            },
+        }
+    }
+    Ok(Location::Opcode { pattern, mask })
+}
+
+fn parse_operand(word: &str) -> Result<Operand, String> {
+    match word.to_ascii_uppercase().as_str() {
+        "I" => Ok(Operand::Index),
+        "DT" => Ok(Operand::DelayTimer),
+        "ST" => Ok(Operand::SoundTimer),
+        register if register.len() == 2 && register.starts_with('V') => {
+            u8::from_str_radix(&register[1..], 16)
+                .map(Operand::Register)
+                .map_err(|_| format!("invalid register: {}", word))
+        }
+        _ => Err(format!("invalid operand: {}", word)),
+    }
+}
+
+fn parse_comparison(word: &str) -> Result<Comparison, String> {
+    match word {
+        "==" => Ok(Comparison::Equal),
+        "!=" => Ok(Comparison::NotEqual),
+        "<" => Ok(Comparison::Less),
+        "<=" => Ok(Comparison::LessOrEqual),
+        ">" => Ok(Comparison::Greater),
+        ">=" => Ok(Comparison::GreaterOrEqual),
+        _ => Err(format!("invalid comparison: {}", word)),
+    }
+}
+
+/// Parses a decimal value, or a hexadecimal one prefixed by `0x`.
+pub(crate) fn parse_value(word: &str) -> Result<u16, String> {
+    match word.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => word.parse(),
+    }
+    .map_err(|_| format!("invalid value: {}", word))
+}
@@ -1,20 +1,18 @@
 //! # A module to emulate the chip8 architecture, and process its opcodes logic.
-use rand::Rng;
-use std::cell::RefCell;
-use std::fmt;
-use std::rc::Rc;
-use wasm_bindgen::JsCast;
-use web_sys::{console, HtmlCollection, HtmlTableRowElement};
+//!
+//! This module is the platform-independent core of `chiphuit`: it only holds
+//! the CPU, memory, timers, framebuffer and keypad state, and has no browser
+//! dependency. Frontends drive it by feeding the keypad, calling
+//! `Emulator::cycle` a number of times per frame, `Emulator::vblank` 60 times
+//! per second, and reading `Emulator::screen`.
+use core::fmt;
 
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 
-use crate::utils::arrays;
-
-#[derive(Serialize, Debug)]
-struct TypeWithConstGenericArray<const N: usize> {
-    #[serde(with = "arrays")]
-    arr: [u8; N],
-}
+use crate::error::EmulatorError;
+use crate::quirks::{IndexIncrement, Quirks};
+use crate::random::Random;
+use crate::watchpoint::{Access, Watchpoint, WatchpointHit};
 
 /// Chip8 fonts set.
 pub const FONTS: [u8; 80] = [
@@ -36,8 +34,37 @@ pub const FONTS: [u8; 80] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
 
+/// SUPER-CHIP big fonts set, loaded right after the chip8 fonts set.
+pub const BIG_FONTS: [u8; 160] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
+/// Screen width and height in low resolution mode.
+pub const LORES: (usize, usize) = (64, 32);
+
+/// Screen width and height in SUPER-CHIP high resolution mode.
+pub const HIRES: (usize, usize) = (128, 64);
+
+/// Size of the XO-CHIP 64 KiB address space.
+pub const MEMORY_SIZE: usize = 0x10000;
+
 /// A struct to access a chip8's opcodes nibbles.
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
 pub struct OpCode {
     pub first_nibble: u8,
     pub second_nibble: u8,
@@ -60,6 +87,60 @@ impl OpCode {
             | self.fourth_nibble as u16)
             & 0x0FFF
     }
+
+    /// Util function to read the 4 nibbles of the opcode in a single u16.
+    pub fn to_u16(&self) -> u16 {
+        (self.first_nibble as u16) << 12
+            | (self.second_nibble as u16) << 8
+            | (self.third_nibble as u16) << 4
+            | self.fourth_nibble as u16
+    }
+}
+
+/// What the `Emulator` should do when an instruction faults.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FaultPolicy {
+    /// Stop at the faulting instruction and return the `EmulatorError`.
+    Halt,
+    /// Skip the faulting instruction and keep running.
+    Ignore,
+    /// Stop at the faulting instruction and report it as a
+    /// `StepOutcome::Break`, so that the frontend can open its debugger.
+    Break,
+}
+
+/// The result of a successful `Emulator::cycle`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StepOutcome {
+    /// The instruction was executed.
+    Executed,
+    /// The ROM exited the interpreter with `00FD`, no instruction is executed
+    /// anymore.
+    Exited,
+    /// The instruction faulted and was skipped, following
+    /// `FaultPolicy::Ignore`.
+    Ignored(EmulatorError),
+    /// The instruction faulted and the program counter was left on it,
+    /// following `FaultPolicy::Break`.
+    Break(EmulatorError),
+    /// The instruction was executed and accessed a watched range of memory.
+    Watchpoint(WatchpointHit),
+}
+
+/// A read or a write of an instruction to memory.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MemoryAccess {
+    pub access: Access,
+    pub start: usize,
+    pub len: usize,
+}
+
+/// The memory accesses of the instructions during the current frame, and
+/// during the previous one.
+#[derive(Default)]
+struct MemoryTrace {
+    frame: Vec<MemoryAccess>,
+    last_frame: Vec<MemoryAccess>,
 }
 
 /// Display Trait to print the `Emulator`'s current `OpCode` in the debugger.
@@ -88,28 +169,142 @@ pub struct Emulator {
     delay_timer: u8,
     pub sound_timer: u8,
 
-    #[serde(with = "arrays")]
-    pub screen: [bool; 64 * 32],
+    #[serde(deserialize_with = "deserialize_screen")]
+    pub screen: Vec<u8>,
+
+    #[serde(default)]
+    hires: bool,
 
-    #[serde(with = "arrays")]
-    memory: [u8; 4096],
+    #[serde(default = "default_plane")]
+    plane: u8,
+
+    #[serde(deserialize_with = "deserialize_memory")]
+    memory: Vec<u8>,
 
     #[serde(skip)]
-    pub keypad: Rc<RefCell<[bool; 16]>>,
+    pub keypad: [bool; 16],
+
+    #[serde(default)]
+    rpl_flags: [u8; 16],
+
+    #[serde(default)]
+    exited: bool,
+
+    #[serde(default)]
+    audio_pattern: Option<[u8; 16]>,
+
+    #[serde(default = "default_pitch")]
+    pitch: u8,
+
+    #[serde(default)]
+    quirks: Quirks,
 
     #[serde(skip)]
-    pub rom_buffer: Rc<RefCell<Vec<u8>>>,
+    vblank: bool,
+
+    #[serde(skip, default = "default_fault_policy")]
+    fault_policy: FaultPolicy,
 
     #[serde(skip)]
-    pub running: Rc<RefCell<bool>>,
+    watchpoints: Vec<Watchpoint>,
 
+    /// Watchpoint index, kind of access and address of the first watched
+    /// access of the current instruction.
     #[serde(skip)]
-    pub tracing: Rc<RefCell<bool>>,
+    watchpoint_hit: Option<(usize, Access, u16)>,
 
+    /// Start and length of the memory writes, recorded while journaling.
     #[serde(skip)]
-    pub load_snapshot: Rc<RefCell<Option<Emulator>>>,
+    journal: Option<Vec<(usize, usize)>>,
+
+    /// Reads and writes of the instructions, recorded while tracing them.
+    #[serde(skip)]
+    memory_trace: Option<MemoryTrace>,
+
+    /// Random number generator of `CXNN`, seeded from the system entropy in
+    /// snapshots predating it.
+    #[serde(default = "Random::from_entropy")]
+    rng: Random,
 }
 
+/// The state of an `Emulator` besides its memory and screen, small enough to
+/// be saved after every instruction by the rewind engine.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct CpuState {
+    current_opcode: OpCode,
+    registers: [u8; 16],
+    index_register: u16,
+    program_counter: u16,
+    stack: [u16; 16],
+    stack_pointer: usize,
+    delay_timer: u8,
+    sound_timer: u8,
+    hires: bool,
+    plane: u8,
+    rpl_flags: [u8; 16],
+    exited: bool,
+    audio_pattern: Option<[u8; 16]>,
+    pitch: u8,
+    vblank: bool,
+    rng: Random,
+}
+
+impl CpuState {
+    /// Returns the number of pixels of the screen in the resolution mode of
+    /// this state.
+    pub(crate) fn screen_size(&self) -> usize {
+        match self.hires {
+            true => HIRES.0 * HIRES.1,
+            false => LORES.0 * LORES.1,
+        }
+    }
+
+    /// Returns the line of a trace for the instruction at `address` which
+    /// led to this state: its address and opcode, then the registers, `I`,
+    /// the stack pointer and the timers.
+    pub(crate) fn trace_line(&self, address: u16) -> String {
+        let registers = self
+            .registers
+            .iter()
+            .map(|register| format!("{:02X}", register))
+            .collect::<Vec<String>>()
+            .join(" ");
+        format!(
+            "{:04X} {} {} {:04X} {:2} {:02X} {:02X}",
+            address,
+            self.current_opcode,
+            registers,
+            self.index_register,
+            self.stack_pointer,
+            self.delay_timer,
+            self.sound_timer
+        )
+    }
+
+    pub(crate) fn program_counter(&self) -> u16 {
+        self.program_counter
+    }
+
+    /// Returns whether the stack pointer and the drawing plane mask are in
+    /// range, as they are in the states of a running `Emulator`.
+    pub(crate) fn is_valid(&self) -> bool {
+        self.stack_pointer <= self.stack.len() && self.plane <= 0b11
+    }
+}
+
+/// Names of the `Emulator` variables returned by `Print::printables`, in the
+/// same order.
+pub const EMULATOR_VARIABLES: [&str; 8] = [
+    "current opcode",
+    "registers",
+    "index register",
+    "program counter",
+    "delay timer",
+    "sound timer",
+    "stack pointer",
+    "stack",
+];
+
 /// Print trait to display an `Emulator`'s specific fields into the debugger
 /// during runtime.
 pub trait Print {
@@ -135,11 +330,16 @@ impl Print for Emulator {
                 .iter()
                 .map(|&x| format!("{},", x))
                 .collect::<String>(),
-            format!("{:?}", self.running.borrow()),
         ]
     }
 }
 
+impl Default for Emulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Emulator {
     //! Creates a new empty `Emulator`.
     //!
@@ -155,13 +355,15 @@ impl Emulator {
                 third_nibble: 0_u8,
                 fourth_nibble: 0_u8,
             },
-            memory: [0; 4096],
+            memory: vec![0; MEMORY_SIZE],
 
             registers: [0; 16],
             index_register: 0,
             program_counter: 512,
 
-            screen: [false; 64 * 32],
+            screen: vec![0; LORES.0 * LORES.1],
+            hires: false,
+            plane: default_plane(),
 
             stack: [0; 16],
             stack_pointer: 0,
@@ -169,95 +371,401 @@ impl Emulator {
             delay_timer: 0,
             sound_timer: 0,
 
-            keypad: Rc::new(RefCell::new([false; 16])),
+            keypad: [false; 16],
+
+            rpl_flags: [0; 16],
+            exited: false,
+
+            audio_pattern: None,
+            pitch: default_pitch(),
+
+            quirks: Quirks::default(),
+            vblank: false,
+
+            fault_policy: default_fault_policy(),
+            watchpoints: Vec::new(),
+            watchpoint_hit: None,
+            journal: None,
+            memory_trace: None,
+            rng: Random::from_entropy(),
+        }
+    }
+
+    /// Creates a new empty `Emulator` that follows the behaviors described by
+    /// `quirks` for ambiguous opcodes.
+    pub fn with_quirks(quirks: Quirks) -> Emulator {
+        Emulator {
+            quirks,
+            ..Emulator::new()
+        }
+    }
+
+    /// Returns the V0 to VF registers.
+    pub fn registers(&self) -> [u8; 16] {
+        self.registers
+    }
+
+    /// Returns the index register I.
+    pub fn index_register(&self) -> u16 {
+        self.index_register
+    }
+
+    /// Returns the address of the next instruction to execute.
+    pub fn program_counter(&self) -> u16 {
+        self.program_counter
+    }
+
+    /// Returns the return addresses stored in the stack, including stale ones
+    /// beyond the stack pointer.
+    pub fn stack(&self) -> [u16; 16] {
+        self.stack
+    }
+
+    /// Returns the number of return addresses pushed on the stack.
+    pub fn stack_pointer(&self) -> usize {
+        self.stack_pointer
+    }
+
+    /// Returns the address of the instruction each active subroutine is
+    /// running, from the program counter to the call made by the outermost
+    /// one.
+    pub fn call_stack(&self) -> Vec<u16> {
+        let callers = self.stack[..self.stack_pointer.min(self.stack.len())]
+            .iter()
+            .rev()
+            .map(|&return_address| return_address.wrapping_sub(2));
+        std::iter::once(self.program_counter)
+            .chain(callers)
+            .collect()
+    }
 
-            rom_buffer: Rc::new(RefCell::new(Vec::new())),
-            running: Rc::new(RefCell::new(false)),
-            tracing: Rc::new(RefCell::new(false)),
-            load_snapshot: Rc::new(RefCell::new(None::<Emulator>)),
+    /// Returns the 64K of memory, holding the fonts and the ROM.
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    /// Returns the delay timer.
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    /// Returns the width of the screen in the current resolution mode.
+    pub fn width(&self) -> usize {
+        match self.hires {
+            true => HIRES.0,
+            false => LORES.0,
         }
     }
 
-    /// Update emulator state in the GUI.
-    pub fn update_emulator_state(&self, emulator_state: &HtmlCollection) {
-        // The index + 1 offset is to skip the first elements of the HtmlTable
-        // entries which are static.
-        for (index, printable) in self.printables().iter().enumerate() {
-            emulator_state
-                .get_with_index((index + 1) as u32)
-                .unwrap()
-                .dyn_into::<HtmlTableRowElement>()
-                .unwrap()
-                .cells()
-                .item(1)
-                .unwrap()
-                .set_inner_html(printable);
+    /// Returns the height of the screen in the current resolution mode.
+    pub fn height(&self) -> usize {
+        match self.hires {
+            true => HIRES.1,
+            false => LORES.1,
         }
     }
 
+    /// Returns true once the ROM has exited the interpreter with `00FD`.
+    pub fn has_exited(&self) -> bool {
+        self.exited
+    }
+
+    /// Returns the XO-CHIP audio pattern buffer, once a ROM has loaded one with
+    /// `F002`.
+    pub fn audio_pattern(&self) -> Option<[u8; 16]> {
+        self.audio_pattern
+    }
+
+    /// Returns the XO-CHIP pitch register, the audio pattern buffer is played
+    /// at `4000 * 2 ^ ((pitch - 64) / 48)` bits per second.
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    /// Sets the register `VX`, as debuggers do.
+    pub fn set_register(&mut self, index: usize, value: u8) {
+        self.registers[index & 0xF] = value;
+    }
+
+    /// Sets the index register I.
+    pub fn set_index_register(&mut self, value: u16) {
+        self.index_register = value;
+    }
+
+    /// Moves the program counter to the instruction at `address`.
+    pub fn set_program_counter(&mut self, address: u16) {
+        self.program_counter = address;
+    }
+
+    /// Sets the number of return addresses pushed on the stack, up to its 16
+    /// slots.
+    pub fn set_stack_pointer(&mut self, stack_pointer: usize) {
+        self.stack_pointer = stack_pointer.min(self.stack.len());
+    }
+
+    /// Sets the return address stored in the stack slot `index`.
+    pub fn set_stack(&mut self, index: usize, address: u16) {
+        self.stack[index & 0xF] = address;
+    }
+
+    /// Sets the delay timer.
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.delay_timer = value;
+    }
+
+    /// Writes `bytes` to memory starting at `start` without going through
+    /// the watchpoints, as debuggers do. Returns false without writing
+    /// anything if they exceed the address space.
+    pub fn set_memory(&mut self, start: usize, bytes: &[u8]) -> bool {
+        let end = match start.checked_add(bytes.len()) {
+            Some(end) => end,
+            None => return false,
+        };
+        match self.memory.get_mut(start..end) {
+            Some(memory) => {
+                memory.copy_from_slice(bytes);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the `Quirks` followed by this `Emulator`.
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    /// Sets the `Quirks` followed by this `Emulator`.
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    /// Sets what the `Emulator` should do when an instruction faults.
+    pub fn set_fault_policy(&mut self, fault_policy: FaultPolicy) {
+        self.fault_policy = fault_policy;
+    }
+
+    /// Sets the ranges of memory to watch, replacing the previous ones.
+    pub fn set_watchpoints(&mut self, watchpoints: Vec<Watchpoint>) {
+        self.watchpoints = watchpoints;
+    }
+
+    /// Returns the watched ranges of memory.
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// Seeds the random number generator of `CXNN`, which is otherwise seeded
+    /// from the system entropy, so that a run can be reproduced. The seed is
+    /// kept by `Emulator::reset`.
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.rng = Random::new(seed);
+    }
+
+    /// Signals the vertical blank interrupt to the `Emulator`, frontends call
+    /// it at the end of each frame, 60 times per second. The delay and sound
+    /// timers are decremented, and `DXYN` waits for it when the `vblank_wait`
+    /// quirk is enabled.
+    pub fn vblank(&mut self) {
+        self.vblank = true;
+        self.update_timers();
+        if let Some(trace) = &mut self.memory_trace {
+            trace.last_frame = std::mem::take(&mut trace.frame);
+        }
+    }
+
+    /// Starts recording the reads and writes of the instructions to memory,
+    /// frame by frame, or stops recording them.
+    pub fn trace_memory(&mut self, enabled: bool) {
+        self.memory_trace = match enabled {
+            true => Some(MemoryTrace::default()),
+            false => None,
+        };
+    }
+
+    /// Returns the reads and writes of the instructions to memory during the
+    /// last frame and the current one, while tracing them. Instruction
+    /// fetches aren't included.
+    pub fn memory_accesses(&self) -> impl Iterator<Item = &MemoryAccess> {
+        self.memory_trace
+            .iter()
+            .flat_map(|trace| trace.last_frame.iter().chain(&trace.frame))
+    }
+
     /// Loads the default font set into the `Emulator` instance's memory from
-    /// offset 0 to 80.
+    /// offset 0 to 80, followed by the big font set from offset 80 to 240.
     pub fn load_font(&mut self) {
         self.memory[0..80].copy_from_slice(&FONTS);
+        self.memory[80..240].copy_from_slice(&BIG_FONTS);
     }
 
     /// Loads the ROM into the `Emulator` instance's memory at offset
     /// 512.
-    pub fn load_rom(&mut self) {
-        let rom_length = self.rom_buffer.borrow().len();
-        self.memory[512..512 + rom_length].copy_from_slice(&self.rom_buffer.borrow());
-        self.rom_buffer.borrow_mut().clear();
-    }
-
-    /// Hotswaps the ROM into the `Emulator` instance's memory at
-    /// offset 512. This allows to change the game ran by the `Emulator` at
-    /// runtime without reloading the page. `Emulator` fields are reinitialized as
-    /// if `Emulator::new()` was called in order to have a fresh `Emulator`.
-    pub fn handle_rom_hotswap(&mut self) {
-        if !self.rom_buffer.borrow().is_empty() {
-            self.memory = [0; 4096];
-            self.screen = [false; 64 * 32];
-            self.registers = [0; 16];
-            self.index_register = 0;
-            self.program_counter = 512;
-            self.stack = [0; 16];
-            self.stack_pointer = 0;
-            self.delay_timer = 0;
-            self.sound_timer = 0;
-
-            self.load_rom();
-        }
-    }
-
-    /// Hotswaps the Emulator with the snapshot provided by
-    /// the user in JSON format.
-    pub fn handle_snapshot_hotswap(&mut self) {
-        if self.load_snapshot.borrow().is_some() {
-            let new_emulator = Rc::clone(&self.load_snapshot).borrow_mut().take().unwrap();
-
-            self.memory = new_emulator.memory;
-            self.screen = new_emulator.screen;
-            self.registers = new_emulator.registers;
-            self.index_register = new_emulator.index_register;
-            self.program_counter = new_emulator.program_counter;
-            self.stack = new_emulator.stack;
-            self.stack_pointer = new_emulator.stack_pointer;
-            self.delay_timer = new_emulator.delay_timer;
-            self.sound_timer = new_emulator.sound_timer;
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), EmulatorError> {
+        if rom.len() > MEMORY_SIZE - 512 {
+            return Err(EmulatorError::RomTooLarge {
+                size: rom.len(),
+                max: MEMORY_SIZE - 512,
+            });
+        }
+        self.memory[512..512 + rom.len()].copy_from_slice(rom);
+        Ok(())
+    }
+
+    /// Reinitializes the `Emulator` fields as if `Emulator::new()` was called,
+    /// with the font set loaded, in order to have a fresh `Emulator` ready to
+    /// receive a new ROM.
+    pub fn reset(&mut self) {
+        self.memory = vec![0; MEMORY_SIZE];
+        self.screen = vec![0; LORES.0 * LORES.1];
+        self.hires = false;
+        self.plane = default_plane();
+        self.exited = false;
+        self.audio_pattern = None;
+        self.pitch = default_pitch();
+        self.registers = [0; 16];
+        self.index_register = 0;
+        self.program_counter = 512;
+        self.stack = [0; 16];
+        self.stack_pointer = 0;
+        self.delay_timer = 0;
+        self.sound_timer = 0;
+
+        self.load_font();
+    }
+
+    /// Returns the state of the `Emulator` besides its memory and screen.
+    pub(crate) fn cpu_state(&self) -> CpuState {
+        CpuState {
+            current_opcode: self.current_opcode,
+            registers: self.registers,
+            index_register: self.index_register,
+            program_counter: self.program_counter,
+            stack: self.stack,
+            stack_pointer: self.stack_pointer,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            hires: self.hires,
+            plane: self.plane,
+            rpl_flags: self.rpl_flags,
+            exited: self.exited,
+            audio_pattern: self.audio_pattern,
+            pitch: self.pitch,
+            vblank: self.vblank,
+            rng: self.rng,
         }
     }
 
+    /// Restores a state returned by `cpu_state`.
+    pub(crate) fn set_cpu_state(&mut self, state: &CpuState) {
+        self.current_opcode = state.current_opcode;
+        self.registers = state.registers;
+        self.index_register = state.index_register;
+        self.program_counter = state.program_counter;
+        self.stack = state.stack;
+        self.stack_pointer = state.stack_pointer;
+        self.delay_timer = state.delay_timer;
+        self.sound_timer = state.sound_timer;
+        self.hires = state.hires;
+        self.plane = state.plane;
+        self.rpl_flags = state.rpl_flags;
+        self.exited = state.exited;
+        self.audio_pattern = state.audio_pattern;
+        self.pitch = state.pitch;
+        self.vblank = state.vblank;
+        self.rng = state.rng;
+    }
+
+    /// Returns the memory to be restored by the rewind engine.
+    pub(crate) fn memory_mut(&mut self) -> &mut [u8] {
+        &mut self.memory
+    }
+
+    /// Starts recording the ranges of memory written by the instructions.
+    pub(crate) fn start_journal(&mut self) {
+        self.journal = Some(Vec::new());
+    }
+
+    /// Stops recording the memory writes, and returns the start and length
+    /// of the ranges written since `start_journal`.
+    pub(crate) fn take_journal(&mut self) -> Vec<(usize, usize)> {
+        self.journal.take().unwrap_or_default()
+    }
+
     /// Decrements `Emulator` timers.
     fn update_timers(&mut self) {
-        if self.delay_timer > 0 {
-            self.delay_timer -= 1
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+    }
+
+    /// Returns the `len` bytes of memory starting at `start`, read for
+    /// `access`, or a `MemoryOutOfBounds` fault if they exceed the address
+    /// space.
+    fn read_memory(
+        &mut self,
+        access: Access,
+        start: usize,
+        len: usize,
+    ) -> Result<&[u8], EmulatorError> {
+        self.access_memory(access, start, len)?;
+        Ok(&self.memory[start..start + len])
+    }
+
+    /// Writes `bytes` to memory starting at `start`, or returns a
+    /// `MemoryOutOfBounds` fault without writing anything if they exceed the
+    /// address space.
+    fn write_memory(&mut self, start: usize, bytes: &[u8]) -> Result<(), EmulatorError> {
+        self.access_memory(Access::Write, start, bytes.len())?;
+        self.memory[start..start + bytes.len()].copy_from_slice(bytes);
+        if let Some(journal) = &mut self.journal {
+            journal.push((start, bytes.len()));
         }
+        Ok(())
+    }
+
+    /// Checks that the `len` bytes starting at `start` fit in the address
+    /// space, and records the first access of the current instruction to a
+    /// watched range. Every access of the instructions to memory goes through
+    /// this function.
+    fn access_memory(
+        &mut self,
+        access: Access,
+        start: usize,
+        len: usize,
+    ) -> Result<(), EmulatorError> {
+        if start + len > MEMORY_SIZE {
+            return Err(EmulatorError::MemoryOutOfBounds {
+                address: self.program_counter.wrapping_sub(2),
+                accessed: start + len - 1,
+            });
+        }
+        if let (Some(trace), false) = (&mut self.memory_trace, access == Access::Execute) {
+            trace.frame.push(MemoryAccess { access, start, len });
+        }
+        if self.watchpoint_hit.is_none() {
+            self.watchpoint_hit =
+                self.watchpoints
+                    .iter()
+                    .enumerate()
+                    .find_map(|(index, watchpoint)| {
+                        watchpoint
+                            .watches(access, start, len)
+                            .map(|address| (index, access, address))
+                    });
+        }
+        Ok(())
     }
 
     /// Fetches `Emulator` opcodes from memory in order to process them.
-    fn fetch_opcode(&mut self) {
-        let opcode = (self.memory[self.program_counter as usize] as u16) << 8
-            | self.memory[self.program_counter as usize + 1] as u16;
+    fn fetch_opcode(&mut self) -> Result<(), EmulatorError> {
+        let program_counter = self.program_counter;
+        let bytes = self
+            .read_memory(Access::Execute, program_counter as usize, 2)
+            .map_err(|_| EmulatorError::MemoryOutOfBounds {
+                address: program_counter,
+                accessed: program_counter as usize + 1,
+            })?;
+        let opcode = (bytes[0] as u16) << 8 | bytes[1] as u16;
 
         self.current_opcode = OpCode {
             first_nibble: ((opcode & 0xF000) >> 12) as u8,
@@ -265,14 +773,7 @@ impl Emulator {
             third_nibble: ((opcode & 0x00F0) >> 4) as u8,
             fourth_nibble: (opcode & 0x000F) as u8,
         };
-    }
-
-    /// Sets the carry to the VF register if needed.
-    fn carry(&self, substraction: i8) -> u8 {
-        match substraction < 0 {
-            true => 0,
-            false => 1,
-        }
+        Ok(())
     }
 
     /// Get the value of the Xth register with X being the value of the second
@@ -287,180 +788,363 @@ impl Emulator {
         self.registers[self.current_opcode.third_nibble as usize]
     }
 
-    /// Skip the next instruction by incrementing the program counter by 2.
+    /// Skip the next instruction by incrementing the program counter by 2, or
+    /// by 4 if the next instruction is the 4 bytes long XO-CHIP `F000 NNNN`.
     fn skip_next_instruction(&mut self) {
-        self.program_counter += 2;
+        let next = self.program_counter as usize;
+        let long = self.memory.get(next) == Some(&0xF0) && self.memory.get(next + 1) == Some(&0);
+        self.program_counter = self.program_counter.wrapping_add(if long { 4 } else { 2 });
+    }
+
+    /// Returns the selected bitplanes of the current drawing plane mask.
+    fn selected_planes(&self) -> impl Iterator<Item = u8> {
+        let mask = self.plane;
+        (0..2)
+            .map(|bit| 1 << bit)
+            .filter(move |plane| mask & plane != 0)
     }
 
     /// Calls machine code routine (RCA 1802 for COSMAC VIP) at
     /// address NNN. Not necessary for most ROMs.
-    fn _0nnn(&mut self) {}
+    fn _0nnn(&mut self) -> Result<(), EmulatorError> {
+        Ok(())
+    }
 
-    /// Clears the screen.
-    fn _00e0(&mut self) {
-        self.screen = [false; 64 * 32];
+    /// Clears the selected bitplanes of the screen.
+    fn _00e0(&mut self) -> Result<(), EmulatorError> {
+        let mask = self.plane;
+        self.screen.iter_mut().for_each(|pixel| *pixel &= !mask);
+        Ok(())
+    }
+
+    /// Moves the selected bitplanes of every pixel by `dx` columns and `dy`
+    /// rows, pixels scrolled in from the edges are turned off.
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let (width, height) = (self.width() as isize, self.height() as isize);
+        let mask = self.plane;
+        let previous = self.screen.clone();
+
+        for y in 0..height {
+            for x in 0..width {
+                let (source_x, source_y) = (x - dx, y - dy);
+                let source = match (0..width).contains(&source_x) && (0..height).contains(&source_y)
+                {
+                    true => previous[(source_x + source_y * width) as usize] & mask,
+                    false => 0,
+                };
+                let pixel = &mut self.screen[(x + y * width) as usize];
+                *pixel = (*pixel & !mask) | source;
+            }
+        }
+    }
+
+    /// Scrolls the display down by N pixels. (SUPER-CHIP)
+    /// scroll_down(N)
+    fn _00cn(&mut self) -> Result<(), EmulatorError> {
+        self.scroll(0, self.current_opcode.fourth_nibble as isize);
+        Ok(())
+    }
+
+    /// Scrolls the display up by N pixels. (XO-CHIP)
+    /// scroll_up(N)
+    fn _00dn(&mut self) -> Result<(), EmulatorError> {
+        self.scroll(0, -(self.current_opcode.fourth_nibble as isize));
+        Ok(())
+    }
+
+    /// Scrolls the display right by 4 pixels. (SUPER-CHIP)
+    /// scroll_right()
+    fn _00fb(&mut self) -> Result<(), EmulatorError> {
+        self.scroll(4, 0);
+        Ok(())
+    }
+
+    /// Scrolls the display left by 4 pixels. (SUPER-CHIP)
+    /// scroll_left()
+    fn _00fc(&mut self) -> Result<(), EmulatorError> {
+        self.scroll(-4, 0);
+        Ok(())
+    }
+
+    /// Exits the interpreter. (SUPER-CHIP)
+    /// exit()
+    fn _00fd(&mut self) -> Result<(), EmulatorError> {
+        self.exited = true;
+        Ok(())
+    }
+
+    /// Switches to the given resolution mode and clears the screen.
+    fn set_resolution(&mut self, hires: bool) {
+        self.hires = hires;
+        self.screen = vec![0; self.width() * self.height()];
+    }
+
+    /// Disables high resolution mode, the screen is 64x32. (SUPER-CHIP)
+    /// lores()
+    fn _00fe(&mut self) -> Result<(), EmulatorError> {
+        self.set_resolution(false);
+        Ok(())
+    }
+
+    /// Enables high resolution mode, the screen is 128x64. (SUPER-CHIP)
+    /// hires()
+    fn _00ff(&mut self) -> Result<(), EmulatorError> {
+        self.set_resolution(true);
+        Ok(())
     }
 
     /// Returns from a subroutine.
     /// return;
-    fn _00ee(&mut self) {
+    fn _00ee(&mut self) -> Result<(), EmulatorError> {
+        if self.stack_pointer == 0 {
+            return Err(EmulatorError::StackUnderflow {
+                address: self.program_counter.wrapping_sub(2),
+            });
+        }
         self.stack_pointer -= 1;
         self.program_counter = self.stack[self.stack_pointer];
+        Ok(())
     }
 
     /// Jumps to address NNN.
     /// goto NNN.
-    fn _1nnn(&mut self) {
+    fn _1nnn(&mut self) -> Result<(), EmulatorError> {
         self.program_counter = self.current_opcode.get_second_third_fourth_nibbles_inline();
+        Ok(())
     }
 
     /// Calls subroutine at NNN.
     /// *(0xNNN)()
-    fn _2nnn(&mut self) {
+    fn _2nnn(&mut self) -> Result<(), EmulatorError> {
+        if self.stack_pointer == self.stack.len() {
+            return Err(EmulatorError::StackOverflow {
+                address: self.program_counter.wrapping_sub(2),
+            });
+        }
         self.stack[self.stack_pointer] = self.program_counter;
         self.stack_pointer += 1;
         self.program_counter = self.current_opcode.get_second_third_fourth_nibbles_inline();
+        Ok(())
     }
 
     /// Skips the next instruction if VX equals NN.
     /// (Usually the next instruction is a jump to skip a code block)
     /// if (vx == NN)
-    fn _3xnn(&mut self) {
+    fn _3xnn(&mut self) -> Result<(), EmulatorError> {
         if self.get_vx() == self.current_opcode.get_third_and_fourth_nibbles_inline() {
             self.skip_next_instruction();
         }
+        Ok(())
     }
 
     /// Skips the next instruction if VX does not equal NN.
     /// (Usually the next instruction is a jump to skip a code block);
     /// if (vx != NN)
-    fn _4xnn(&mut self) {
+    fn _4xnn(&mut self) -> Result<(), EmulatorError> {
         if self.get_vx() != self.current_opcode.get_third_and_fourth_nibbles_inline() {
             self.skip_next_instruction();
         }
+        Ok(())
     }
 
     /// Skips the next instruction if VX equals VY.
     /// (Usually the next instruction is a jump to skip a code block).
     /// if (vx == vy)
-    fn _5xy0(&mut self) {
+    fn _5xy0(&mut self) -> Result<(), EmulatorError> {
         if self.get_vx() == self.get_vy() {
             self.skip_next_instruction();
         }
+        Ok(())
+    }
+
+    /// Returns the registers indexes from X to Y (including both), in
+    /// descending order if X is greater than Y.
+    fn register_range(&self) -> Vec<usize> {
+        let x = self.current_opcode.second_nibble as usize;
+        let y = self.current_opcode.third_nibble as usize;
+        match x <= y {
+            true => (x..=y).collect(),
+            false => (y..=x).rev().collect(),
+        }
+    }
+
+    /// Stores from VX to VY (including both) in memory, starting at address I.
+    /// I itself is left unmodified. (XO-CHIP)
+    /// save vx - vy
+    fn _5xy2(&mut self) -> Result<(), EmulatorError> {
+        let values: Vec<u8> = self
+            .register_range()
+            .into_iter()
+            .map(|register| self.registers[register])
+            .collect();
+        self.write_memory(self.index_register as usize, &values)
+    }
+
+    /// Fills from VX to VY (including both) with values from memory, starting
+    /// at address I. I itself is left unmodified. (XO-CHIP)
+    /// load vx - vy
+    fn _5xy3(&mut self) -> Result<(), EmulatorError> {
+        let registers = self.register_range();
+        let values = self
+            .read_memory(Access::Read, self.index_register as usize, registers.len())?
+            .to_vec();
+        for (register, value) in registers.into_iter().zip(values) {
+            self.registers[register] = value;
+        }
+        Ok(())
     }
 
     /// Sets VX to NN.
     /// vx = N
-    fn _6xnn(&mut self) {
+    fn _6xnn(&mut self) -> Result<(), EmulatorError> {
         self.registers[self.current_opcode.second_nibble as usize] =
             self.current_opcode.get_third_and_fourth_nibbles_inline();
+        Ok(())
     }
 
     /// Adds NN to VX. (Carry flag is not changed);
     /// vx += NN
-    fn _7xnn(&mut self) {
-        self.registers[self.current_opcode.second_nibble as usize] +=
-            self.current_opcode.get_third_and_fourth_nibbles_inline();
+    fn _7xnn(&mut self) -> Result<(), EmulatorError> {
+        let x = self.current_opcode.second_nibble as usize;
+        self.registers[x] = self.registers[x]
+            .wrapping_add(self.current_opcode.get_third_and_fourth_nibbles_inline());
+        Ok(())
     }
 
     /// Sets VX to the value of VY.
     /// vx = vy
-    fn _8xy0(&mut self) {
+    fn _8xy0(&mut self) -> Result<(), EmulatorError> {
         self.registers[self.current_opcode.second_nibble as usize] = self.get_vy();
+        Ok(())
+    }
+
+    /// Resets VF after a bitwise operation if the `logic_resets_vf` quirk is
+    /// enabled.
+    fn logic_quirk(&mut self) {
+        if self.quirks.logic_resets_vf {
+            self.registers[15] = 0;
+        }
     }
 
     /// Sets VX to VX or VY. (Bitwise OR operation).
     /// vx |= vy
-    fn _8xy1(&mut self) {
+    fn _8xy1(&mut self) -> Result<(), EmulatorError> {
         self.registers[self.current_opcode.second_nibble as usize] |= self.get_vy();
+        self.logic_quirk();
+        Ok(())
     }
 
     /// Sets VX to VX and VY. (Bitwise AND operation).
     /// vx &= vy
-    fn _8xy2(&mut self) {
+    fn _8xy2(&mut self) -> Result<(), EmulatorError> {
         self.registers[self.current_opcode.second_nibble as usize] &= self.get_vy();
+        self.logic_quirk();
+        Ok(())
     }
 
     /// Sets VX to VX xor VY.
     /// vx ^= vy
-    fn _8xy3(&mut self) {
+    fn _8xy3(&mut self) -> Result<(), EmulatorError> {
         self.registers[self.current_opcode.second_nibble as usize] ^= self.get_vy();
+        self.logic_quirk();
+        Ok(())
     }
 
     /// Adds VY to VX. VF is set to 1 when there's a carry,
     /// and to 0 when there is not.
     /// vx += vy
-    fn _8xy4(&mut self) {
-        let sum = (self.get_vx() + self.get_vy()) as u16;
-        self.registers[15] = match sum > 255 {
-            true => 1,
-            false => 0,
-        };
-        self.registers[self.current_opcode.second_nibble as usize] += self.get_vy();
+    fn _8xy4(&mut self) -> Result<(), EmulatorError> {
+        let (sum, carry) = self.get_vx().overflowing_add(self.get_vy());
+        self.registers[self.current_opcode.second_nibble as usize] = sum;
+        self.registers[15] = carry as u8;
+        Ok(())
     }
 
     /// VY is subtracted from VX. VF is set to 0 when there's a borrow,
     /// and 1 when there is not.
     /// vx -= vy
-    fn _8xy5(&mut self) {
-        let substraction = (self.get_vx() - self.get_vy()) as i8;
-        self.registers[15] = self.carry(substraction);
-        self.registers[self.current_opcode.second_nibble as usize] = substraction as u8;
+    fn _8xy5(&mut self) -> Result<(), EmulatorError> {
+        let (substraction, borrow) = self.get_vx().overflowing_sub(self.get_vy());
+        self.registers[self.current_opcode.second_nibble as usize] = substraction;
+        self.registers[15] = !borrow as u8;
+        Ok(())
+    }
+
+    /// Returns the register shifted by `8XY6` and `8XYE`, VY if the
+    /// `shift_uses_vy` quirk is enabled, VX otherwise.
+    fn shift_operand(&mut self) -> u8 {
+        match self.quirks.shift_uses_vy {
+            true => self.get_vy(),
+            false => self.get_vx(),
+        }
     }
 
     /// Stores the least significant bit of VX in VF and then shifts
     /// VX to the right by 1.
     /// vx >>= 1
-    fn _8xy6(&mut self) {
-        self.registers[15] = 0b00000001 & self.get_vx();
-        self.registers[self.current_opcode.second_nibble as usize] >>= 1;
+    fn _8xy6(&mut self) -> Result<(), EmulatorError> {
+        let operand = self.shift_operand();
+        self.registers[self.current_opcode.second_nibble as usize] = operand >> 1;
+        self.registers[15] = 0b00000001 & operand;
+        Ok(())
     }
 
     /// Sets VX to VY minus VX. VF is set to 0 when there's a borrow,
     /// and 1 when there is not.
     /// vx = vy - vx
-    fn _8xy7(&mut self) {
-        let substraction = (self.get_vy() - self.get_vx()) as i8;
-        self.registers[15] = self.carry(substraction);
-        self.registers[self.current_opcode.second_nibble as usize] = substraction as u8;
+    fn _8xy7(&mut self) -> Result<(), EmulatorError> {
+        let (substraction, borrow) = self.get_vy().overflowing_sub(self.get_vx());
+        self.registers[self.current_opcode.second_nibble as usize] = substraction;
+        self.registers[15] = !borrow as u8;
+        Ok(())
     }
 
     /// Stores the most significant bit of VX in VF
     /// and then shifts VX to the left by 1.
     /// vx <<= 1
-    fn _8xye(&mut self) {
-        self.registers[15] = 0b10000000 & self.get_vx();
-        self.registers[self.current_opcode.second_nibble as usize] <<= 1;
+    fn _8xye(&mut self) -> Result<(), EmulatorError> {
+        let operand = self.shift_operand();
+        self.registers[self.current_opcode.second_nibble as usize] = operand << 1;
+        self.registers[15] = (0b10000000 & operand) >> 7;
+        Ok(())
     }
 
     /// Skips the next instruction if VX does not equal VY.
     /// (Usually the next instruction is a jump to skip a code block)
     /// if (vx != vy)
-    fn _9xy0(&mut self) {
+    fn _9xy0(&mut self) -> Result<(), EmulatorError> {
         if self.get_vx() != self.get_vy() {
             self.skip_next_instruction();
         }
+        Ok(())
     }
 
     /// Sets I to the address NNN.
     /// I = NNN
-    fn annn(&mut self) {
+    fn annn(&mut self) -> Result<(), EmulatorError> {
         self.index_register = self.current_opcode.get_second_third_fourth_nibbles_inline();
+        Ok(())
     }
 
-    /// Jumps to the address NNN plus V0.
+    /// Jumps to the address NNN plus V0, or to XNN plus VX if the
+    /// `jump_uses_vx` quirk is enabled.
     /// PC = V0 + NNN
-    fn bnnn(&mut self) {
+    fn bnnn(&mut self) -> Result<(), EmulatorError> {
+        let offset = match self.quirks.jump_uses_vx {
+            true => self.get_vx(),
+            false => self.registers[0],
+        };
         self.program_counter =
-            self.registers[0] as u16 + self.current_opcode.get_second_third_fourth_nibbles_inline();
+            offset as u16 + self.current_opcode.get_second_third_fourth_nibbles_inline();
+        Ok(())
     }
 
     /// Sets VX to the result of a bitwise and operation on a random number
     /// (Typically: 0 to 255) and NN. vx = rand() & NN
-    fn cxnn(&mut self) {
-        let mut rng = rand::thread_rng();
+    fn cxnn(&mut self) -> Result<(), EmulatorError> {
         self.registers[self.current_opcode.second_nibble as usize] =
-            rng.gen::<u8>() & self.current_opcode.get_third_and_fourth_nibbles_inline();
+            self.rng.next_u8() & self.current_opcode.get_third_and_fourth_nibbles_inline();
+        Ok(())
     }
 
     /// Draws a sprite at coordinate (VX, VY) that has a width of 8 pixels and
@@ -468,25 +1152,78 @@ impl Emulator {
     /// from memory location I; I value does not change after the execution of
     /// this instruction. As described above, VF is set to 1 if any screen pixels
     /// are flipped from set to unset when the sprite is drawn, and to 0 if that
-    /// does not happen
+    /// does not happen. Sprites going past the screen edges are clipped or
+    /// wrapped around according to the `clip_sprites` quirk.
     /// draw(vx, vy, N)
-    fn dxyn(&mut self) {
-        let height = self.current_opcode.fourth_nibble;
-        let x = self.get_vx() as u16;
-        let y = self.get_vy() as u16;
-        let mut collision = false;
+    fn dxyn(&mut self) -> Result<(), EmulatorError> {
+        let height = self.current_opcode.fourth_nibble as usize;
+        self.draw_sprite(8, height)
+    }
 
-        for row in 0..height as u16 {
-            let row_pixels: [bool; 8] =
-                u8_to_bools(self.memory[(self.index_register + row) as usize]);
+    /// Draws a 16x16 sprite at coordinate (VX, VY), read from memory location
+    /// I as 2 bytes per row. (SUPER-CHIP)
+    /// draw(vx, vy, 0)
+    fn dxy0(&mut self) -> Result<(), EmulatorError> {
+        self.draw_sprite(16, 16)
+    }
 
-            for i in 0..8 {
-                let index = (x + i + (y + row) * 64) as usize;
-                let previous_state = self.screen[index];
-                self.screen[index] ^= row_pixels[i as usize];
+    /// Draws a sprite of `sprite_width` by `sprite_height` pixels at
+    /// coordinate (VX, VY), XORing it onto the screen and setting VF on
+    /// collision. Waits for the vertical blank interrupt if the `vblank_wait`
+    /// quirk is enabled.
+    fn draw_sprite(
+        &mut self,
+        sprite_width: usize,
+        sprite_height: usize,
+    ) -> Result<(), EmulatorError> {
+        if self.quirks.vblank_wait {
+            if !self.vblank {
+                self.program_counter = self.program_counter.wrapping_sub(2);
+                return Ok(());
+            }
+            self.vblank = false;
+        }
 
-                if previous_state && !self.screen[index] {
-                    collision = true;
+        let (width, height) = (self.width(), self.height());
+        let x = self.get_vx() as usize % width;
+        let y = self.get_vy() as usize % height;
+        let bytes_per_row = sprite_width / 8;
+        let planes = self.selected_planes().collect::<Vec<u8>>();
+        let sprite = self
+            .read_memory(
+                Access::Read,
+                self.index_register as usize,
+                planes.len() * sprite_height * bytes_per_row,
+            )?
+            .to_vec();
+        let mut collision = false;
+
+        // With XO-CHIP, each selected bitplane is drawn with its own sprite
+        // data, stored right after the previous plane's one.
+        for (plane, data) in planes
+            .into_iter()
+            .zip(sprite.chunks(sprite_height * bytes_per_row))
+        {
+            for row in 0..sprite_height {
+                if self.quirks.clip_sprites && y + row >= height {
+                    break;
+                }
+
+                for column in 0..sprite_width {
+                    if self.quirks.clip_sprites && x + column >= width {
+                        break;
+                    }
+
+                    let byte = data[row * bytes_per_row + column / 8];
+                    if byte & (0b10000000 >> (column % 8)) == 0 {
+                        continue;
+                    }
+
+                    let index = (x + column) % width + (y + row) % height * width;
+                    if self.screen[index] & plane != 0 {
+                        collision = true;
+                    }
+                    self.screen[index] ^= plane;
                 }
             }
         }
@@ -494,70 +1231,122 @@ impl Emulator {
             true => 1,
             false => 0,
         };
+        Ok(())
     }
 
     /// Skips the next instruction if the key stored in VX is pressed.
     /// (Usually the next instruction is a jump to skip a code block);
     /// if (key() == vx)
-    fn ex9e(&mut self) {
-        if self.keypad.borrow()[self.registers[self.current_opcode.second_nibble as usize] as usize]
-        {
+    fn ex9e(&mut self) -> Result<(), EmulatorError> {
+        if self.keypad[self.get_vx() as usize & 0xF] {
             self.skip_next_instruction();
         }
+        Ok(())
     }
 
     /// Skips the next instruction if the key stored in VX is not pressed.
     /// (Usually the next instruction is a jump to skip a code block).
     /// if (key() != vx)
-    fn exa1(&mut self) {
-        if !self.keypad.borrow()
-            [self.registers[self.current_opcode.second_nibble as usize] as usize]
-        {
+    fn exa1(&mut self) -> Result<(), EmulatorError> {
+        if !self.keypad[self.get_vx() as usize & 0xF] {
             self.skip_next_instruction();
         }
+        Ok(())
     }
 
     /// Sets VX to the value of the delay timer.
     /// vx = get_delay()
-    fn fx07(&mut self) {
+    fn fx07(&mut self) -> Result<(), EmulatorError> {
         self.registers[self.current_opcode.second_nibble as usize] = self.delay_timer;
+        Ok(())
     }
 
     /// A key press is awaited, and then stored in VX. (Blocking Operation).
     /// All instruction halted until next key event);
     /// vx = get_key()
-    fn fx0a(&mut self) {
-        self.program_counter -= 2;
-        if self.keypad.borrow()[self.registers[self.current_opcode.second_nibble as usize] as usize]
-        {
+    fn fx0a(&mut self) -> Result<(), EmulatorError> {
+        self.program_counter = self.program_counter.wrapping_sub(2);
+        if self.keypad[self.get_vx() as usize & 0xF] {
             self.registers[self.current_opcode.second_nibble as usize] = self.get_vx();
-            self.program_counter += 2;
+            self.program_counter = self.program_counter.wrapping_add(2);
         }
+        Ok(())
+    }
+
+    /// Selects the bitplanes N (from 0 to 3) drawn, cleared and scrolled by
+    /// the next opcodes. (XO-CHIP)
+    /// plane N
+    fn fn01(&mut self) -> Result<(), EmulatorError> {
+        self.plane = self.current_opcode.second_nibble & 0b11;
+        Ok(())
+    }
+
+    /// Loads the 16 bytes starting at I into the audio pattern buffer.
+    /// (XO-CHIP)
+    /// audio
+    fn f002(&mut self) -> Result<(), EmulatorError> {
+        let mut pattern = [0; 16];
+        pattern.copy_from_slice(self.read_memory(
+            Access::Read,
+            self.index_register as usize,
+            16,
+        )?);
+        self.audio_pattern = Some(pattern);
+        Ok(())
+    }
+
+    /// Sets I to the 16 bits address NNNN stored after the opcode. (XO-CHIP)
+    /// I = NNNN
+    fn f000(&mut self) -> Result<(), EmulatorError> {
+        let address = self.read_memory(Access::Execute, self.program_counter as usize, 2)?;
+        self.index_register = (address[0] as u16) << 8 | address[1] as u16;
+        self.program_counter = self.program_counter.wrapping_add(2);
+        Ok(())
+    }
+
+    /// Sets the audio pitch register to VX. (XO-CHIP)
+    /// pitch := vx
+    fn fx3a(&mut self) -> Result<(), EmulatorError> {
+        self.pitch = self.get_vx();
+        Ok(())
     }
 
     /// Sets the delay timer to VX.
     /// delay_timer(vx)
-    fn fx15(&mut self) {
+    fn fx15(&mut self) -> Result<(), EmulatorError> {
         self.delay_timer = self.get_vx();
+        Ok(())
     }
 
     /// Sets the sound timer to VX.
     /// sound_timer(vx)
-    fn fx18(&mut self) {
+    fn fx18(&mut self) -> Result<(), EmulatorError> {
         self.sound_timer = self.get_vx();
+        Ok(())
     }
 
     /// Adds VX to I. VF is not affected.
     /// I += vx
-    fn fx1e(&mut self) {
-        self.index_register += self.get_vx() as u16;
+    fn fx1e(&mut self) -> Result<(), EmulatorError> {
+        self.index_register = self.index_register.wrapping_add(self.get_vx() as u16);
+        Ok(())
     }
 
     /// Sets I to the location of the sprite for the character in VX.
     /// Characters 0-F (in hexadecimal) are represented by a 4x5 font.
     /// I = sprite_addr[vx]
-    fn fx29(&mut self) {
+    fn fx29(&mut self) -> Result<(), EmulatorError> {
         self.index_register = self.get_vx() as u16 * 5;
+        Ok(())
+    }
+
+    /// Sets I to the location of the big sprite for the character in VX.
+    /// Characters 0-F (in hexadecimal) are represented by a 8x10 font.
+    /// (SUPER-CHIP)
+    /// I = big_sprite_addr[vx]
+    fn fx30(&mut self) -> Result<(), EmulatorError> {
+        self.index_register = 80 + self.get_vx() as u16 * 10;
+        Ok(())
     }
 
     /// Stores the binary-coded decimal representation of VX, with the most
@@ -570,42 +1359,114 @@ impl Emulator {
     /// *(I+0) = BCD(3);
     /// *(I+1) = BCD(2);
     /// *(I+2) = BCD(1);
-    fn fx33(&mut self) {
-        self.memory[self.index_register as usize] = self.get_vx() / 100;
-        self.memory[self.index_register as usize + 1] = (self.get_vx() / 10) % 10;
-        self.memory[self.index_register as usize + 2] = self.get_vx() % 10;
+    fn fx33(&mut self) -> Result<(), EmulatorError> {
+        let vx = self.get_vx();
+        self.write_memory(
+            self.index_register as usize,
+            &[vx / 100, (vx / 10) % 10, vx % 10],
+        )
     }
 
     /// Stores from V0 to VX (including VX) in memory, starting at address I.
-    /// The offset from I is increased by 1 for each value written, but I
-    /// itself is left unmodified
+    /// The offset from I is increased by 1 for each value written, and I
+    /// itself is modified according to the `index_increment` quirk.
     /// reg_dump(vx, &I)
-    fn fx55(&mut self) {
-        for i in 0..self.current_opcode.second_nibble as usize + 1 {
-            self.memory[(self.index_register as usize + i)] = self.registers[i];
-        }
+    fn fx55(&mut self) -> Result<(), EmulatorError> {
+        let x = self.current_opcode.second_nibble as usize;
+        let registers = self.registers;
+        self.write_memory(self.index_register as usize, &registers[..=x])?;
+        self.index_quirk();
+        Ok(())
     }
 
     /// Fills from V0 to VX (including VX) with values from memory, starting at
     /// address I. The offset from I is increased by 1 for each value written,
-    /// but I itself is left unmodified.
+    /// and I itself is modified according to the `index_increment` quirk.
     /// reg_load(vx, &I)
-    fn fx65(&mut self) {
-        for i in 0..self.current_opcode.second_nibble as usize + 1 {
-            self.registers[i] = self.memory[(self.index_register as usize + i)];
-        }
+    fn fx65(&mut self) -> Result<(), EmulatorError> {
+        let x = self.current_opcode.second_nibble as usize;
+        let values = self
+            .read_memory(Access::Read, self.index_register as usize, x + 1)?
+            .to_vec();
+        self.registers[..=x].copy_from_slice(&values);
+        self.index_quirk();
+        Ok(())
     }
 
-    pub fn cycle(&mut self) {
-        self.fetch_opcode();
+    /// Increments I after `FX55` and `FX65` according to the
+    /// `index_increment` quirk.
+    fn index_quirk(&mut self) {
+        let x = self.current_opcode.second_nibble as u16;
+        self.index_register = self
+            .index_register
+            .wrapping_add(match self.quirks.index_increment {
+                IndexIncrement::None => 0,
+                IndexIncrement::X => x,
+                IndexIncrement::XPlusOne => x + 1,
+            });
+    }
 
-        self.process_opcode();
+    /// Stores V0 to VX (including VX) in the RPL user flags. (SUPER-CHIP)
+    /// rpl_dump(vx)
+    fn fx75(&mut self) -> Result<(), EmulatorError> {
+        let x = self.current_opcode.second_nibble as usize;
+        self.rpl_flags[..=x].copy_from_slice(&self.registers[..=x]);
+        Ok(())
+    }
 
-        self.update_timers();
+    /// Fills V0 to VX (including VX) with values from the RPL user flags.
+    /// (SUPER-CHIP)
+    /// rpl_load(vx)
+    fn fx85(&mut self) -> Result<(), EmulatorError> {
+        let x = self.current_opcode.second_nibble as usize;
+        self.registers[..=x].copy_from_slice(&self.rpl_flags[..=x]);
+        Ok(())
+    }
+
+    /// Fetches, decodes and executes the instruction at the program counter.
+    /// Faults are handled according to the
+    /// `FaultPolicy` of the `Emulator`, and accesses to watched memory are
+    /// reported once the instruction completes.
+    pub fn cycle(&mut self) -> Result<StepOutcome, EmulatorError> {
+        if self.exited {
+            return Ok(StepOutcome::Exited);
+        }
+
+        let address = self.program_counter;
+        self.watchpoint_hit = None;
+        let result = self.fetch_opcode().and_then(|_| self.process_opcode());
+
+        match (result, self.fault_policy) {
+            (Ok(()), _) if self.exited => Ok(StepOutcome::Exited),
+            (Ok(()), _) => match self.watchpoint_hit {
+                Some((watchpoint, access, accessed)) => {
+                    Ok(StepOutcome::Watchpoint(WatchpointHit {
+                        watchpoint,
+                        access,
+                        address: accessed,
+                        program_counter: address,
+                        opcode: self.current_opcode.to_u16(),
+                    }))
+                }
+                None => Ok(StepOutcome::Executed),
+            },
+            (Err(error), FaultPolicy::Ignore) => {
+                self.program_counter = address.wrapping_add(2);
+                Ok(StepOutcome::Ignored(error))
+            }
+            (Err(error), FaultPolicy::Break) => {
+                self.program_counter = address;
+                Ok(StepOutcome::Break(error))
+            }
+            (Err(error), FaultPolicy::Halt) => {
+                self.program_counter = address;
+                Err(error)
+            }
+        }
     }
 
-    pub fn process_opcode(&mut self) {
-        self.program_counter += 2;
+    pub fn process_opcode(&mut self) -> Result<(), EmulatorError> {
+        self.program_counter = self.program_counter.wrapping_add(2);
 
         match (
             self.current_opcode.first_nibble,
@@ -615,12 +1476,21 @@ impl Emulator {
         ) {
             (0, 0, 0xE, 0xE) => self._00ee(),
             (0, 0, 0xE, 0) => self._00e0(),
+            (0, 0, 0xC, _) => self._00cn(),
+            (0, 0, 0xD, _) => self._00dn(),
+            (0, 0, 0xF, 0xB) => self._00fb(),
+            (0, 0, 0xF, 0xC) => self._00fc(),
+            (0, 0, 0xF, 0xD) => self._00fd(),
+            (0, 0, 0xF, 0xE) => self._00fe(),
+            (0, 0, 0xF, 0xF) => self._00ff(),
             (0, _, _, _) => self._0nnn(),
             (1, _, _, _) => self._1nnn(),
             (2, _, _, _) => self._2nnn(),
             (3, _, _, _) => self._3xnn(),
             (4, _, _, _) => self._4xnn(),
             (5, _, _, 0) => self._5xy0(),
+            (5, _, _, 2) => self._5xy2(),
+            (5, _, _, 3) => self._5xy3(),
             (6, _, _, _) => self._6xnn(),
             (7, _, _, _) => self._7xnn(),
             (8, _, _, 0) => self._8xy0(),
@@ -636,45 +1506,73 @@ impl Emulator {
             (0xA, _, _, _) => self.annn(),
             (0xB, _, _, _) => self.bnnn(),
             (0xC, _, _, _) => self.cxnn(),
+            (0xD, _, _, 0) => self.dxy0(),
             (0xD, _, _, _) => self.dxyn(),
             (0xE, _, 9, 0xE) => self.ex9e(),
             (0xE, _, 0xA, 1) => self.exa1(),
+            (0xF, 0, 0, 0) => self.f000(),
+            (0xF, _, 0, 1) => self.fn01(),
+            (0xF, 0, 0, 2) => self.f002(),
             (0xF, _, 0, 7) => self.fx07(),
             (0xF, _, 0, 0xA) => self.fx0a(),
             (0xF, _, 1, 5) => self.fx15(),
             (0xF, _, 1, 8) => self.fx18(),
             (0xF, _, 1, 0xE) => self.fx1e(),
             (0xF, _, 2, 9) => self.fx29(),
+            (0xF, _, 3, 0) => self.fx30(),
             (0xF, _, 3, 3) => self.fx33(),
+            (0xF, _, 3, 0xA) => self.fx3a(),
             (0xF, _, 5, 5) => self.fx55(),
             (0xF, _, 6, 5) => self.fx65(),
-            _ => {
-                self.screen = [true; 2048];
-                console::log_1(
-                    &format!(
-                        "Unknown opcode: {:X}{:X}{:X}{:X}, instructions unclear, got stuck in the washing machine.",
-                        self.current_opcode.first_nibble,
-                        self.current_opcode.second_nibble,
-                        self.current_opcode.third_nibble,
-                        self.current_opcode.fourth_nibble
-                    )
-                    .into(),
-                );
-            }
+            (0xF, _, 7, 5) => self.fx75(),
+            (0xF, _, 8, 5) => self.fx85(),
+            _ => Err(EmulatorError::UnknownOpcode {
+                opcode: self.current_opcode.to_u16(),
+                address: self.program_counter.wrapping_sub(2),
+            }),
         }
     }
 }
 
-/// Returns an array of booleans according to a byte's bits.
-fn u8_to_bools(byte: u8) -> [bool; 8] {
-    [
-        0b10000000 & byte == 0b10000000,
-        0b01000000 & byte == 0b01000000,
-        0b00100000 & byte == 0b00100000,
-        0b00010000 & byte == 0b00010000,
-        0b00001000 & byte == 0b00001000,
-        0b00000100 & byte == 0b00000100,
-        0b00000010 & byte == 0b00000010,
-        0b00000001 & byte == 0b00000001,
-    ]
+/// Faults stop the `Emulator` by default.
+fn default_fault_policy() -> FaultPolicy {
+    FaultPolicy::Halt
+}
+
+/// Bitplanes drawn by default, only the first one as in chip8 and SUPER-CHIP.
+fn default_plane() -> u8 {
+    1
+}
+
+/// Default XO-CHIP pitch, playing the audio pattern buffer at 4000 bits per
+/// second.
+fn default_pitch() -> u8 {
+    64
+}
+
+/// Deserializes the screen of a snapshot, either as bitplanes or as the bools
+/// of single plane snapshots.
+fn deserialize_screen<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Pixel {
+        Planes(u8),
+        Lit(bool),
+    }
+
+    Ok(Vec::<Pixel>::deserialize(deserializer)?
+        .into_iter()
+        .map(|pixel| match pixel {
+            Pixel::Planes(planes) => planes,
+            Pixel::Lit(lit) => lit as u8,
+        })
+        .collect())
+}
+
+/// Deserializes the memory of a snapshot, padding the 4 KiB memory of chip8
+/// snapshots to the XO-CHIP address space.
+fn deserialize_memory<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    let mut memory = Vec::<u8>::deserialize(deserializer)?;
+    memory.resize(MEMORY_SIZE, 0);
+    Ok(memory)
 }
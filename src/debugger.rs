@@ -1,84 +1,403 @@
 //! # A module to view and modify the `Emulator` variables in the GUI.
-use crate::cpu::Emulator;
+use crate::assembler::{self, SourceMap};
+use crate::breakpoint::{parse_value, Breakpoint, Breakpoints};
+use crate::cpu::{Emulator, MemoryAccess, Print, BIG_FONTS, EMULATOR_VARIABLES, FONTS};
+use crate::disassembler::{self, Syntax};
+use crate::frontend::{
+    Edit, Follow, Frontend, MemoryRequest, RewindRequest, SlotRequest, StepRequest,
+};
+use crate::movie::Movie;
+use crate::savestate::SLOTS;
+use crate::storage;
 use crate::utils::{
-    append_element_to_another, append_to_body, change_view, document, read_user_file, to_clipboard,
-    EMULATOR_VARIABLES,
+    change_view, create_file_input, document, find, read_user_file, stop_key_propagation,
 };
+use crate::watchpoint::{Access, Watchpoint};
 use js_sys::JsString;
-use std::cell::RefCell;
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
+use std::convert::TryFrom;
 use std::rc::Rc;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 use web_sys::{
-    console, Event, FileReader, HtmlElement, HtmlInputElement, HtmlLabelElement,
+    console, Element, Event, FileReader, HtmlInputElement, HtmlOptionElement, HtmlSelectElement,
     HtmlTableRowElement,
 };
 
+/// Bytes of code disassembled before and after the program counter.
+const DISASSEMBLY_WINDOW: (u16, usize) = (16, 32);
+
+/// Bytes of memory shown by the memory viewer, 16 per line.
+const MEMORY_PAGE: usize = 0x1000;
+
+/// `Emulator` variables whose value can be edited while it is paused.
+const EDITABLE_VARIABLES: [&str; 7] = [
+    "registers",
+    "index register",
+    "program counter",
+    "delay timer",
+    "sound timer",
+    "stack pointer",
+    "stack",
+];
+
 /// An `Emulator` debugger.
 pub struct Debugger {
     pub element: web_sys::HtmlTableElement,
-    pub current_snapshot: Rc<RefCell<String>>,
-    pub snapshots: Rc<RefCell<Vec<String>>>,
+    pub root: Element,
+    pub syntax: Rc<RefCell<Syntax>>,
+    /// Address of the memory viewer when last shown, to scroll to it once it
+    /// moves.
+    pub memory_cursor: Cell<Option<u16>>,
+    /// Memory shown by the memory viewer and the classes of its bytes, to
+    /// rebuild it only once they change.
+    pub memory_shown: RefCell<(Vec<u8>, Vec<Option<&'static str>>)>,
+    /// Labels of the ROM, from the source code it was assembled from or from
+    /// a symbol file.
+    pub symbols: Rc<RefCell<Option<SourceMap>>>,
+}
+
+/// The state of the `Emulator` shown by the debugger.
+#[derive(Serialize, Deserialize, Default)]
+pub struct View {
+    pub printables: Vec<String>,
+    pub disassembly: String,
+    /// Address of the memory editor, and the page of memory around it.
+    pub memory_address: u16,
+    pub memory: Vec<u8>,
+    /// Accesses to memory since the start of the previous frame.
+    pub accesses: Vec<MemoryAccess>,
+    pub program_counter: u16,
+    pub index_register: u16,
+    /// Address of the instruction run by each active subroutine, from the
+    /// program counter on, and the address the subroutine was called at.
+    pub call_stack: Vec<(u16, Option<u16>)>,
+}
+
+impl View {
+    /// Returns the view of `emulator`, running or not, with its code
+    /// disassembled in `syntax` and its memory shown around the address of
+    /// the memory editor of `frontend`.
+    pub fn new(emulator: &Emulator, frontend: &Frontend, syntax: Syntax) -> View {
+        let mut printables = emulator.printables();
+        printables.push(format!("{:?}", *frontend.running.borrow()));
+        let memory_address = frontend.memory_cursor(emulator);
+        let start = memory_page_start(memory_address);
+        let page = start..start + MEMORY_PAGE;
+        View {
+            printables,
+            disassembly: disassembly_listing(
+                emulator,
+                frontend.disassembly_address(emulator),
+                syntax,
+            ),
+            memory_address,
+            memory: emulator.memory()[page.clone()].to_vec(),
+            accesses: emulator
+                .memory_accesses()
+                .filter(|access| access.start < page.end && access.start + access.len > page.start)
+                .copied()
+                .collect(),
+            program_counter: emulator.program_counter(),
+            index_register: emulator.index_register(),
+            call_stack: emulator
+                .call_stack()
+                .into_iter()
+                .enumerate()
+                .map(|(frame, address)| match frame {
+                    0 => (address, None),
+                    _ => (address, call_target(emulator, address)),
+                })
+                .collect(),
+        }
+    }
 }
 
 impl Debugger {
-    /// Returns a Debugger that can be paired to an Emulator.
+    /// Returns a Debugger that can be paired to an Emulator, rendered in
+    /// `container`.
     ///
     /// # Arguments
     ///
-    /// * `element` - A HTMLTableElement to render the Debugger in the GUI.
-    /// * `current_snapshot` - Serialized Emulator snapshot during runtime.
-    /// * `snapshots` - Stacked serialized Emulator snapshots, when tracing.
-    pub fn new() -> Debugger {
-        let debugger = create_element();
+    /// * `container` - The element containing the UI of the Emulator, in
+    ///   which the HTMLTableElement of the Debugger is created.
+    pub fn new(container: &Element) -> Debugger {
+        let debugger = create_element(container);
 
         Debugger {
             element: debugger,
-            current_snapshot: Rc::new(RefCell::new(String::new())),
-            snapshots: Rc::new(RefCell::new(Vec::new())),
+            root: container.clone(),
+            syntax: Rc::new(RefCell::new(Syntax::Classic)),
+            memory_cursor: Cell::new(None),
+            memory_shown: RefCell::new((Vec::new(), Vec::new())),
+            symbols: Rc::new(RefCell::new(None)),
         }
     }
 
     /// Fill the Debugger elements in the GUI.
-    pub fn set_debugger(self: &Debugger, emulator: &Emulator) {
+    pub fn set_debugger(self: &Debugger, frontend: &Frontend) {
         fill_rows(&self.element);
+        editable_values(&self.element, &frontend.running, &frontend.edits);
+        disassembly(&self.element, &self.syntax);
+        call_stack(&self.element, &frontend.disassembly_focus);
+        memory_editor(&self.element, frontend);
 
         // 2nd row
         load(&self.element);
-        copy(&self.element, &self.current_snapshot);
+        copy(&self.element, &frontend.copy_request);
+        slots(&self.element, &frontend.slot, &frontend.slot_request);
+        download(&self.element, &frontend.download_request);
 
         // 3rd row
-        trace(&self.element, &emulator.tracing);
-        set_load_file_reader(&emulator.load_snapshot);
-        dump(self);
+        rewind(&self.element, &frontend.rewind_request);
+        trace(&self.element, &frontend.trace_request);
+        set_load_file_reader(&self.element, &frontend.load_snapshot);
+
+        // 4th row
+        record(&self.element, &frontend.recording);
+        replay(&self.element);
+        set_replay_file_reader(&self.element, &frontend.load_movie);
+        seed(&self.element, &frontend.seed);
+
+        // 5th row
+        speed(&self.element, &frontend.instructions_per_frame);
+        vblank_wait(&self.element, &frontend.vblank_wait);
+
+        // 6th row
+        assemble(&self.element);
+        set_assemble_file_reader(
+            &self.element,
+            &frontend.rom_buffer,
+            &frontend.running,
+            &self.symbols,
+        );
+        set_symbols_file_reader(&self.element, &self.symbols);
+
+        // 7th row
+        library(&self.element, frontend, &self.symbols);
+
+        // 8th to 10th rows
+        steps(&self.element, &frontend.step_request);
+        breakpoints(&self.element, &frontend.breakpoints);
+        watchpoints(&self.element, &frontend.watchpoints);
 
         // last row
-        set_breakpoint_and_keypad_view(&self.element);
+        set_breakpoint_and_keypad_view(&self.element, &self.root, &frontend.running);
+    }
+
+    /// Update emulator state in the GUI.
+    pub fn update_emulator_state(self: &Debugger, emulator: &Emulator, frontend: &Frontend) {
+        self.show(&View::new(emulator, frontend, *self.syntax.borrow()));
+    }
+
+    /// Show the state of the `Emulator` in the GUI.
+    pub fn show(self: &Debugger, view: &View) {
+        // The index + 1 offset is to skip the first elements of the HtmlTable
+        // entries which are static.
+        for (index, printable) in view.printables.iter().enumerate() {
+            let cell = self
+                .element
+                .rows()
+                .get_with_index((index + 1) as u32)
+                .unwrap()
+                .dyn_into::<HtmlTableRowElement>()
+                .unwrap()
+                .cells()
+                .item(1)
+                .unwrap();
+            // Keep the values being edited.
+            if cell.query_selector("input").unwrap().is_none() {
+                cell.set_inner_html(printable);
+            }
+        }
+
+        find(&self.element, "disassembly").set_text_content(Some(&view.disassembly));
+        self.show_call_stack(view);
+        let viewer = find(&self.element, "memory_viewer");
+        let moved =
+            self.memory_cursor.replace(Some(view.memory_address)) != Some(view.memory_address);
+        let classes = memory_classes(view);
+        let mut shown = self.memory_shown.borrow_mut();
+        if moved || shown.0 != view.memory || shown.1 != classes {
+            viewer.set_inner_html(&memory_listing(view, &classes));
+            *shown = (view.memory.clone(), classes);
+        }
+        // Scroll to the address once it moves, leaving the user scroll
+        // the memory around it.
+        if moved {
+            let line = (view.memory_address as usize % MEMORY_PAGE / 16) as i32;
+            let line_height = viewer.scroll_height() / (MEMORY_PAGE / 16) as i32;
+            viewer.set_scroll_top((line - 4).max(0) * line_height);
+        }
+    }
+
+    /// Show the active frames of the call stack, each with the address it
+    /// runs and, for the callers, the subroutine they called, along with
+    /// their labels.
+    fn show_call_stack(self: &Debugger, view: &View) {
+        let frames = find(&self.element, "call_stack");
+        frames
+            .set_attribute("data-pc", &view.program_counter.to_string())
+            .unwrap();
+        frames.set_text_content(None);
+
+        let symbols = self.symbols.borrow();
+        let symbolic = |address: u16| match symbols.as_ref().and_then(|map| map.symbol(address)) {
+            Some(symbol) => format!("{:04X} {}", address, symbol),
+            None => format!("{:04X}", address),
+        };
+        for (index, (address, callee)) in view.call_stack.iter().enumerate() {
+            let mut text = format!("#{:<2} {}", index, symbolic(*address));
+            if let Some(callee) = callee {
+                text.push_str(&format!(" call {}", symbolic(*callee)));
+            }
+            let frame = document().create_element("div").unwrap();
+            frame.set_class_name("frame");
+            frame
+                .set_attribute("data-address", &address.to_string())
+                .unwrap();
+            frame.set_text_content(Some(&text));
+            frames.append_child(&frame).unwrap();
+        }
     }
+}
 
-    pub fn get_serialized_current_snapshot(self: &Debugger, emulator: &Emulator) {
-        *self.current_snapshot.borrow_mut() = serde_json::to_string(&emulator).unwrap();
+/// Returns the subroutine called by the `2NNN` instruction at `address`, if
+/// it is one.
+fn call_target(emulator: &Emulator, address: u16) -> Option<u16> {
+    let memory = emulator.memory();
+    let address = address as usize;
+    match (memory.get(address)?, memory.get(address + 1)?) {
+        (&high, &low) if high >> 4 == 0x2 => Some(u16::from_be_bytes([high & 0xF, low])),
+        _ => None,
     }
+}
+
+/// Disassemble the code around `address`, usually the program counter, which
+/// is pointed at by `->`, while another `address` is pointed at by `=>`.
+/// Instructions targeted by jumps and calls are marked by `*`.
+fn disassembly_listing(emulator: &Emulator, address: u16, syntax: Syntax) -> String {
+    let program_counter = emulator.program_counter();
+    let start = address.saturating_sub(DISASSEMBLY_WINDOW.0) as usize;
+    let end = address as usize + DISASSEMBLY_WINDOW.1;
 
-    pub fn handle_tracing(self: &Debugger, emulator: &Emulator) {
-        if *emulator.tracing.borrow() {
-            self.snapshots
-                .borrow_mut()
-                .push(self.current_snapshot.borrow().to_string());
+    disassembler::disassemble(emulator.memory(), start..end, syntax)
+        .iter()
+        .map(|instruction| {
+            format!(
+                "{:2}{:04X}{:2}{:<10}{}",
+                if instruction.address == program_counter {
+                    "->"
+                } else if instruction.address == address {
+                    "=>"
+                } else {
+                    ""
+                },
+                instruction.address,
+                if instruction.is_target { "*" } else { "" },
+                instruction
+                    .bytes
+                    .iter()
+                    .map(|byte| format!("{:02X}", byte))
+                    .collect::<String>(),
+                instruction.mnemonic
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Returns the address of the page of memory shown by the memory viewer at
+/// `address`.
+fn memory_page_start(address: u16) -> usize {
+    address as usize & !(MEMORY_PAGE - 1)
+}
+
+/// Returns the classes highlighting the bytes of the page shown by the
+/// memory viewer: the instruction at the program counter, the byte `I`
+/// points at, the bytes written or read during the last frames, then the
+/// fonts.
+fn memory_classes(view: &View) -> Vec<Option<&'static str>> {
+    let start = memory_page_start(view.memory_address);
+    let mut classes = vec![None; view.memory.len()];
+    let end = start + classes.len();
+    let mut mark = |from: usize, len: usize, class| {
+        for address in from.max(start)..(from + len).min(end) {
+            classes[address - start] = Some(class);
+        }
+    };
+
+    // From the lowest priority to the highest.
+    mark(0, FONTS.len() + BIG_FONTS.len(), "font");
+    for (kind, class) in [(Access::Read, "read"), (Access::Write, "write")].iter() {
+        for access in view.accesses.iter().filter(|access| access.access == *kind) {
+            mark(access.start, access.len, class);
         }
     }
+    mark(view.index_register as usize, 1, "index");
+    mark(view.program_counter as usize, 2, "pc");
+    classes
+}
+
+/// Write the page of memory shown by the memory viewer in HTML, 16 bytes per
+/// line in hexadecimal then in ASCII, with its address pointed at by `>` and
+/// the bytes highlighted by their `classes`.
+fn memory_listing(view: &View, classes: &[Option<&'static str>]) -> String {
+    let start = memory_page_start(view.memory_address);
+    view.memory
+        .chunks(16)
+        .enumerate()
+        .map(|(line, bytes)| {
+            let address = start + line * 16;
+            let mut hex = String::new();
+            let mut ascii = String::new();
+            let mut class = None;
+            for (index, byte) in bytes.iter().enumerate() {
+                let next = classes[line * 16 + index];
+                if next != class {
+                    if class.is_some() {
+                        hex.push_str("</span>");
+                        ascii.push_str("</span>");
+                    }
+                    if let Some(next) = next {
+                        hex.push_str(&format!("<span class=\"{}\">", next));
+                        ascii.push_str(&format!("<span class=\"{}\">", next));
+                    }
+                    class = next;
+                }
+                hex.push_str(match address + index == view.memory_address as usize {
+                    true => "&gt;",
+                    false => " ",
+                });
+                hex.push_str(&format!("{:02X}", byte));
+                match byte {
+                    b'<' => ascii.push_str("&lt;"),
+                    b'>' => ascii.push_str("&gt;"),
+                    b'&' => ascii.push_str("&amp;"),
+                    0x20..=0x7E => ascii.push(*byte as char),
+                    _ => ascii.push('.'),
+                }
+            }
+            if class.is_some() {
+                hex.push_str("</span>");
+                ascii.push_str("</span>");
+            }
+            format!("{:04X}{} |{}|", address, hex, ascii)
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
 }
 
-/// Create the `Debugger` GUI element.
-fn create_element() -> web_sys::HtmlTableElement {
+/// Create the `Debugger` GUI element in `container`.
+fn create_element(container: &Element) -> web_sys::HtmlTableElement {
     let element = document()
         .create_element("table")
         .expect("should have an element.")
         .dyn_into::<web_sys::HtmlTableElement>()
         .expect("should have an HtmlTableElement.");
-    element.set_id("debugger");
     element.set_class_name("debugger");
-    append_to_body(&element);
+    container.append_child(&element).unwrap();
     element
 }
 
@@ -97,7 +416,7 @@ fn fill_rows(element: &web_sys::HtmlTableElement) {
     first_row.insert_cell().unwrap().set_inner_html("variable");
     first_row.insert_cell().unwrap().set_inner_html("value");
 
-    for variable in EMULATOR_VARIABLES.iter() {
+    for variable in EMULATOR_VARIABLES.iter().chain(&["running"]) {
         let row = element
             .insert_row()
             .unwrap()
@@ -113,33 +432,372 @@ fn fill_rows(element: &web_sys::HtmlTableElement) {
     }
 }
 
-/// Activate tracing mode of the VM, allowing to save the VM's internal snapshot
-/// at each CPU cycle, and to save it in JSON format.
-fn trace(element: &web_sys::HtmlTableElement, tracing: &Rc<RefCell<bool>>) {
+/// Make the values of the registers, the timers and the stack editable while
+/// the `Emulator` is paused: clicking one turns it into an input, whose text
+/// is written back into the `Emulator` once changed.
+fn editable_values(
+    element: &web_sys::HtmlTableElement,
+    running: &Rc<RefCell<bool>>,
+    edits: &Rc<RefCell<Vec<Edit>>>,
+) {
+    let rows = element.rows();
+    for (index, variable) in EMULATOR_VARIABLES.iter().enumerate() {
+        if !EDITABLE_VARIABLES.contains(variable) {
+            continue;
+        }
+        let cell = rows
+            .get_with_index((index + 1) as u32)
+            .unwrap()
+            .dyn_into::<HtmlTableRowElement>()
+            .unwrap()
+            .cells()
+            .item(1)
+            .unwrap();
+        cell.set_class_name("editable");
+
+        let input: HtmlInputElement = document()
+            .create_element("input")
+            .unwrap()
+            .dyn_into::<HtmlInputElement>()
+            .unwrap();
+        stop_key_propagation(&input);
+
+        let (running, input_clone, cell_clone) = (Rc::clone(running), input.clone(), cell.clone());
+        let edit_callback = Closure::wrap(Box::new(move |_event: web_sys::MouseEvent| {
+            if *running.borrow() || input_clone.is_connected() {
+                return;
+            }
+            input_clone.set_value(cell_clone.text_content().unwrap_or_default().trim());
+            cell_clone.set_text_content(None);
+            cell_clone.append_child(&input_clone).unwrap();
+            input_clone.focus().unwrap();
+        }) as Box<dyn FnMut(_)>);
+
+        cell.add_event_listener_with_callback("mousedown", edit_callback.as_ref().unchecked_ref())
+            .unwrap();
+        edit_callback.forget();
+
+        let (edits, input_clone) = (Rc::clone(edits), input.clone());
+        let change_callback = Closure::wrap(Box::new(move |_event: Event| {
+            match parse_edit(variable, &input_clone.value()) {
+                Ok(edit) => edits.borrow_mut().push(edit),
+                Err(error) => console::log_1(&error.into()),
+            }
+            input_clone.blur().unwrap();
+        }) as Box<dyn FnMut(_)>);
+
+        input
+            .add_event_listener_with_callback("change", change_callback.as_ref().unchecked_ref())
+            .unwrap();
+        change_callback.forget();
+
+        let input_clone = input.clone();
+        let blur_callback = Closure::wrap(Box::new(move |_event: Event| {
+            cell.set_text_content(Some(&input_clone.value()));
+        }) as Box<dyn FnMut(_)>);
+
+        input
+            .add_event_listener_with_callback("blur", blur_callback.as_ref().unchecked_ref())
+            .unwrap();
+        blur_callback.forget();
+    }
+}
+
+/// Parses the text typed in the value of `variable`, written the way the
+/// debugger shows it.
+fn parse_edit(variable: &str, text: &str) -> Result<Edit, String> {
+    let text = text.trim();
+    let byte = |text: &str| {
+        let value = parse_value(text)?;
+        u8::try_from(value).map_err(|_| format!("{} out of range: {}", variable, value))
+    };
+    match variable {
+        "registers" => parse_list(text, |word| {
+            u8::from_str_radix(word.strip_prefix("0x").unwrap_or(word), 16)
+                .map_err(|_| format!("invalid register: {}", word))
+        })
+        .map(Edit::Registers),
+        "index register" => parse_value(text).map(Edit::IndexRegister),
+        "program counter" => parse_value(text).map(Edit::ProgramCounter),
+        "delay timer" => byte(text).map(Edit::DelayTimer),
+        "sound timer" => byte(text).map(Edit::SoundTimer),
+        "stack pointer" => match parse_value(text)? {
+            stack_pointer if stack_pointer <= 16 => Ok(Edit::StackPointer(stack_pointer as usize)),
+            stack_pointer => Err(format!("stack pointer out of range: {}", stack_pointer)),
+        },
+        "stack" => parse_list(text, parse_value).map(Edit::Stack),
+        _ => Err(format!("{} can't be edited", variable)),
+    }
+}
+
+/// Parses the 16 values of a list separated by commas.
+fn parse_list<T: Copy + Default>(
+    text: &str,
+    parse: impl Fn(&str) -> Result<T, String>,
+) -> Result<[T; 16], String> {
+    let values = text
+        .split(',')
+        .map(str::trim)
+        .filter(|word| !word.is_empty())
+        .map(parse)
+        .collect::<Result<Vec<T>, String>>()?;
+    if values.len() != 16 {
+        return Err(format!("expected 16 values, found {}", values.len()));
+    }
+    let mut list = [T::default(); 16];
+    list.copy_from_slice(&values);
+    Ok(list)
+}
+
+/// Parses hexadecimal bytes, such as `A2 2A` or `A22A`.
+fn parse_bytes(text: &str) -> Result<Vec<u8>, String> {
+    let digits: String = text.split_whitespace().collect();
+    if digits.is_empty() || !digits.len().is_multiple_of(2) {
+        return Err(format!("invalid bytes: {}", text));
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|index| {
+            digits
+                .get(index..index + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .ok_or_else(|| format!("invalid bytes: {}", text))
+        })
+        .collect()
+}
+
+/// Add the memory editor: inputs to go to an address, to search bytes such as
+/// `A2 2A` after it, and to write bytes there, and a button to follow the
+/// program counter or `I` instead, above the memory viewer.
+fn memory_editor(element: &web_sys::HtmlTableElement, frontend: &Frontend) {
     let row = element
         .insert_row()
         .unwrap()
         .dyn_into::<web_sys::HtmlTableRowElement>()
         .unwrap();
 
-    let trace = row.insert_cell().unwrap();
+    let follow = document().create_element("td").unwrap();
+    follow.set_class_name("debugger_button");
+    follow.set_inner_html(follow_label(*frontend.memory_follow.borrow()));
 
-    trace.set_class_name("debugger_button");
-    trace.set_inner_html("trace");
+    let (memory_request, memory_follow, follow_clone) = (
+        Rc::clone(&frontend.memory_request),
+        Rc::clone(&frontend.memory_follow),
+        follow.clone(),
+    );
+    memory_input(&row, "goto 0x200", move |text| {
+        *memory_request.borrow_mut() = Some(MemoryRequest::Goto(parse_value(text)?));
+        *memory_follow.borrow_mut() = Follow::Address;
+        follow_clone.set_inner_html(follow_label(Follow::Address));
+        Ok(())
+    });
+    let (memory_request, memory_follow, follow_clone) = (
+        Rc::clone(&frontend.memory_request),
+        Rc::clone(&frontend.memory_follow),
+        follow.clone(),
+    );
+    memory_input(&row, "search A2 2A", move |text| {
+        *memory_request.borrow_mut() = Some(MemoryRequest::Search(parse_bytes(text)?));
+        *memory_follow.borrow_mut() = Follow::Address;
+        follow_clone.set_inner_html(follow_label(Follow::Address));
+        Ok(())
+    });
+    let edits = Rc::clone(&frontend.edits);
+    memory_input(&row, "write AB CD", move |text| {
+        edits.borrow_mut().push(Edit::Memory(parse_bytes(text)?));
+        Ok(())
+    });
+    row.append_child(&follow).unwrap();
 
-    let trace_clone = Rc::clone(tracing);
-    let trace_callback = Closure::wrap(Box::new(move |_event: web_sys::MouseEvent| {
-        *trace_clone.borrow_mut() ^= true;
+    let (memory_follow, follow_clone) = (Rc::clone(&frontend.memory_follow), follow.clone());
+    let follow_callback = Closure::wrap(Box::new(move |_event: web_sys::MouseEvent| {
+        let next = match *memory_follow.borrow() {
+            Follow::Address => Follow::ProgramCounter,
+            Follow::ProgramCounter => Follow::IndexRegister,
+            Follow::IndexRegister => Follow::Address,
+        };
+        *memory_follow.borrow_mut() = next;
+        follow_clone.set_inner_html(follow_label(next));
     }) as Box<dyn FnMut(_)>);
+    follow
+        .add_event_listener_with_callback("mousedown", follow_callback.as_ref().unchecked_ref())
+        .unwrap();
+    follow_callback.forget();
 
-    trace
-        .add_event_listener_with_callback("mousedown", trace_callback.as_ref().unchecked_ref())
+    let row = element
+        .insert_row()
+        .unwrap()
+        .dyn_into::<web_sys::HtmlTableRowElement>()
         .unwrap();
-    trace_callback.forget();
+    row.insert_cell().unwrap().set_inner_html("memory");
+
+    let viewer = document().create_element("pre").unwrap();
+    viewer.set_class_name("memory_viewer");
+    row.insert_cell().unwrap().append_child(&viewer).unwrap();
+}
+
+/// Returns the label of the button choosing what the memory viewer follows.
+fn follow_label(follow: Follow) -> &'static str {
+    match follow {
+        Follow::Address => "follow address",
+        Follow::ProgramCounter => "follow PC",
+        Follow::IndexRegister => "follow I",
+    }
+}
+
+/// Add an input to `row` whose text is handed to `submit` when changed, and
+/// cleared unless `submit` fails.
+fn memory_input(
+    row: &HtmlTableRowElement,
+    placeholder: &str,
+    mut submit: impl FnMut(&str) -> Result<(), String> + 'static,
+) {
+    let input: HtmlInputElement = document()
+        .create_element("input")
+        .unwrap()
+        .dyn_into::<HtmlInputElement>()
+        .unwrap();
+    input.set_placeholder(placeholder);
+    stop_key_propagation(&input);
+    row.insert_cell().unwrap().append_child(&input).unwrap();
+
+    let input_clone = input.clone();
+    let submit_callback =
+        Closure::wrap(Box::new(
+            move |_event: Event| match submit(input_clone.value().trim()) {
+                Ok(()) => input_clone.set_value(""),
+                Err(error) => console::log_1(&error.into()),
+            },
+        ) as Box<dyn FnMut(_)>);
+
+    input
+        .add_event_listener_with_callback("change", submit_callback.as_ref().unchecked_ref())
+        .unwrap();
+    submit_callback.forget();
+}
+
+/// Add the disassembly view to the `Debugger`'s table, with a button to switch
+/// between the classic and the Octo mnemonics.
+fn disassembly(element: &web_sys::HtmlTableElement, syntax: &Rc<RefCell<Syntax>>) {
+    let row = element
+        .insert_row()
+        .unwrap()
+        .dyn_into::<web_sys::HtmlTableRowElement>()
+        .unwrap();
+
+    let button = row.insert_cell().unwrap();
+
+    button.set_class_name("debugger_button");
+    button.set_inner_html("classic");
+
+    let listing = document().create_element("pre").unwrap();
+    listing.set_class_name("disassembly");
+    row.insert_cell().unwrap().append_child(&listing).unwrap();
+
+    let syntax_clone = Rc::clone(syntax);
+    let button_clone = button.clone();
+    let syntax_callback = Closure::wrap(Box::new(move |_event: web_sys::MouseEvent| {
+        let mut syntax = syntax_clone.borrow_mut();
+        *syntax = match *syntax {
+            Syntax::Classic => Syntax::Octo,
+            Syntax::Octo => Syntax::Classic,
+        };
+        button_clone.set_inner_html(match *syntax {
+            Syntax::Classic => "classic",
+            Syntax::Octo => "octo",
+        });
+    }) as Box<dyn FnMut(_)>);
+
+    button
+        .add_event_listener_with_callback("mousedown", syntax_callback.as_ref().unchecked_ref())
+        .unwrap();
+    syntax_callback.forget();
+}
+
+/// Add the call stack to the `Debugger`'s table. Clicking a frame centers the
+/// disassembly on it until the `Emulator` moves, and clicking the first one
+/// centers it back on the program counter.
+fn call_stack(
+    element: &web_sys::HtmlTableElement,
+    disassembly_focus: &Rc<RefCell<Option<(u16, u16)>>>,
+) {
+    let row = element
+        .insert_row()
+        .unwrap()
+        .dyn_into::<web_sys::HtmlTableRowElement>()
+        .unwrap();
+    row.insert_cell().unwrap().set_inner_html("call stack");
+
+    let frames = document().create_element("pre").unwrap();
+    frames.set_class_name("call_stack");
+    row.insert_cell().unwrap().append_child(&frames).unwrap();
+
+    let (disassembly_focus, frames_clone) = (Rc::clone(disassembly_focus), frames.clone());
+    let frame_callback = Closure::wrap(Box::new(move |event: web_sys::MouseEvent| {
+        let frame = event
+            .target()
+            .and_then(|target| target.dyn_into::<Element>().ok())
+            .and_then(|target| target.closest(".frame").ok().flatten());
+        let address = |element: &Element, name| {
+            element
+                .get_attribute(name)
+                .and_then(|value| value.parse::<u16>().ok())
+        };
+        if let (Some(address), Some(program_counter)) = (
+            frame.and_then(|frame| address(&frame, "data-address")),
+            address(&frames_clone, "data-pc"),
+        ) {
+            *disassembly_focus.borrow_mut() = match address == program_counter {
+                true => None,
+                false => Some((program_counter, address)),
+            };
+        }
+    }) as Box<dyn FnMut(_)>);
+
+    frames
+        .add_event_listener_with_callback("mousedown", frame_callback.as_ref().unchecked_ref())
+        .unwrap();
+    frame_callback.forget();
+}
+
+/// Add buttons to step back one instruction, and to rewind to the previous
+/// instruction a breakpoint points at.
+fn rewind(
+    element: &web_sys::HtmlTableElement,
+    rewind_request: &Rc<RefCell<Option<RewindRequest>>>,
+) {
+    let row = element
+        .insert_row()
+        .unwrap()
+        .dyn_into::<web_sys::HtmlTableRowElement>()
+        .unwrap();
+
+    for (name, request) in [
+        ("step back", RewindRequest::StepBack),
+        ("rewind", RewindRequest::ToBreakpoint),
+    ]
+    .iter()
+    {
+        let rewind = row.insert_cell().unwrap();
+
+        rewind.set_class_name("debugger_button");
+        rewind.set_inner_html(name);
+
+        let rewind_request_clone = Rc::clone(rewind_request);
+        let request = *request;
+        let rewind_callback = Closure::wrap(Box::new(move |_event: web_sys::MouseEvent| {
+            *rewind_request_clone.borrow_mut() = Some(request);
+        }) as Box<dyn FnMut(_)>);
+
+        rewind
+            .add_event_listener_with_callback("mousedown", rewind_callback.as_ref().unchecked_ref())
+            .unwrap();
+        rewind_callback.forget();
+    }
 }
 
 /// Copy the current VM snapshot in JSON format to clipboard.
-fn copy(element: &web_sys::HtmlTableElement, snapshot: &Rc<RefCell<String>>) {
+fn copy(element: &web_sys::HtmlTableElement, copy_request: &Rc<RefCell<bool>>) {
     let rows = element.rows();
 
     let copy = rows
@@ -153,9 +811,9 @@ fn copy(element: &web_sys::HtmlTableElement, snapshot: &Rc<RefCell<String>>) {
     copy.set_class_name("debugger_button");
     copy.set_inner_html("copy to 📋");
 
-    let snapshot_clone = Rc::clone(snapshot);
+    let copy_request = Rc::clone(copy_request);
     let copy_callback = Closure::wrap(Box::new(move |_event: web_sys::MouseEvent| {
-        to_clipboard(snapshot_clone.borrow().to_string());
+        *copy_request.borrow_mut() = true;
     }) as Box<dyn FnMut(_)>);
 
     copy.add_event_listener_with_callback("mousedown", copy_callback.as_ref().unchecked_ref())
@@ -163,7 +821,61 @@ fn copy(element: &web_sys::HtmlTableElement, snapshot: &Rc<RefCell<String>>) {
     copy_callback.forget();
 }
 
-/// Load a JSON VM snapshot in the `Emulator`.
+/// Download the save state of the `Emulator`.
+fn download(element: &web_sys::HtmlTableElement, download_request: &Rc<RefCell<bool>>) {
+    let rows = element.rows();
+
+    let download = rows
+        .get_with_index(rows.length() - 1)
+        .unwrap()
+        .dyn_into::<web_sys::HtmlTableRowElement>()
+        .unwrap()
+        .insert_cell()
+        .unwrap();
+
+    download.set_class_name("debugger_button");
+    download.set_inner_html("download state");
+
+    let download_request_clone = Rc::clone(download_request);
+    let download_callback = Closure::wrap(Box::new(move |_event: web_sys::MouseEvent| {
+        *download_request_clone.borrow_mut() = true;
+    }) as Box<dyn FnMut(_)>);
+
+    download
+        .add_event_listener_with_callback("mousedown", download_callback.as_ref().unchecked_ref())
+        .unwrap();
+    download_callback.forget();
+}
+
+/// Download the trace of the instructions kept in the history of the
+/// `Emulator`.
+fn trace(element: &web_sys::HtmlTableElement, trace_request: &Rc<RefCell<bool>>) {
+    let rows = element.rows();
+
+    let trace = rows
+        .get_with_index(rows.length() - 1)
+        .unwrap()
+        .dyn_into::<web_sys::HtmlTableRowElement>()
+        .unwrap()
+        .insert_cell()
+        .unwrap();
+
+    trace.set_class_name("debugger_button");
+    trace.set_inner_html("download trace");
+
+    let trace_request_clone = Rc::clone(trace_request);
+    let trace_callback = Closure::wrap(Box::new(move |_event: web_sys::MouseEvent| {
+        *trace_request_clone.borrow_mut() = true;
+    }) as Box<dyn FnMut(_)>);
+
+    trace
+        .add_event_listener_with_callback("mousedown", trace_callback.as_ref().unchecked_ref())
+        .unwrap();
+    trace_callback.forget();
+}
+
+/// Load a save state, or a JSON VM snapshot of the running ROM, in the
+/// `Emulator`.
 fn load(element: &web_sys::HtmlTableElement) {
     let row = element
         .insert_row()
@@ -173,37 +885,171 @@ fn load(element: &web_sys::HtmlTableElement) {
 
     let load = row.insert_cell().unwrap();
 
-    load.set_id("load");
-    load.set_class_name("debugger_button");
+    load.set_class_name("debugger_button load");
+    load.append_child(&create_file_input("load")).unwrap();
+}
 
-    let fileinput: HtmlInputElement = document()
+/// Set the button to allow the user to supply a VM snapshot to the `Emulator`.
+pub fn set_load_file_reader(
+    element: &web_sys::HtmlTableElement,
+    emulator_load_snapshot: &Rc<RefCell<Option<Vec<u8>>>>,
+) {
+    let file_input = find(element, "load");
+    let file_reader = FileReader::new().unwrap().dyn_into::<FileReader>().unwrap();
+
+    let handle_load_event = load_user_snapshot(emulator_load_snapshot);
+    file_reader.set_onloadend(Some(handle_load_event.as_ref().unchecked_ref()));
+    handle_load_event.forget();
+
+    let handle_read_event = read_user_file(file_reader);
+    file_input
+        .add_event_listener_with_callback("change", handle_read_event.as_ref().unchecked_ref())
+        .unwrap();
+    handle_read_event.forget();
+}
+
+/// Closure to load user input save state or VM snapshot in the Emulator.
+pub fn load_user_snapshot(
+    emulator_load_snapshot: &Rc<RefCell<Option<Vec<u8>>>>,
+) -> Closure<dyn FnMut(Event)> {
+    let load_snapshot = Rc::clone(emulator_load_snapshot);
+    Closure::wrap(Box::new(move |event: Event| {
+        *load_snapshot.borrow_mut() = Some(
+            event
+                .target()
+                .unwrap()
+                .dyn_into::<FileReader>()
+                .unwrap()
+                .result()
+                .unwrap()
+                .dyn_into::<JsString>()
+                .unwrap()
+                .iter()
+                .map(|x| x as u8)
+                .collect(),
+        );
+    }))
+}
+
+/// Add an input to choose the slot of quick saves, along with the buttons to
+/// save the state of the `Emulator` there and to load it back, also bound to
+/// `F2` and `F4`.
+fn slots(
+    element: &web_sys::HtmlTableElement,
+    slot: &Rc<RefCell<usize>>,
+    slot_request: &Rc<RefCell<Option<SlotRequest>>>,
+) {
+    let rows = element.rows();
+    let row = rows
+        .get_with_index(rows.length() - 1)
+        .unwrap()
+        .dyn_into::<web_sys::HtmlTableRowElement>()
+        .unwrap();
+
+    let input: HtmlInputElement = document()
         .create_element("input")
         .unwrap()
         .dyn_into::<HtmlInputElement>()
         .unwrap();
+    input.set_placeholder("slot");
+    stop_key_propagation(&input);
+    input.set_value(&slot.borrow().to_string());
+    row.insert_cell().unwrap().append_child(&input).unwrap();
+
+    let slot_clone = Rc::clone(slot);
+    let input_clone = input.clone();
+    let slot_callback = Closure::wrap(Box::new(move |_event: Event| {
+        let value = input_clone.value();
+        match value.trim().parse::<usize>() {
+            Ok(slot) if slot < SLOTS => *slot_clone.borrow_mut() = slot,
+            _ => console::log_1(&format!("invalid slot: {}", value).into()),
+        }
+    }) as Box<dyn FnMut(_)>);
+
+    input
+        .add_event_listener_with_callback("change", slot_callback.as_ref().unchecked_ref())
+        .unwrap();
+    slot_callback.forget();
+
+    for (name, request) in [
+        ("save state", SlotRequest::Save),
+        ("load state", SlotRequest::Load),
+    ]
+    .iter()
+    {
+        let button = row.insert_cell().unwrap();
+        button.set_class_name("debugger_button");
+        button.set_inner_html(name);
 
-    fileinput.set_id("load_upload");
-    fileinput.set_type("file");
-    append_element_to_another(&fileinput, "load");
+        let slot_request_clone = Rc::clone(slot_request);
+        let request = *request;
+        let button_callback = Closure::wrap(Box::new(move |_event: web_sys::MouseEvent| {
+            *slot_request_clone.borrow_mut() = Some(request);
+        }) as Box<dyn FnMut(_)>);
 
-    let label: HtmlLabelElement = document()
-        .create_element("label")
+        button
+            .add_event_listener_with_callback("mousedown", button_callback.as_ref().unchecked_ref())
+            .unwrap();
+        button_callback.forget();
+    }
+}
+
+/// Restart the ROM and record a movie of the session, copied to clipboard in
+/// JSON format once the recording stops.
+fn record(element: &web_sys::HtmlTableElement, recording: &Rc<RefCell<bool>>) {
+    let row = element
+        .insert_row()
         .unwrap()
-        .dyn_into::<HtmlLabelElement>()
+        .dyn_into::<web_sys::HtmlTableRowElement>()
         .unwrap();
 
-    label.set_html_for("load_upload");
-    label.set_inner_text("load");
-    append_element_to_another(&label, "load");
+    let record = row.insert_cell().unwrap();
+
+    record.set_class_name("debugger_button record");
+    record.set_inner_html("record");
+
+    let recording_clone = Rc::clone(recording);
+    let record_clone = record.clone();
+    let record_callback = Closure::wrap(Box::new(move |_event: web_sys::MouseEvent| {
+        let mut recording = recording_clone.borrow_mut();
+        *recording ^= true;
+        record_clone.set_inner_html(match *recording {
+            true => "stop",
+            false => "record",
+        });
+    }) as Box<dyn FnMut(_)>);
+
+    record
+        .add_event_listener_with_callback("mousedown", record_callback.as_ref().unchecked_ref())
+        .unwrap();
+    record_callback.forget();
 }
 
-/// Set the button to allow the user to supply a VM snapshot to the `Emulator`.
-pub fn set_load_file_reader(emulator_load_snapshot: &Rc<RefCell<Option<Emulator>>>) {
-    let file_input = document().get_element_by_id("load").unwrap();
-    // file_input.clone().dyn_into::<HtmlInputElement>().unwrap().set_type("file");
+/// Replay a JSON movie from the start of the ROM.
+fn replay(element: &web_sys::HtmlTableElement) {
+    let rows = element.rows();
+
+    let replay = rows
+        .get_with_index(rows.length() - 1)
+        .unwrap()
+        .dyn_into::<web_sys::HtmlTableRowElement>()
+        .unwrap()
+        .insert_cell()
+        .unwrap();
+
+    replay.set_class_name("debugger_button replay");
+    replay.append_child(&create_file_input("replay")).unwrap();
+}
+
+/// Set the button to allow the user to supply a movie to replay.
+pub fn set_replay_file_reader(
+    element: &web_sys::HtmlTableElement,
+    load_movie: &Rc<RefCell<Option<Movie>>>,
+) {
+    let file_input = find(element, "replay");
     let file_reader = FileReader::new().unwrap().dyn_into::<FileReader>().unwrap();
 
-    let handle_load_event = load_user_snapshot(emulator_load_snapshot);
+    let handle_load_event = load_user_movie(load_movie);
     file_reader.set_onloadend(Some(handle_load_event.as_ref().unchecked_ref()));
     handle_load_event.forget();
 
@@ -214,11 +1060,9 @@ pub fn set_load_file_reader(emulator_load_snapshot: &Rc<RefCell<Option<Emulator>
     handle_read_event.forget();
 }
 
-/// Closure to load user input VM snapshot in the Emulator.
-pub fn load_user_snapshot(
-    emulator_load_snapshot: &Rc<RefCell<Option<Emulator>>>,
-) -> Closure<dyn FnMut(Event)> {
-    let load_snapshot = Rc::clone(emulator_load_snapshot);
+/// Closure to load user input movie, replayed by the event loop.
+pub fn load_user_movie(load_movie: &Rc<RefCell<Option<Movie>>>) -> Closure<dyn FnMut(Event)> {
+    let load_movie = Rc::clone(load_movie);
     Closure::wrap(Box::new(move |event: Event| {
         let json: String = event
             .target()
@@ -231,22 +1075,96 @@ pub fn load_user_snapshot(
             .unwrap()
             .into();
 
-        let emulator: Result<Emulator, serde_json::Error> = serde_json::from_str(&json);
-
-        match emulator {
-            Ok(emulator) => *load_snapshot.borrow_mut() = Some(emulator),
-            Err(error)=> console::log_1(
-                &format!("The provided JSON failed to Deserialize into an Emulator structure, are you sure you provided a valid JSON?: {}", error).into(),
-            ),
+        match serde_json::from_str::<Movie>(&json) {
+            Ok(movie) => *load_movie.borrow_mut() = Some(movie),
+            Err(error) => console::log_1(&format!("Invalid movie: {}", error).into()),
         }
     }))
 }
 
-/// Save all the traced VM snapshots in JSON format to your clipboard.
-fn dump(debugger: &Debugger) {
-    let rows = debugger.element.rows();
+/// Add an input to choose the seed of the random numbers of `CXNN`, used from
+/// the next start of a ROM. An empty seed picks a random one.
+fn seed(element: &web_sys::HtmlTableElement, seed: &Rc<RefCell<Option<u64>>>) {
+    let rows = element.rows();
+
+    let cell = rows
+        .get_with_index(rows.length() - 1)
+        .unwrap()
+        .dyn_into::<web_sys::HtmlTableRowElement>()
+        .unwrap()
+        .insert_cell()
+        .unwrap();
+
+    let input: HtmlInputElement = document()
+        .create_element("input")
+        .unwrap()
+        .dyn_into::<HtmlInputElement>()
+        .unwrap();
+    input.set_placeholder("seed");
+    stop_key_propagation(&input);
+    cell.append_child(&input).unwrap();
+
+    let seed_clone = Rc::clone(seed);
+    let input_clone = input.clone();
+    let seed_callback = Closure::wrap(Box::new(move |_event: Event| {
+        let value = input_clone.value();
+        match value.trim() {
+            "" => *seed_clone.borrow_mut() = None,
+            text => match text.parse::<u64>() {
+                Ok(seed) => *seed_clone.borrow_mut() = Some(seed),
+                Err(_) => console::log_1(&format!("invalid seed: {}", value).into()),
+            },
+        }
+    }) as Box<dyn FnMut(_)>);
+
+    input
+        .add_event_listener_with_callback("change", seed_callback.as_ref().unchecked_ref())
+        .unwrap();
+    seed_callback.forget();
+}
+
+/// Add an input to choose the instructions run per frame, at 60 frames per
+/// second.
+fn speed(element: &web_sys::HtmlTableElement, instructions_per_frame: &Rc<RefCell<usize>>) {
+    let cell = element
+        .insert_row()
+        .unwrap()
+        .dyn_into::<web_sys::HtmlTableRowElement>()
+        .unwrap()
+        .insert_cell()
+        .unwrap();
+
+    let input: HtmlInputElement = document()
+        .create_element("input")
+        .unwrap()
+        .dyn_into::<HtmlInputElement>()
+        .unwrap();
+    input.set_placeholder("instructions per frame");
+    stop_key_propagation(&input);
+    input.set_value(&instructions_per_frame.borrow().to_string());
+    cell.append_child(&input).unwrap();
+
+    let instructions_per_frame_clone = Rc::clone(instructions_per_frame);
+    let input_clone = input.clone();
+    let speed_callback = Closure::wrap(Box::new(move |_event: Event| {
+        let value = input_clone.value();
+        match value.trim().parse::<usize>() {
+            Ok(speed) if speed > 0 => *instructions_per_frame_clone.borrow_mut() = speed,
+            _ => console::log_1(&format!("invalid instructions per frame: {}", value).into()),
+        }
+    }) as Box<dyn FnMut(_)>);
+
+    input
+        .add_event_listener_with_callback("change", speed_callback.as_ref().unchecked_ref())
+        .unwrap();
+    speed_callback.forget();
+}
+
+/// Toggle the `vblank_wait` quirk, limiting drawing to one sprite per frame.
+fn vblank_wait(element: &web_sys::HtmlTableElement, vblank_wait: &Rc<RefCell<bool>>) {
+    let rows = element.rows();
 
-    let dump = rows
+    let toggle = rows
         .get_with_index(rows.length() - 1)
         .unwrap()
         .dyn_into::<web_sys::HtmlTableRowElement>()
@@ -254,24 +1172,377 @@ fn dump(debugger: &Debugger) {
         .insert_cell()
         .unwrap();
 
-    dump.set_class_name("debugger_button");
-    dump.set_inner_html("dump");
+    let label = |vblank_wait: bool| match vblank_wait {
+        true => "vblank wait: on",
+        false => "vblank wait: off",
+    };
+    toggle.set_class_name("debugger_button");
+    toggle.set_inner_html(label(*vblank_wait.borrow()));
+
+    let vblank_wait_clone = Rc::clone(vblank_wait);
+    let toggle_clone = toggle.clone();
+    let toggle_callback = Closure::wrap(Box::new(move |_event: web_sys::MouseEvent| {
+        let mut vblank_wait = vblank_wait_clone.borrow_mut();
+        *vblank_wait ^= true;
+        toggle_clone.set_inner_html(label(*vblank_wait));
+    }) as Box<dyn FnMut(_)>);
+
+    toggle
+        .add_event_listener_with_callback("mousedown", toggle_callback.as_ref().unchecked_ref())
+        .unwrap();
+    toggle_callback.forget();
+}
+
+/// Add the library of the ROMs played, kept in the browser storage, with
+/// buttons to play the selected ROM, forgetting the labels of the previous
+/// one, and to remove it.
+fn library(
+    element: &web_sys::HtmlTableElement,
+    frontend: &Frontend,
+    symbols: &Rc<RefCell<Option<SourceMap>>>,
+) {
+    let row = element
+        .insert_row()
+        .unwrap()
+        .dyn_into::<web_sys::HtmlTableRowElement>()
+        .unwrap();
+
+    let select: HtmlSelectElement = document()
+        .create_element("select")
+        .unwrap()
+        .dyn_into::<HtmlSelectElement>()
+        .unwrap();
+    select.set_class_name("library");
+    row.insert_cell().unwrap().append_child(&select).unwrap();
+    update_library(element);
+
+    let play = row.insert_cell().unwrap();
+    play.set_class_name("debugger_button");
+    play.set_inner_html("play");
+
+    let (rom_buffer, rom_name, running, symbols) = (
+        Rc::clone(&frontend.rom_buffer),
+        Rc::clone(&frontend.rom_name),
+        Rc::clone(&frontend.running),
+        Rc::clone(symbols),
+    );
+    let select_clone = select.clone();
+    let play_callback = Closure::wrap(Box::new(move |_event: web_sys::MouseEvent| {
+        let name = select_clone.value();
+        if let Some(rom) = storage::rom(&name) {
+            *rom_buffer.borrow_mut() = rom;
+            *rom_name.borrow_mut() = Some(name);
+            *symbols.borrow_mut() = None;
+            *running.borrow_mut() = true;
+        }
+    }) as Box<dyn FnMut(_)>);
+
+    play.add_event_listener_with_callback("mousedown", play_callback.as_ref().unchecked_ref())
+        .unwrap();
+    play_callback.forget();
+
+    let remove = row.insert_cell().unwrap();
+    remove.set_class_name("debugger_button");
+    remove.set_inner_html("remove");
+
+    let element_clone = element.clone();
+    let remove_callback = Closure::wrap(Box::new(move |_event: web_sys::MouseEvent| {
+        storage::remove_rom(&select.value());
+        update_library(&element_clone);
+    }) as Box<dyn FnMut(_)>);
+
+    remove
+        .add_event_listener_with_callback("mousedown", remove_callback.as_ref().unchecked_ref())
+        .unwrap();
+    remove_callback.forget();
+}
+
+/// Fill the library found in `root` with the names of the ROMs in the browser
+/// storage.
+pub fn update_library(root: &Element) {
+    let select = match root.query_selector(".library") {
+        Ok(Some(select)) => select,
+        _ => return,
+    };
+    select.set_inner_html("");
+    for name in storage::roms() {
+        let option = HtmlOptionElement::new_with_text_and_value(&name, &name).unwrap();
+        select.append_child(&option).unwrap();
+    }
+}
+
+/// Assemble an Octo source file and run it in the `Emulator`.
+fn assemble(element: &web_sys::HtmlTableElement) {
+    let row = element
+        .insert_row()
+        .unwrap()
+        .dyn_into::<web_sys::HtmlTableRowElement>()
+        .unwrap();
+
+    let assemble = row.insert_cell().unwrap();
 
-    let snapshots_clone = Rc::clone(&debugger.snapshots);
-    let dump_callback = Closure::wrap(Box::new(move |_event: web_sys::MouseEvent| {
-        let snapshots = (*snapshots_clone.borrow()).join("");
-        to_clipboard(snapshots);
+    assemble.set_class_name("debugger_button assemble");
+    assemble
+        .append_child(&create_file_input("assemble & run"))
+        .unwrap();
+
+    let symbols = row.insert_cell().unwrap();
+
+    symbols.set_class_name("debugger_button symbols");
+    symbols
+        .append_child(&create_file_input("load symbols"))
+        .unwrap();
+}
+
+/// Set the button to allow the user to supply Octo source code, assembled into
+/// the ROM of the `Emulator`.
+pub fn set_assemble_file_reader(
+    element: &web_sys::HtmlTableElement,
+    rom_buffer: &Rc<RefCell<Vec<u8>>>,
+    running: &Rc<RefCell<bool>>,
+    symbols: &Rc<RefCell<Option<SourceMap>>>,
+) {
+    let file_input = find(element, "assemble");
+    let file_reader = FileReader::new().unwrap().dyn_into::<FileReader>().unwrap();
+
+    let handle_load_event = load_user_source(rom_buffer, running, symbols);
+    file_reader.set_onloadend(Some(handle_load_event.as_ref().unchecked_ref()));
+    handle_load_event.forget();
+
+    let handle_read_event = read_user_file(file_reader);
+    file_input
+        .add_event_listener_with_callback("change", handle_read_event.as_ref().unchecked_ref())
+        .unwrap();
+    handle_read_event.forget();
+}
+
+/// Closure to assemble user input Octo source code, and to run the resulting
+/// ROM with the labels of the source code.
+pub fn load_user_source(
+    rom_buffer: &Rc<RefCell<Vec<u8>>>,
+    running: &Rc<RefCell<bool>>,
+    symbols: &Rc<RefCell<Option<SourceMap>>>,
+) -> Closure<dyn FnMut(Event)> {
+    let rom = Rc::clone(rom_buffer);
+    let running = Rc::clone(running);
+    let symbols = Rc::clone(symbols);
+    Closure::wrap(Box::new(move |event: Event| {
+        let source: String = event
+            .target()
+            .unwrap()
+            .dyn_into::<FileReader>()
+            .unwrap()
+            .result()
+            .unwrap()
+            .dyn_into::<JsString>()
+            .unwrap()
+            .into();
+
+        match assembler::assemble_with_source_map(&source) {
+            Ok((assembled, source_map)) => {
+                *rom.borrow_mut() = assembled;
+                *symbols.borrow_mut() = Some(source_map);
+                *running.borrow_mut() = true;
+            }
+            Err(error) => console::log_1(&format!("{}", error).into()),
+        }
+    }))
+}
+
+/// Set the file reader of the symbol files uploaded by the user, naming the
+/// addresses of the ROM in the call stack.
+pub fn set_symbols_file_reader(
+    element: &web_sys::HtmlTableElement,
+    symbols: &Rc<RefCell<Option<SourceMap>>>,
+) {
+    let file_input = find(element, "symbols");
+    let file_reader = FileReader::new().unwrap().dyn_into::<FileReader>().unwrap();
+
+    let handle_load_event = load_user_symbols(symbols);
+    file_reader.set_onloadend(Some(handle_load_event.as_ref().unchecked_ref()));
+    handle_load_event.forget();
+
+    let handle_read_event = read_user_file(file_reader);
+    file_input
+        .add_event_listener_with_callback("change", handle_read_event.as_ref().unchecked_ref())
+        .unwrap();
+    handle_read_event.forget();
+}
+
+/// Parse the symbol file uploaded by the user, such as `0x0200 main` lines,
+/// replacing the labels of the ROM.
+pub fn load_user_symbols(symbols: &Rc<RefCell<Option<SourceMap>>>) -> Closure<dyn FnMut(Event)> {
+    let symbols = Rc::clone(symbols);
+    Closure::wrap(Box::new(move |event: Event| {
+        let text: String = event
+            .target()
+            .unwrap()
+            .dyn_into::<FileReader>()
+            .unwrap()
+            .result()
+            .unwrap()
+            .dyn_into::<JsString>()
+            .unwrap()
+            .into();
+
+        match SourceMap::from_symbols(&text) {
+            Ok(source_map) => *symbols.borrow_mut() = Some(source_map),
+            Err(error) => console::log_1(&format!("{}", error).into()),
+        }
+    }))
+}
+
+/// Add buttons to step into, over and out of the next instruction.
+fn steps(element: &web_sys::HtmlTableElement, step_request: &Rc<RefCell<Option<StepRequest>>>) {
+    let row = element
+        .insert_row()
+        .unwrap()
+        .dyn_into::<web_sys::HtmlTableRowElement>()
+        .unwrap();
+
+    for (name, request) in [
+        ("step into", StepRequest::Into),
+        ("step over", StepRequest::Over),
+        ("step out", StepRequest::Out),
+    ]
+    .iter()
+    {
+        let step = row.insert_cell().unwrap();
+
+        step.set_class_name("debugger_button");
+        step.set_inner_html(name);
+
+        let step_request_clone = Rc::clone(step_request);
+        let request = *request;
+        let step_callback = Closure::wrap(Box::new(move |_event: web_sys::MouseEvent| {
+            *step_request_clone.borrow_mut() = Some(request);
+        }) as Box<dyn FnMut(_)>);
+
+        step.add_event_listener_with_callback("mousedown", step_callback.as_ref().unchecked_ref())
+            .unwrap();
+        step_callback.forget();
+    }
+}
+
+/// Add an input to set breakpoints, such as `0x2A4`, `DXYN` or
+/// `0x2A4 if V3 == 5`, the list of the breakpoints set, and a button to clear
+/// them.
+fn breakpoints(element: &web_sys::HtmlTableElement, breakpoints: &Rc<RefCell<Breakpoints>>) {
+    let breakpoints_clone = Rc::clone(breakpoints);
+    let add = move |text: &str| {
+        let breakpoint = text.parse::<Breakpoint>()?;
+        let mut breakpoints = breakpoints_clone.borrow_mut();
+        breakpoints.add(breakpoint);
+        Ok(list(breakpoints.breakpoints()))
+    };
+
+    let breakpoints_clone = Rc::clone(breakpoints);
+    let clear = move || breakpoints_clone.borrow_mut().clear();
+
+    editable_list(element, "0x2A4 if V3 == 5", add, clear);
+}
+
+/// Add an input to set watchpoints, such as `0x300` or `0x000-0x04F w`, the
+/// list of the watchpoints set, and a button to clear them.
+fn watchpoints(
+    element: &web_sys::HtmlTableElement,
+    emulator_watchpoints: &Rc<RefCell<Option<Vec<Watchpoint>>>>,
+) {
+    let watchpoints = Rc::new(RefCell::new(Vec::new()));
+
+    let watchpoints_clone = Rc::clone(&watchpoints);
+    let emulator_watchpoints_clone = Rc::clone(emulator_watchpoints);
+    let add = move |text: &str| {
+        let watchpoint = text.parse::<Watchpoint>()?;
+        let mut watchpoints = watchpoints_clone.borrow_mut();
+        watchpoints.push(watchpoint);
+        *emulator_watchpoints_clone.borrow_mut() = Some(watchpoints.clone());
+        Ok(list(&watchpoints))
+    };
+
+    let emulator_watchpoints_clone = Rc::clone(emulator_watchpoints);
+    let clear = move || {
+        watchpoints.borrow_mut().clear();
+        *emulator_watchpoints_clone.borrow_mut() = Some(Vec::new());
+    };
+
+    editable_list(element, "0x000-0x04F w", add, clear);
+}
+
+/// Add a row with an input whose text is handed to `add` when changed, the
+/// list of items returned by `add`, and a button calling `clear`.
+fn editable_list(
+    element: &web_sys::HtmlTableElement,
+    placeholder: &str,
+    mut add: impl FnMut(&str) -> Result<String, String> + 'static,
+    mut clear: impl FnMut() + 'static,
+) {
+    let row = element
+        .insert_row()
+        .unwrap()
+        .dyn_into::<web_sys::HtmlTableRowElement>()
+        .unwrap();
+
+    let input: HtmlInputElement = document()
+        .create_element("input")
+        .unwrap()
+        .dyn_into::<HtmlInputElement>()
+        .unwrap();
+    input.set_placeholder(placeholder);
+    stop_key_propagation(&input);
+    row.insert_cell().unwrap().append_child(&input).unwrap();
+
+    let list = document().create_element("pre").unwrap();
+    row.insert_cell().unwrap().append_child(&list).unwrap();
+
+    let clear_button = row.insert_cell().unwrap();
+    clear_button.set_class_name("debugger_button");
+    clear_button.set_inner_html("clear");
+
+    let input_clone = input.clone();
+    let list_clone = list.clone();
+    let add_callback = Closure::wrap(Box::new(
+        move |_event: Event| match add(&input_clone.value()) {
+            Ok(items) => {
+                list_clone.set_text_content(Some(&items));
+                input_clone.set_value("");
+            }
+            Err(error) => console::log_1(&error.into()),
+        },
+    ) as Box<dyn FnMut(_)>);
+
+    input
+        .add_event_listener_with_callback("change", add_callback.as_ref().unchecked_ref())
+        .unwrap();
+    add_callback.forget();
+
+    let clear_callback = Closure::wrap(Box::new(move |_event: web_sys::MouseEvent| {
+        clear();
+        list.set_text_content(None);
     }) as Box<dyn FnMut(_)>);
 
-    dump.add_event_listener_with_callback("mousedown", dump_callback.as_ref().unchecked_ref())
+    clear_button
+        .add_event_listener_with_callback("mousedown", clear_callback.as_ref().unchecked_ref())
         .unwrap();
-    dump_callback.forget();
+    clear_callback.forget();
+}
 
-    // todo: Serialize Debugger.snapshots to JSON and allow to it save on disk.
+/// Write `items`, one per line.
+fn list<T: ToString>(items: &[T]) -> String {
+    items
+        .iter()
+        .map(|item| item.to_string())
+        .collect::<Vec<String>>()
+        .join("\n")
 }
 
-/// Set button to go back to keypad view and to play/pause in debugger view.
-fn set_breakpoint_and_keypad_view(element: &web_sys::HtmlTableElement) {
+/// Set button to go back to the keypad view of `root` and to play/pause in
+/// debugger view.
+fn set_breakpoint_and_keypad_view(
+    element: &web_sys::HtmlTableElement,
+    root: &Element,
+    running: &Rc<RefCell<bool>>,
+) {
     let row = element
         .insert_row()
         .unwrap()
@@ -281,16 +1552,11 @@ fn set_breakpoint_and_keypad_view(element: &web_sys::HtmlTableElement) {
     let breakpoint = row.insert_cell().unwrap();
 
     breakpoint.set_class_name("debugger_button");
-    breakpoint.set_id("debugger_breakpoint");
     breakpoint.set_inner_html("⏯");
 
+    let running = Rc::clone(running);
     let closure = Closure::wrap(Box::new(move |_event: web_sys::MouseEvent| {
-        document()
-            .get_element_by_id("breakpoint")
-            .unwrap()
-            .dyn_into::<HtmlElement>()
-            .unwrap()
-            .click();
+        *running.borrow_mut() ^= true;
     }) as Box<dyn FnMut(_)>);
 
     breakpoint
@@ -301,10 +1567,9 @@ fn set_breakpoint_and_keypad_view(element: &web_sys::HtmlTableElement) {
     let keypad = row.insert_cell().unwrap();
 
     keypad.set_class_name("debugger_button");
-    keypad.set_id("keypad_view");
     keypad.set_inner_html("↩");
 
-    let closure = change_view();
+    let closure = change_view(root);
 
     keypad
         .add_event_listener_with_callback("mousedown", closure.as_ref().unchecked_ref())
@@ -0,0 +1,228 @@
+//! # A module to decode the `Emulator` memory into assembly mnemonics.
+//!
+//! Instructions are decoded either with the classic mnemonics of Cowgod's
+//! chip8 reference (`LD V1, 0x20`), or with the syntax of the Octo assembler
+//! (`v1 := 0x20`). The decoding follows the dispatch of
+//! `Emulator::process_opcode`, including the SUPER-CHIP and XO-CHIP
+//! instructions; opcodes it doesn't know are rendered as raw data.
+use core::ops::Range;
+use serde::{Deserialize, Serialize};
+
+/// The flavour of mnemonics to decode instructions into.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Syntax {
+    /// Mnemonics of Cowgod's chip8 technical reference.
+    Classic,
+    /// Statements of the Octo assembler.
+    Octo,
+}
+
+/// An instruction decoded from memory.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Instruction {
+    /// Address of the first byte of the instruction.
+    pub address: u16,
+    /// The bytes of the instruction, 4 for `F000 NNNN`, 1 for a trailing
+    /// byte at the end of a range, and 2 otherwise.
+    pub bytes: Vec<u8>,
+    pub mnemonic: String,
+    /// Address jumped to or called by `1NNN`, `2NNN` and `BNNN`.
+    pub target: Option<u16>,
+    /// Whether an instruction of the disassembled range jumps to or calls
+    /// this one.
+    pub is_target: bool,
+}
+
+/// Decodes the instruction at `address` of `memory`. Bytes past the end of
+/// `memory` are read as zeroes.
+pub fn decode(memory: &[u8], address: u16, syntax: Syntax) -> Instruction {
+    let byte = |offset: u16| {
+        memory
+            .get(address.wrapping_add(offset) as usize)
+            .copied()
+            .unwrap_or(0)
+    };
+    let opcode = (byte(0) as u16) << 8 | byte(1) as u16;
+    let long = (byte(2) as u16) << 8 | byte(3) as u16;
+    let (mnemonic, size, target) = mnemonic(opcode, long, syntax);
+
+    Instruction {
+        address,
+        bytes: (0..size).map(byte).collect(),
+        mnemonic,
+        target,
+        is_target: false,
+    }
+}
+
+/// Decodes the instructions of the `range` of addresses of `memory`, one
+/// after the other, and marks the ones targeted by jumps and calls of the
+/// range.
+pub fn disassemble(memory: &[u8], range: Range<usize>, syntax: Syntax) -> Vec<Instruction> {
+    let end = range.end.min(memory.len());
+    let mut instructions = Vec::new();
+    let mut address = range.start;
+
+    while address < end {
+        let instruction = match end - address {
+            1 => Instruction {
+                address: address as u16,
+                bytes: vec![memory[address]],
+                mnemonic: data(memory[address] as u16, 2, syntax),
+                target: None,
+                is_target: false,
+            },
+            _ => decode(memory, address as u16, syntax),
+        };
+        address += instruction.bytes.len();
+        instructions.push(instruction);
+    }
+
+    let targets: Vec<u16> = instructions.iter().filter_map(|i| i.target).collect();
+    for instruction in instructions.iter_mut() {
+        instruction.is_target = targets.contains(&instruction.address);
+    }
+    instructions
+}
+
+/// Returns the mnemonic of `opcode`, the size of its instruction, and the
+/// address it jumps to if any. `long` is the word following the opcode, read
+/// by `F000 NNNN`.
+fn mnemonic(opcode: u16, long: u16, syntax: Syntax) -> (String, u16, Option<u16>) {
+    let x = (opcode >> 8 & 0xF) as usize;
+    let y = (opcode >> 4 & 0xF) as usize;
+    let n = opcode & 0xF;
+    let nn = opcode & 0xFF;
+    let nnn = opcode & 0xFFF;
+    let nibbles = (opcode >> 12, x, y, n);
+    let octo = syntax == Syntax::Octo;
+    let (vx, vy) = if octo {
+        (format!("v{:x}", x), format!("v{:x}", y))
+    } else {
+        (format!("V{:X}", x), format!("V{:X}", y))
+    };
+
+    let text = match (nibbles, octo) {
+        ((0, 0, 0xE, 0xE), false) => "RET".to_string(),
+        ((0, 0, 0xE, 0xE), true) => "return".to_string(),
+        ((0, 0, 0xE, 0), false) => "CLS".to_string(),
+        ((0, 0, 0xE, 0), true) => "clear".to_string(),
+        ((0, 0, 0xC, _), false) => format!("SCD {}", n),
+        ((0, 0, 0xC, _), true) => format!("scroll-down {}", n),
+        ((0, 0, 0xD, _), false) => format!("SCU {}", n),
+        ((0, 0, 0xD, _), true) => format!("scroll-up {}", n),
+        ((0, 0, 0xF, 0xB), false) => "SCR".to_string(),
+        ((0, 0, 0xF, 0xB), true) => "scroll-right".to_string(),
+        ((0, 0, 0xF, 0xC), false) => "SCL".to_string(),
+        ((0, 0, 0xF, 0xC), true) => "scroll-left".to_string(),
+        ((0, 0, 0xF, 0xD), false) => "EXIT".to_string(),
+        ((0, 0, 0xF, 0xD), true) => "exit".to_string(),
+        ((0, 0, 0xF, 0xE), false) => "LOW".to_string(),
+        ((0, 0, 0xF, 0xE), true) => "lores".to_string(),
+        ((0, 0, 0xF, 0xF), false) => "HIGH".to_string(),
+        ((0, 0, 0xF, 0xF), true) => "hires".to_string(),
+        ((0, _, _, _), false) => format!("SYS {:#05X}", nnn),
+        ((0, _, _, _), true) => data(opcode, 4, syntax),
+        ((1, _, _, _), false) => format!("JP {:#05X}", nnn),
+        ((1, _, _, _), true) => format!("jump {:#05X}", nnn),
+        ((2, _, _, _), false) => format!("CALL {:#05X}", nnn),
+        ((2, _, _, _), true) => format!(":call {:#05X}", nnn),
+        ((3, _, _, _), false) => format!("SE {}, {:#04X}", vx, nn),
+        ((3, _, _, _), true) => format!("if {} != {:#04X} then", vx, nn),
+        ((4, _, _, _), false) => format!("SNE {}, {:#04X}", vx, nn),
+        ((4, _, _, _), true) => format!("if {} == {:#04X} then", vx, nn),
+        ((5, _, _, 0), false) => format!("SE {}, {}", vx, vy),
+        ((5, _, _, 0), true) => format!("if {} != {} then", vx, vy),
+        ((5, _, _, 2), false) => format!("LD [I], {}-{}", vx, vy),
+        ((5, _, _, 2), true) => format!("save {} - {}", vx, vy),
+        ((5, _, _, 3), false) => format!("LD {}-{}, [I]", vx, vy),
+        ((5, _, _, 3), true) => format!("load {} - {}", vx, vy),
+        ((6, _, _, _), false) => format!("LD {}, {:#04X}", vx, nn),
+        ((6, _, _, _), true) => format!("{} := {:#04X}", vx, nn),
+        ((7, _, _, _), false) => format!("ADD {}, {:#04X}", vx, nn),
+        ((7, _, _, _), true) => format!("{} += {:#04X}", vx, nn),
+        ((8, _, _, 0), false) => format!("LD {}, {}", vx, vy),
+        ((8, _, _, 0), true) => format!("{} := {}", vx, vy),
+        ((8, _, _, 1), false) => format!("OR {}, {}", vx, vy),
+        ((8, _, _, 1), true) => format!("{} |= {}", vx, vy),
+        ((8, _, _, 2), false) => format!("AND {}, {}", vx, vy),
+        ((8, _, _, 2), true) => format!("{} &= {}", vx, vy),
+        ((8, _, _, 3), false) => format!("XOR {}, {}", vx, vy),
+        ((8, _, _, 3), true) => format!("{} ^= {}", vx, vy),
+        ((8, _, _, 4), false) => format!("ADD {}, {}", vx, vy),
+        ((8, _, _, 4), true) => format!("{} += {}", vx, vy),
+        ((8, _, _, 5), false) => format!("SUB {}, {}", vx, vy),
+        ((8, _, _, 5), true) => format!("{} -= {}", vx, vy),
+        ((8, _, _, 6), false) => format!("SHR {}, {}", vx, vy),
+        ((8, _, _, 6), true) => format!("{} >>= {}", vx, vy),
+        ((8, _, _, 7), false) => format!("SUBN {}, {}", vx, vy),
+        ((8, _, _, 7), true) => format!("{} =- {}", vx, vy),
+        ((8, _, _, 0xE), false) => format!("SHL {}, {}", vx, vy),
+        ((8, _, _, 0xE), true) => format!("{} <<= {}", vx, vy),
+        ((9, _, _, 0), false) => format!("SNE {}, {}", vx, vy),
+        ((9, _, _, 0), true) => format!("if {} == {} then", vx, vy),
+        ((0xA, _, _, _), false) => format!("LD I, {:#05X}", nnn),
+        ((0xA, _, _, _), true) => format!("i := {:#05X}", nnn),
+        ((0xB, _, _, _), false) => format!("JP V0, {:#05X}", nnn),
+        ((0xB, _, _, _), true) => format!("jump0 {:#05X}", nnn),
+        ((0xC, _, _, _), false) => format!("RND {}, {:#04X}", vx, nn),
+        ((0xC, _, _, _), true) => format!("{} := random {:#04X}", vx, nn),
+        ((0xD, _, _, _), false) => format!("DRW {}, {}, {}", vx, vy, n),
+        ((0xD, _, _, _), true) => format!("sprite {} {} {}", vx, vy, n),
+        ((0xE, _, 9, 0xE), false) => format!("SKP {}", vx),
+        ((0xE, _, 9, 0xE), true) => format!("if {} -key then", vx),
+        ((0xE, _, 0xA, 1), false) => format!("SKNP {}", vx),
+        ((0xE, _, 0xA, 1), true) => format!("if {} key then", vx),
+        ((0xF, 0, 0, 0), false) => format!("LD I, {:#06X}", long),
+        ((0xF, 0, 0, 0), true) => format!("i := long {:#06X}", long),
+        ((0xF, _, 0, 1), false) => format!("PLANE {}", x),
+        ((0xF, _, 0, 1), true) => format!("plane {}", x),
+        ((0xF, 0, 0, 2), false) => "AUDIO".to_string(),
+        ((0xF, 0, 0, 2), true) => "audio".to_string(),
+        ((0xF, _, 0, 7), false) => format!("LD {}, DT", vx),
+        ((0xF, _, 0, 7), true) => format!("{} := delay", vx),
+        ((0xF, _, 0, 0xA), false) => format!("LD {}, K", vx),
+        ((0xF, _, 0, 0xA), true) => format!("{} := key", vx),
+        ((0xF, _, 1, 5), false) => format!("LD DT, {}", vx),
+        ((0xF, _, 1, 5), true) => format!("delay := {}", vx),
+        ((0xF, _, 1, 8), false) => format!("LD ST, {}", vx),
+        ((0xF, _, 1, 8), true) => format!("buzzer := {}", vx),
+        ((0xF, _, 1, 0xE), false) => format!("ADD I, {}", vx),
+        ((0xF, _, 1, 0xE), true) => format!("i += {}", vx),
+        ((0xF, _, 2, 9), false) => format!("LD F, {}", vx),
+        ((0xF, _, 2, 9), true) => format!("i := hex {}", vx),
+        ((0xF, _, 3, 0), false) => format!("LD HF, {}", vx),
+        ((0xF, _, 3, 0), true) => format!("i := bighex {}", vx),
+        ((0xF, _, 3, 3), false) => format!("LD B, {}", vx),
+        ((0xF, _, 3, 3), true) => format!("bcd {}", vx),
+        ((0xF, _, 3, 0xA), false) => format!("LD PITCH, {}", vx),
+        ((0xF, _, 3, 0xA), true) => format!("pitch := {}", vx),
+        ((0xF, _, 5, 5), false) => format!("LD [I], {}", vx),
+        ((0xF, _, 5, 5), true) => format!("save {}", vx),
+        ((0xF, _, 6, 5), false) => format!("LD {}, [I]", vx),
+        ((0xF, _, 6, 5), true) => format!("load {}", vx),
+        ((0xF, _, 7, 5), false) => format!("LD R, {}", vx),
+        ((0xF, _, 7, 5), true) => format!("saveflags {}", vx),
+        ((0xF, _, 8, 5), false) => format!("LD {}, R", vx),
+        ((0xF, _, 8, 5), true) => format!("loadflags {}", vx),
+        _ => data(opcode, 4, syntax),
+    };
+
+    let size = if opcode == 0xF000 { 4 } else { 2 };
+    let target = match nibbles.0 {
+        1 | 2 | 0xB => Some(nnn),
+        _ => None,
+    };
+    (text, size, target)
+}
+
+/// Renders `digits` hexadecimal digits of `value` as raw data, a word with
+/// the classic syntax and bytes with the Octo one.
+fn data(value: u16, digits: usize, syntax: Syntax) -> String {
+    match (syntax, digits) {
+        (Syntax::Classic, 2) => format!("DB {:#04X}", value),
+        (Syntax::Classic, _) => format!("DW {:#06X}", value),
+        (Syntax::Octo, 2) => format!("{:#04x}", value),
+        (Syntax::Octo, _) => format!("{:#04x} {:#04x}", value >> 8, value & 0xFF),
+    }
+}
@@ -0,0 +1,52 @@
+//! # A module to describe the faults an `Emulator` can run into.
+use core::fmt;
+use serde::{Deserialize, Serialize};
+
+/// A fault raised by the `Emulator` while loading or running a ROM.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EmulatorError {
+    /// The opcode fetched at `address` is not part of any supported
+    /// instruction set.
+    UnknownOpcode { opcode: u16, address: u16 },
+    /// `2NNN` was called at `address` while the 16 stack slots were in use.
+    StackOverflow { address: u16 },
+    /// `00EE` was called at `address` while the stack was empty.
+    StackUnderflow { address: u16 },
+    /// The instruction at `address` accessed memory past the end of the
+    /// address space, at `accessed`.
+    MemoryOutOfBounds { address: u16, accessed: usize },
+    /// The ROM of `size` bytes does not fit in the `max` bytes of memory
+    /// available from offset 512.
+    RomTooLarge { size: usize, max: usize },
+}
+
+/// Display Trait to report an `EmulatorError` to the user.
+impl fmt::Display for EmulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EmulatorError::UnknownOpcode { opcode, address } => write!(
+                f,
+                "Unknown opcode: {:04X} at {:#05X}, instructions unclear, got stuck in the washing machine.",
+                opcode, address
+            ),
+            EmulatorError::StackOverflow { address } => {
+                write!(f, "Stack overflow at {:#05X}.", address)
+            }
+            EmulatorError::StackUnderflow { address } => {
+                write!(f, "Stack underflow at {:#05X}.", address)
+            }
+            EmulatorError::MemoryOutOfBounds { address, accessed } => write!(
+                f,
+                "Memory access out of bounds at {:#05X}: {:#X}.",
+                address, accessed
+            ),
+            EmulatorError::RomTooLarge { size, max } => write!(
+                f,
+                "ROM too large: {} bytes, at most {} bytes fit in memory.",
+                size, max
+            ),
+        }
+    }
+}
+
+impl std::error::Error for EmulatorError {}
@@ -0,0 +1,636 @@
+//! # A module to drive the platform-independent `Emulator` from the browser UI.
+//!
+//! The UI callbacks (keypad, breakpoint, file inputs, debugger buttons) can't
+//! borrow the `Emulator` owned by the event loop, so they write into the shared
+//! handles of a `Frontend`, which are then applied to the `Emulator` between
+//! CPU cycles. The changes of the page asked in return, such as opening the
+//! debugger or saving to the browser storage, are queued as `Effect`s applied
+//! by the UI thread, so that the `Emulator` can also run in a worker.
+use crate::breakpoint::{Breakpoints, Stop};
+use crate::cpu::{Emulator, StepOutcome};
+use crate::debugger::update_library;
+use crate::error::EmulatorError;
+use crate::movie::{Movie, Player, Recorder};
+use crate::quirks::Quirks;
+use crate::rewind::Rewind;
+use crate::savestate::{self, SaveStateError};
+use crate::scheduler::DEFAULT_INSTRUCTIONS_PER_FRAME;
+use crate::storage;
+use crate::utils::{download, set_view, to_clipboard};
+use crate::watchpoint::Watchpoint;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::rc::Rc;
+use web_sys::{console, Element};
+
+/// A step through the code requested from the debugger.
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub enum StepRequest {
+    Into,
+    Over,
+    Out,
+}
+
+/// A move back in time requested from the debugger.
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub enum RewindRequest {
+    /// Goes back one instruction.
+    StepBack,
+    /// Goes back to the previous instruction a breakpoint points at.
+    ToBreakpoint,
+}
+
+/// A quick save or quick load of the selected slot, requested from the
+/// debugger or the keyboard.
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub enum SlotRequest {
+    Save,
+    Load,
+}
+
+/// A change of the `Emulator` state typed in the debugger.
+#[derive(Serialize, Deserialize, Clone)]
+pub enum Edit {
+    Registers([u8; 16]),
+    IndexRegister(u16),
+    ProgramCounter(u16),
+    DelayTimer(u8),
+    SoundTimer(u8),
+    StackPointer(usize),
+    Stack([u16; 16]),
+    /// Writes bytes at the address of the memory editor, then moves it past
+    /// them.
+    Memory(Vec<u8>),
+}
+
+/// A move of the memory editor requested from the debugger.
+#[derive(Serialize, Deserialize, Clone)]
+pub enum MemoryRequest {
+    Goto(u16),
+    /// Moves to the next occurrence of the bytes after the current address,
+    /// wrapping around the memory.
+    Search(Vec<u8>),
+}
+
+/// What the memory viewer follows.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum Follow {
+    /// The address chosen by the user.
+    Address,
+    ProgramCounter,
+    IndexRegister,
+}
+
+/// A change of the page requested by the event loop, which may run in a
+/// worker without access to the DOM or the browser storage.
+#[derive(Serialize, Deserialize)]
+pub enum Effect {
+    /// Opens the debugger view, once the `Emulator` paused on a fault, a
+    /// breakpoint or a watchpoint.
+    ShowDebugger,
+    /// Adds a ROM to the library.
+    SaveRom {
+        name: String,
+        rom: Vec<u8>,
+    },
+    SaveState {
+        rom: Vec<u8>,
+        slot: usize,
+        state: Vec<u8>,
+    },
+    /// Reads the save state of `rom` in `slot`, loaded by the event loop.
+    LoadSlot {
+        rom: Vec<u8>,
+        slot: usize,
+    },
+    Download {
+        name: String,
+        content: Vec<u8>,
+    },
+    Clipboard(String),
+    /// Resets the record button once the recording stopped.
+    RecordingStopped,
+}
+
+/// A movie of the session, being recorded or replayed by the event loop.
+pub enum Session {
+    Recording(Recorder),
+    Replaying(Player),
+}
+
+/// Instructions rewound per frame while the rewind key is held.
+const REWIND_SPEED: usize = 10;
+
+/// Shared state between the browser UI and the event loop.
+pub struct Frontend {
+    pub keypad: Rc<RefCell<[bool; 16]>>,
+    pub rom_buffer: Rc<RefCell<Vec<u8>>>,
+    /// Name of the ROM in the buffer, added to the library once it starts.
+    pub rom_name: Rc<RefCell<Option<String>>>,
+    /// The ROM running, restarted to record or replay a movie.
+    pub rom: Rc<RefCell<Vec<u8>>>,
+    pub running: Rc<RefCell<bool>>,
+    pub rewinding: Rc<RefCell<bool>>,
+    pub rewind_request: Rc<RefCell<Option<RewindRequest>>>,
+    /// A save state or a JSON snapshot supplied by the user.
+    pub load_snapshot: Rc<RefCell<Option<Vec<u8>>>>,
+    pub slot: Rc<RefCell<usize>>,
+    pub slot_request: Rc<RefCell<Option<SlotRequest>>>,
+    /// The save state of the selected slot, read from the browser storage.
+    pub slot_state: Rc<RefCell<Option<Vec<u8>>>>,
+    pub download_request: Rc<RefCell<bool>>,
+    pub trace_request: Rc<RefCell<bool>>,
+    pub copy_request: Rc<RefCell<bool>>,
+    pub breakpoints: Rc<RefCell<Breakpoints>>,
+    pub step_request: Rc<RefCell<Option<StepRequest>>>,
+    pub watchpoints: Rc<RefCell<Option<Vec<Watchpoint>>>>,
+    pub recording: Rc<RefCell<bool>>,
+    /// Seed of the random numbers of `CXNN` chosen by the user, applied when
+    /// a ROM starts.
+    pub seed: Rc<RefCell<Option<u64>>>,
+    pub load_movie: Rc<RefCell<Option<Movie>>>,
+    pub instructions_per_frame: Rc<RefCell<usize>>,
+    /// The `vblank_wait` quirk chosen by the user, applied outside of movies.
+    pub vblank_wait: Rc<RefCell<bool>>,
+    pub edits: Rc<RefCell<Vec<Edit>>>,
+    /// Address of the memory editor, owned by the event loop.
+    pub memory_address: Rc<RefCell<u16>>,
+    pub memory_follow: Rc<RefCell<Follow>>,
+    pub memory_request: Rc<RefCell<Option<MemoryRequest>>>,
+    /// Address of a frame of the call stack chosen to center the disassembly
+    /// on, as long as the program counter stays the one it was chosen at.
+    pub disassembly_focus: Rc<RefCell<Option<(u16, u16)>>>,
+    pub effects: Rc<RefCell<Vec<Effect>>>,
+}
+
+impl Frontend {
+    /// Returns a `Frontend` with a released keypad, an empty ROM buffer, no
+    /// breakpoints, and a paused `Emulator`.
+    pub fn new() -> Frontend {
+        Frontend {
+            keypad: Rc::new(RefCell::new([false; 16])),
+            rom_buffer: Rc::new(RefCell::new(Vec::new())),
+            rom_name: Rc::new(RefCell::new(None)),
+            rom: Rc::new(RefCell::new(Vec::new())),
+            running: Rc::new(RefCell::new(false)),
+            rewinding: Rc::new(RefCell::new(false)),
+            rewind_request: Rc::new(RefCell::new(None)),
+            load_snapshot: Rc::new(RefCell::new(None)),
+            slot: Rc::new(RefCell::new(0)),
+            slot_request: Rc::new(RefCell::new(None)),
+            slot_state: Rc::new(RefCell::new(None)),
+            download_request: Rc::new(RefCell::new(false)),
+            trace_request: Rc::new(RefCell::new(false)),
+            copy_request: Rc::new(RefCell::new(false)),
+            breakpoints: Rc::new(RefCell::new(Breakpoints::new())),
+            step_request: Rc::new(RefCell::new(None)),
+            watchpoints: Rc::new(RefCell::new(None)),
+            recording: Rc::new(RefCell::new(false)),
+            seed: Rc::new(RefCell::new(None)),
+            load_movie: Rc::new(RefCell::new(None)),
+            instructions_per_frame: Rc::new(RefCell::new(DEFAULT_INSTRUCTIONS_PER_FRAME)),
+            vblank_wait: Rc::new(RefCell::new(Quirks::default().vblank_wait)),
+            edits: Rc::new(RefCell::new(Vec::new())),
+            memory_address: Rc::new(RefCell::new(0x200)),
+            memory_follow: Rc::new(RefCell::new(Follow::Address)),
+            memory_request: Rc::new(RefCell::new(None)),
+            disassembly_focus: Rc::new(RefCell::new(None)),
+            effects: Rc::new(RefCell::new(Vec::new())),
+        }
+    }
+
+    fn effect(&self, effect: Effect) {
+        self.effects.borrow_mut().push(effect);
+    }
+
+    /// Applies the `Effect`s queued by the event loop to the page, in the
+    /// emulator UI contained by `root`.
+    pub fn apply_effects(&self, root: &Element) {
+        for effect in self.effects.take() {
+            match effect {
+                Effect::ShowDebugger => set_view(root, true),
+                Effect::SaveRom { name, rom } => {
+                    storage::save_rom(&name, &rom);
+                    update_library(root);
+                }
+                Effect::SaveState { rom, slot, state } => storage::save_state(&rom, slot, &state),
+                Effect::LoadSlot { rom, slot } => match storage::state(&rom, slot) {
+                    Some(state) => *self.slot_state.borrow_mut() = Some(state),
+                    None => console::log_1(&SaveStateError::EmptySlot(slot).to_string().into()),
+                },
+                Effect::Download { name, content } => download(&name, &content),
+                Effect::Clipboard(content) => to_clipboard(content),
+                Effect::RecordingStopped => {
+                    if let Ok(Some(button)) = root.query_selector(".record") {
+                        button.set_inner_html("record");
+                    }
+                }
+            }
+        }
+    }
+
+    /// Copies the keys pressed on the virtual keypad or the keyboard into the
+    /// `Emulator`, recording them while recording a movie, or feeds it the
+    /// inputs of the replayed movie instead. Returns false once the replay is
+    /// over, pausing the `Emulator`.
+    pub fn sync_keypad(&self, emulator: &mut Emulator, session: &mut Option<Session>) -> bool {
+        match session {
+            Some(Session::Replaying(player)) => {
+                if !player.play(emulator) {
+                    console::log_1(&"Replay finished.".into());
+                    *session = None;
+                    *self.running.borrow_mut() = false;
+                    return false;
+                }
+            }
+            Some(Session::Recording(recorder)) => {
+                emulator.keypad = *self.keypad.borrow();
+                recorder.record(emulator);
+            }
+            None => emulator.keypad = *self.keypad.borrow(),
+        }
+        true
+    }
+
+    /// Signals the vertical blank interrupt to the `Emulator` once per frame,
+    /// unless it comes from the replayed movie.
+    pub fn vblank(&self, emulator: &mut Emulator, session: &mut Option<Session>) {
+        match session {
+            Some(Session::Replaying(_)) => {}
+            Some(Session::Recording(recorder)) => {
+                recorder.vblank();
+                emulator.vblank();
+            }
+            None => emulator.vblank(),
+        }
+    }
+
+    /// Applies the `vblank_wait` quirk chosen by the user to the `Emulator`,
+    /// unless a movie is recorded or replayed with the quirks it started with.
+    pub fn handle_quirks(&self, emulator: &mut Emulator, session: &Option<Session>) {
+        let vblank_wait = *self.vblank_wait.borrow();
+        if session.is_none() && emulator.quirks().vblank_wait != vblank_wait {
+            emulator.set_quirks(Quirks {
+                vblank_wait,
+                ..emulator.quirks()
+            });
+        }
+    }
+
+    /// Hotswaps the ROM supplied by the user into the `Emulator`. This allows
+    /// to change the game ran by the `Emulator` at runtime without reloading
+    /// the page.
+    pub fn handle_rom_hotswap(
+        &self,
+        emulator: &mut Emulator,
+        rewind: &mut Rewind,
+        session: &mut Option<Session>,
+    ) {
+        if !self.rom_buffer.borrow().is_empty() {
+            *self.rom.borrow_mut() = self.rom_buffer.take();
+            if let Some(name) = self.rom_name.take() {
+                let rom = self.rom.borrow().clone();
+                self.effect(Effect::SaveRom { name, rom });
+            }
+            self.stop_movie(session);
+            self.restart(emulator, rewind);
+        }
+    }
+
+    /// Puts the last ROM played in the buffer, to resume it when the
+    /// `Emulator` starts.
+    pub fn restore_last_rom(&self) {
+        if let Some(rom) = storage::last_rom().and_then(|name| storage::rom(&name)) {
+            *self.rom_buffer.borrow_mut() = rom;
+        }
+    }
+
+    /// Restarts the ROM running with the seed chosen by the user, forgetting
+    /// the history of the `Emulator`.
+    fn restart(&self, emulator: &mut Emulator, rewind: &mut Rewind) {
+        emulator.reset();
+        rewind.clear();
+        if let Some(seed) = *self.seed.borrow() {
+            emulator.seed_rng(seed);
+        }
+        if let Err(error) = emulator.load_rom(&self.rom.borrow()) {
+            console::log_1(&format!("{}", error).into());
+        }
+    }
+
+    /// Restarts the ROM running to record a movie of the session when the
+    /// recording starts, or to replay the movie provided by the user.
+    pub fn handle_movie(
+        &self,
+        emulator: &mut Emulator,
+        rewind: &mut Rewind,
+        session: &mut Option<Session>,
+    ) {
+        if let Some(movie) = self.load_movie.borrow_mut().take() {
+            self.stop_movie(session);
+            self.restart(emulator, rewind);
+            *session = Some(Session::Replaying(Player::new(movie, emulator)));
+            *self.running.borrow_mut() = true;
+            return;
+        }
+
+        let recording = *self.recording.borrow();
+        match session {
+            Some(Session::Recording(_)) if !recording => self.stop_movie(session),
+            Some(Session::Recording(_)) => {}
+            _ if recording => {
+                self.stop_movie(session);
+                self.restart(emulator, rewind);
+                let seed = self.seed.borrow().unwrap_or_else(rand::random);
+                let recorder = Recorder::new(emulator, seed);
+                *session = Some(Session::Recording(recorder));
+                *self.running.borrow_mut() = true;
+            }
+            _ => {}
+        }
+    }
+
+    /// Stops the movie being recorded or replayed. A recorded movie is copied
+    /// to the clipboard in JSON format, and downloaded.
+    pub fn stop_movie(&self, session: &mut Option<Session>) {
+        match session.take() {
+            Some(Session::Recording(recorder)) => {
+                *self.recording.borrow_mut() = false;
+                self.effect(Effect::RecordingStopped);
+                let movie = serde_json::to_string(&recorder.finish()).unwrap();
+                self.effect(Effect::Download {
+                    name: "chiphuit.movie.json".to_string(),
+                    content: movie.as_bytes().to_vec(),
+                });
+                self.effect(Effect::Clipboard(movie));
+                console::log_1(&"Movie copied to the clipboard.".into());
+            }
+            Some(Session::Replaying(_)) => console::log_1(&"Replay stopped.".into()),
+            None => {}
+        }
+    }
+
+    /// Reacts to the outcome of an `Emulator` cycle: faults are logged, and
+    /// pause the `Emulator` unless they were ignored. A `StepOutcome::Break`
+    /// also opens the debugger view on the faulting instruction, and so does
+    /// a `StepOutcome::Watchpoint` after the accessing instruction.
+    pub fn handle_outcome(&self, outcome: Result<StepOutcome, EmulatorError>) {
+        match outcome {
+            Ok(StepOutcome::Executed) => {}
+            Ok(StepOutcome::Exited) => *self.running.borrow_mut() = false,
+            Ok(StepOutcome::Ignored(error)) => console::log_1(&format!("{}", error).into()),
+            Ok(StepOutcome::Break(error)) => {
+                console::log_1(&format!("{}", error).into());
+                *self.running.borrow_mut() = false;
+                self.effect(Effect::ShowDebugger);
+            }
+            Ok(StepOutcome::Watchpoint(hit)) => {
+                console::log_1(&format!("{}", hit).into());
+                *self.running.borrow_mut() = false;
+                self.effect(Effect::ShowDebugger);
+            }
+            Err(error) => {
+                console::log_1(&format!("{}", error).into());
+                *self.running.borrow_mut() = false;
+            }
+        }
+    }
+
+    /// Starts the step requested from the debugger, resuming the `Emulator`
+    /// until the step completes.
+    pub fn handle_step_request(&self, emulator: &Emulator) {
+        if let Some(request) = self.step_request.borrow_mut().take() {
+            let mut breakpoints = self.breakpoints.borrow_mut();
+            match request {
+                StepRequest::Into => breakpoints.step_into(),
+                StepRequest::Over => breakpoints.step_over(emulator),
+                StepRequest::Out => breakpoints.step_out(emulator),
+            }
+            *self.running.borrow_mut() = true;
+        }
+    }
+
+    /// Replaces the watchpoints of the `Emulator` by the ones set in the
+    /// debugger, if they changed.
+    pub fn handle_watchpoints(&self, emulator: &mut Emulator) {
+        if let Some(watchpoints) = self.watchpoints.borrow_mut().take() {
+            emulator.set_watchpoints(watchpoints);
+        }
+    }
+
+    /// Pauses the `Emulator` on the instruction a breakpoint points at, or once
+    /// the requested step completes, and opens the debugger view.
+    pub fn handle_breakpoints(&self, emulator: &Emulator) {
+        let stop = self.breakpoints.borrow_mut().check(emulator);
+        if let Some(stop) = stop {
+            if let Stop::Breakpoint(index) = stop {
+                let breakpoint = self.breakpoints.borrow().breakpoints()[index];
+                console::log_1(&format!("Breakpoint hit: {}", breakpoint).into());
+            }
+            *self.running.borrow_mut() = false;
+            self.effect(Effect::ShowDebugger);
+        }
+    }
+
+    /// Moves the `Emulator` back in time, as requested from the debugger or
+    /// while the rewind key is held, and returns whether it moved. Rewinding
+    /// to a breakpoint pauses the `Emulator` and opens the debugger view.
+    pub fn handle_rewind(&self, emulator: &mut Emulator, rewind: &mut Rewind) -> bool {
+        if let Some(request) = self.rewind_request.borrow_mut().take() {
+            *self.running.borrow_mut() = false;
+            match request {
+                RewindRequest::StepBack => rewind.step_back(emulator),
+                RewindRequest::ToBreakpoint => {
+                    let breakpoints = self.breakpoints.borrow();
+                    if !rewind.rewind_until(emulator, |emulator| breakpoints.hits(emulator)) {
+                        console::log_1(&"No breakpoint hit in the recorded history.".into());
+                    }
+                    self.effect(Effect::ShowDebugger);
+                    true
+                }
+            }
+        } else if *self.rewinding.borrow() {
+            for _ in 0..REWIND_SPEED {
+                if !rewind.step_back(emulator) {
+                    break;
+                }
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Hotswaps the `Emulator` with the snapshot provided by the user in JSON
+    /// format.
+    pub fn handle_snapshot_hotswap(
+        &self,
+        emulator: &mut Emulator,
+        rewind: &mut Rewind,
+        session: &mut Option<Session>,
+    ) {
+        if let Some(snapshot) = self.load_snapshot.borrow_mut().take() {
+            let rom = self.rom.borrow();
+            let loaded = savestate::migrate(&snapshot, &rom)
+                .and_then(|state| savestate::load(emulator, &rom, &state));
+            match loaded {
+                Ok(()) => {
+                    self.stop_movie(session);
+                    rewind.clear();
+                }
+                Err(error) => console::log_1(&error.to_string().into()),
+            }
+        }
+    }
+
+    /// Saves the state of the `Emulator` in the selected slot of the browser
+    /// storage, or restores it from there once read.
+    pub fn handle_slots(
+        &self,
+        emulator: &mut Emulator,
+        rewind: &mut Rewind,
+        session: &mut Option<Session>,
+    ) {
+        let slot = *self.slot.borrow();
+        let rom = self.rom.borrow();
+        match self.slot_request.borrow_mut().take() {
+            Some(SlotRequest::Save) => {
+                self.effect(Effect::SaveState {
+                    rom: rom.clone(),
+                    slot,
+                    state: savestate::save(emulator, &rom),
+                });
+                console::log_1(&format!("Saved state in slot {}.", slot).into());
+            }
+            Some(SlotRequest::Load) => self.effect(Effect::LoadSlot {
+                rom: rom.clone(),
+                slot,
+            }),
+            None => {}
+        }
+        if let Some(state) = self.slot_state.take() {
+            match savestate::load(emulator, &rom, &state) {
+                Ok(()) => {
+                    self.stop_movie(session);
+                    rewind.clear();
+                }
+                Err(error) => console::log_1(&error.to_string().into()),
+            }
+        }
+    }
+
+    /// Downloads the save state of the `Emulator` when requested from the
+    /// debugger.
+    pub fn handle_download(&self, emulator: &Emulator) {
+        if self.download_request.replace(false) {
+            self.effect(Effect::Download {
+                name: "chiphuit.state".to_string(),
+                content: savestate::save(emulator, &self.rom.borrow()),
+            });
+        }
+    }
+
+    /// Downloads the trace of the instructions kept in the history of the
+    /// `Emulator` when requested from the debugger.
+    pub fn handle_trace(&self, rewind: &Rewind) {
+        if self.trace_request.replace(false) {
+            self.effect(Effect::Download {
+                name: "chiphuit.trace.txt".to_string(),
+                content: rewind.trace().into_bytes(),
+            });
+        }
+    }
+
+    /// Copies the `Emulator` to the clipboard in JSON format when requested
+    /// from the debugger.
+    pub fn handle_copy(&self, emulator: &Emulator) {
+        if self.copy_request.replace(false) {
+            self.effect(Effect::Clipboard(serde_json::to_string(emulator).unwrap()));
+        }
+    }
+
+    /// Writes the values typed in the debugger into the `Emulator`, and
+    /// returns whether there were any.
+    pub fn handle_edits(&self, emulator: &mut Emulator) -> bool {
+        let edits = self.edits.take();
+        for edit in &edits {
+            match edit {
+                Edit::Registers(registers) => {
+                    for (index, &value) in registers.iter().enumerate() {
+                        emulator.set_register(index, value);
+                    }
+                }
+                Edit::IndexRegister(value) => emulator.set_index_register(*value),
+                Edit::ProgramCounter(address) => emulator.set_program_counter(*address),
+                Edit::DelayTimer(value) => emulator.set_delay_timer(*value),
+                Edit::SoundTimer(value) => emulator.sound_timer = *value,
+                Edit::StackPointer(stack_pointer) => emulator.set_stack_pointer(*stack_pointer),
+                Edit::Stack(stack) => {
+                    for (index, &address) in stack.iter().enumerate() {
+                        emulator.set_stack(index, address);
+                    }
+                }
+                Edit::Memory(bytes) => {
+                    let address = self.memory_cursor(emulator);
+                    if emulator.set_memory(address as usize, bytes) {
+                        *self.memory_address.borrow_mut() =
+                            address.wrapping_add(bytes.len() as u16);
+                    } else {
+                        console::log_1(&"The bytes don't fit in memory.".into());
+                    }
+                }
+            }
+        }
+        !edits.is_empty()
+    }
+
+    /// Moves the memory editor as requested from the debugger.
+    pub fn handle_memory_request(&self, emulator: &Emulator) {
+        let request = match self.memory_request.take() {
+            Some(request) => request,
+            None => return,
+        };
+        let start = self.memory_cursor(emulator) as usize + 1;
+        let mut address = self.memory_address.borrow_mut();
+        match request {
+            MemoryRequest::Goto(target) => *address = target,
+            MemoryRequest::Search(bytes) => match search(emulator.memory(), &bytes, start) {
+                Some(found) => *address = found as u16,
+                None => console::log_1(&"Bytes not found in memory.".into()),
+            },
+        }
+    }
+
+    /// Returns the address of the memory editor, or the pointer it follows.
+    pub fn memory_cursor(&self, emulator: &Emulator) -> u16 {
+        match *self.memory_follow.borrow() {
+            Follow::Address => *self.memory_address.borrow(),
+            Follow::ProgramCounter => emulator.program_counter(),
+            Follow::IndexRegister => emulator.index_register(),
+        }
+    }
+
+    /// Returns the address the disassembly is centered on: the frame of the
+    /// call stack chosen while the `Emulator` hasn't moved since, or its
+    /// program counter.
+    pub fn disassembly_address(&self, emulator: &Emulator) -> u16 {
+        match *self.disassembly_focus.borrow() {
+            Some((program_counter, address)) if program_counter == emulator.program_counter() => {
+                address
+            }
+            _ => emulator.program_counter(),
+        }
+    }
+}
+
+/// Returns the address of the first occurrence of `bytes` in `memory` from
+/// `start` on, wrapping around to the beginning of `memory`.
+fn search(memory: &[u8], bytes: &[u8], start: usize) -> Option<usize> {
+    if bytes.is_empty() || bytes.len() > memory.len() {
+        return None;
+    }
+    let start = start % memory.len();
+    (start..memory.len())
+        .chain(0..start)
+        .find(|&address| memory.get(address..address + bytes.len()) == Some(bytes))
+}
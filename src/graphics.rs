@@ -1,14 +1,23 @@
 //! # A module to display the screen of our `Emulator` with the [Canvas API](https://developer.mozilla.org/en-US/docs/Web/API/Canvas_API).
 
-use crate::utils::{append_to_body, document};
+use crate::utils::document;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::Clamped;
 use wasm_bindgen::JsCast;
-use web_sys::{CanvasRenderingContext2d, ImageData};
+use web_sys::{CanvasRenderingContext2d, Element, ImageData, OffscreenCanvasRenderingContext2d};
 
 pub const WIDTH: u32 = 64;
 pub const HEIGHT: u32 = 32;
 
+/// RGBA colors of the pixels according to their lit bitplanes: none, the
+/// first one, the second one (XO-CHIP), or both.
+pub const PALETTE: [[u8; 4]; 4] = [
+    [0, 0, 0, 0],
+    [255, 255, 255, 255],
+    [139, 0, 139, 255],
+    [85, 85, 85, 255],
+];
+
 pub fn request_animation_frame(f: &Closure<dyn FnMut()>) -> i32 {
     web_sys::window()
         .expect("should have a window.")
@@ -16,22 +25,24 @@ pub fn request_animation_frame(f: &Closure<dyn FnMut()>) -> i32 {
         .expect("should register `requestAnimationFrame` OK")
 }
 
-/// Set the canvas in the browser that will be be used to render the chip8
+/// Set the canvas in `container` that will be be used to render the chip8
 /// Emulator screen.
-pub fn set_canvas() -> web_sys::CanvasRenderingContext2d {
+pub fn set_canvas(container: &Element) -> web_sys::HtmlCanvasElement {
     let canvas: web_sys::HtmlCanvasElement = document()
         .create_element("canvas")
         .expect("Should have an element named canvas.")
         .dyn_into::<web_sys::HtmlCanvasElement>()
         .expect("Should have a Canvas element.");
 
-    canvas.set_id("canvas");
-
     canvas.set_width(WIDTH);
     canvas.set_height(HEIGHT);
 
-    append_to_body(&canvas);
+    container.append_child(&canvas).unwrap();
+    canvas
+}
 
+/// Returns the context to draw on `canvas` from the page.
+pub fn context(canvas: &web_sys::HtmlCanvasElement) -> CanvasRenderingContext2d {
     canvas
         .get_context("2d")
         .expect("Should have a 2D Context.")
@@ -42,33 +53,62 @@ pub fn set_canvas() -> web_sys::CanvasRenderingContext2d {
 
 /// Render the chip8 Emulator screen in the browser using the Canvas API.
 ///
-/// Since every pixel of a chip8 `Emulator` screen (64x32)
-/// has only 2 possible values (turned off or turned on), these are represented
-/// in memory by bools. This function iterates over the pixel states of the
+/// Every pixel of a chip8 `Emulator` screen (64x32, or 128x64 in SUPER-CHIP
+/// high resolution mode) is represented in memory by a byte whose 2 lowest
+/// bits are the states of the XO-CHIP bitplanes, giving 4 possible colors
+/// picked from the `PALETTE`. This function resizes the Canvas to the
+/// `Emulator` resolution if needed, iterates over the pixel states of the
 /// `Emulator` and draws pixels on the Canvas.
 ///
 /// # Examples
 ///
 /// Basic usage:
 ///
-/// ```
-/// let context = set_canvas();
-/// let screen = [true, 64 * 32];
+/// ```ignore
+/// let context = context(&set_canvas(&container));
+/// let screen = vec![1; 64 * 32];
 ///
 /// // turns all the pixels of the Emulator screen on.
-/// draw_screen(&context, screen);
+/// draw_screen(&context, &screen, 64, 32);
 /// ```
-pub fn draw_screen(context: &CanvasRenderingContext2d, screen: [bool; 64 * 32]) {
+pub fn draw_screen(context: &CanvasRenderingContext2d, screen: &[u8], width: u32, height: u32) {
+    let canvas = context.canvas().expect("should have a canvas.");
+    if canvas.width() != width || canvas.height() != height {
+        canvas.set_width(width);
+        canvas.set_height(height);
+    }
+
+    context
+        .put_image_data(&frame(screen, width, height), 0.0, 0.0)
+        .unwrap();
+}
+
+/// Render the chip8 Emulator screen from a worker, on a canvas transferred
+/// by the page, as `draw_screen` does.
+pub fn draw_offscreen(
+    context: &OffscreenCanvasRenderingContext2d,
+    screen: &[u8],
+    width: u32,
+    height: u32,
+) {
+    let canvas = context.canvas();
+    if canvas.width() != width || canvas.height() != height {
+        canvas.set_width(width);
+        canvas.set_height(height);
+    }
+
+    context
+        .put_image_data(&frame(screen, width, height), 0.0, 0.0)
+        .unwrap();
+}
+
+/// Returns the pixels of the `Emulator` screen in the colors of the
+/// `PALETTE`.
+fn frame(screen: &[u8], width: u32, height: u32) -> ImageData {
     let rgba_screen: Vec<u8> = screen
         .iter()
-        .flat_map(|x| match x {
-            false => [0u8; 4],
-            true => [255u8; 4],
-        })
+        .flat_map(|&planes| PALETTE[(planes & 0b11) as usize])
         .collect();
 
-    let frame =
-        ImageData::new_with_u8_clamped_array_and_sh(Clamped(&rgba_screen), WIDTH, HEIGHT).unwrap();
-
-    context.put_image_data(&frame, 0.0, 0.0).unwrap();
+    ImageData::new_with_u8_clamped_array_and_sh(Clamped(&rgba_screen), width, height).unwrap()
 }
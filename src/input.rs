@@ -2,27 +2,31 @@
 //! - The keypad
 //! - The breakpoint
 //! - The file input to handle the ROM
+//! - The rewind key
+//! - The quick save and quick load keys
+use crate::assembler::SourceMap;
+use crate::frontend::SlotRequest;
 use crate::utils::{
-    append_element_to_another, append_to_body, change_view, document, read_user_file,
-    set_callback_to_button, set_callback_to_key,
+    change_view, create_file_input, document, find, read_user_file, set_callback_to_button,
+    set_callback_to_key,
 };
 use js_sys::JsString;
 use std::cell::RefCell;
 use std::rc::Rc;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
-use web_sys::{Event, FileReader, HtmlInputElement, HtmlLabelElement, Node};
+use web_sys::{Element, Event, FileReader, HtmlInputElement, Node};
 
-/// Set the keypad in the UI.
-pub fn set_keypad(emulator_keypad: &Rc<RefCell<[bool; 16]>>) {
+/// Set the keypad in `container`, also played with the keyboard while the
+/// `container` has the focus.
+pub fn set_keypad(container: &Element, emulator_keypad: &Rc<RefCell<[bool; 16]>>) {
     let keypad = document()
         .create_element("keypad")
         .expect("should have a keypad.");
 
-    keypad.set_id("keypad");
     keypad.set_class_name("keypad-base");
 
-    append_to_body(&keypad);
+    container.append_child(&keypad).unwrap();
 
     for (index, &key) in [
         "1", "2", "3", "4", "Q", "W", "E", "R", "A", "S", "D", "F", "Z", "X", "C", "V",
@@ -31,7 +35,6 @@ pub fn set_keypad(emulator_keypad: &Rc<RefCell<[bool; 16]>>) {
     .enumerate()
     {
         let keypad_key = document().create_element("div").unwrap();
-        keypad_key.set_id(key);
         keypad_key.set_inner_html(key);
         keypad_key.set_class_name("key");
         keypad
@@ -43,21 +46,22 @@ pub fn set_keypad(emulator_keypad: &Rc<RefCell<[bool; 16]>>) {
         set_callback_to_button(false, &keypad_key, emulator_keypad, index);
 
         // Handle keyboard events
-        set_callback_to_key(true, key.to_string(), emulator_keypad, index);
-        set_callback_to_key(false, key.to_string(), emulator_keypad, index);
+        set_callback_to_key(container, true, key.to_string(), emulator_keypad, index);
+        set_callback_to_key(container, false, key.to_string(), emulator_keypad, index);
     }
 }
 
-/// Set the breakpoint button in the UI.
-pub fn set_breakpoint(emulator_breakpoint: &Rc<RefCell<bool>>) {
+/// Set the breakpoint button in the keypad of `container`.
+pub fn set_breakpoint(container: &Element, emulator_breakpoint: &Rc<RefCell<bool>>) {
     let breakpoint = document()
         .create_element("breakpoint")
         .expect("should have a breakpoint.");
 
-    breakpoint.set_id("breakpoint");
     breakpoint.set_class_name("breakpoint");
     breakpoint.set_inner_html("⏯");
-    append_element_to_another(&breakpoint, "keypad");
+    find(container, "keypad-base")
+        .append_child(&breakpoint)
+        .unwrap();
 
     let breakpoint_clone = Rc::clone(emulator_breakpoint);
     let closure = Closure::wrap(Box::new(move |_event: web_sys::MouseEvent| {
@@ -70,19 +74,18 @@ pub fn set_breakpoint(emulator_breakpoint: &Rc<RefCell<bool>>) {
     closure.forget()
 }
 
-/// Set the debug button in the UI.
-pub fn set_debug() {
+/// Set the debug button in the keypad of `container`.
+pub fn set_debug(container: &Element) {
     let debug = document()
         .create_element("debug")
         .expect("should have a debug.");
 
-    debug.set_id("debug");
     debug.set_class_name("debug");
     debug.set_inner_html("⚙");
 
-    append_element_to_another(&debug, "keypad");
+    find(container, "keypad-base").append_child(&debug).unwrap();
 
-    let closure = change_view();
+    let closure = change_view(container);
 
     debug
         .add_event_listener_with_callback("click", closure.as_ref().unchecked_ref())
@@ -90,34 +93,61 @@ pub fn set_debug() {
     closure.forget()
 }
 
-/// Set button for rom selection.
-pub fn set_file_upload_button() {
-    let fileinput: HtmlInputElement = document()
-        .create_element("input")
-        .unwrap()
-        .dyn_into::<HtmlInputElement>()
-        .unwrap();
+/// Rewind the `Emulator` while `Backspace` is held in `container`.
+pub fn set_rewind_key(container: &Element, rewinding: &Rc<RefCell<bool>>) {
+    for (event, pressed) in [("keydown", true), ("keyup", false)].iter() {
+        let rewinding_clone = Rc::clone(rewinding);
+        let pressed = *pressed;
+        let closure = Closure::wrap(Box::new(move |event: web_sys::KeyboardEvent| {
+            if event.key() == "Backspace" {
+                *rewinding_clone.borrow_mut() = pressed;
+            }
+        }) as Box<dyn FnMut(_)>);
+
+        container
+            .add_event_listener_with_callback(event, closure.as_ref().unchecked_ref())
+            .unwrap();
+        closure.forget();
+    }
+}
 
-    fileinput.set_id("file-upload");
-    fileinput.set_type("file");
-    append_to_body(&fileinput);
+/// Quick save the state of the `Emulator` with `F2`, and quick load it with
+/// `F4`, pressed in `container`.
+pub fn set_slot_keys(container: &Element, slot_request: &Rc<RefCell<Option<SlotRequest>>>) {
+    let slot_request = Rc::clone(slot_request);
+    let closure = Closure::wrap(Box::new(move |event: web_sys::KeyboardEvent| {
+        let request = match event.key().as_str() {
+            "F2" => SlotRequest::Save,
+            "F4" => SlotRequest::Load,
+            _ => return,
+        };
+        event.prevent_default();
+        *slot_request.borrow_mut() = Some(request);
+    }) as Box<dyn FnMut(_)>);
 
-    let label: HtmlLabelElement = document()
-        .create_element("label")
-        .unwrap()
-        .dyn_into::<HtmlLabelElement>()
+    container
+        .add_event_listener_with_callback("keydown", closure.as_ref().unchecked_ref())
         .unwrap();
+    closure.forget();
+}
 
-    label.set_html_for("file-upload");
-    label.set_inner_text("Select ROM");
+/// Set button for rom selection in the keypad of `container`.
+pub fn set_file_upload_button(container: &Element) {
+    let label = create_file_input("Select ROM");
     label.set_class_name("file-upload");
-    append_element_to_another(&label, "keypad");
+    find(container, "keypad-base").append_child(&label).unwrap();
 }
 
-/// Closure to load user input ROM in the Emulator.
-pub fn load_user_rom(rom_buffer: &Rc<RefCell<Vec<u8>>>) -> Closure<dyn FnMut(Event)> {
+/// Closure to load user input ROM in the Emulator, forgetting the labels of
+/// the previous one.
+pub fn load_user_rom(
+    rom_buffer: &Rc<RefCell<Vec<u8>>>,
+    symbols: &Rc<RefCell<Option<SourceMap>>>,
+) -> Closure<dyn FnMut(Event)> {
     let rom = Rc::clone(rom_buffer);
+    let symbols = Rc::clone(symbols);
     Closure::wrap(Box::new(move |event: Event| {
+        *symbols.borrow_mut() = None;
         *rom.borrow_mut() = event
             .target()
             .unwrap()
@@ -133,20 +163,39 @@ pub fn load_user_rom(rom_buffer: &Rc<RefCell<Vec<u8>>>) -> Closure<dyn FnMut(Eve
     }))
 }
 
-/// Set the button to allow the user to supply a ROM to the `Emulator`.
-pub fn set_file_reader(rom_buffer: &Rc<RefCell<Vec<u8>>>) {
-    let file_input = document()
-        .get_element_by_id("file-upload")
+/// Set the button of `container` to allow the user to supply a ROM to the
+/// `Emulator`, named after its file in the library.
+pub fn set_file_reader(
+    container: &Element,
+    rom_buffer: &Rc<RefCell<Vec<u8>>>,
+    rom_name: &Rc<RefCell<Option<String>>>,
+    symbols: &Rc<RefCell<Option<SourceMap>>>,
+) {
+    let file_input = find(container, "file-upload")
+        .query_selector("input")
+        .unwrap()
         .unwrap()
         .dyn_into::<HtmlInputElement>()
         .unwrap();
 
     let file_reader = FileReader::new().unwrap().dyn_into::<FileReader>().unwrap();
 
-    let handle_load_event = load_user_rom(rom_buffer);
+    let handle_load_event = load_user_rom(rom_buffer, symbols);
     file_reader.set_onloadend(Some(handle_load_event.as_ref().unchecked_ref()));
     handle_load_event.forget();
 
+    let rom_name = Rc::clone(rom_name);
+    let file_input_clone = file_input.clone();
+    let handle_name_event = Closure::wrap(Box::new(move |_event: Event| {
+        if let Some(file) = file_input_clone.files().and_then(|files| files.get(0)) {
+            *rom_name.borrow_mut() = Some(file.name());
+        }
+    }) as Box<dyn FnMut(_)>);
+    file_input
+        .add_event_listener_with_callback("change", handle_name_event.as_ref().unchecked_ref())
+        .unwrap();
+    handle_name_event.forget();
+
     let handle_read_event = read_user_file(file_reader);
     file_input
         .add_event_listener_with_callback("change", handle_read_event.as_ref().unchecked_ref())
@@ -3,6 +3,15 @@
 //! The `chiphuit` crate provides a [Chip8](https://en.wikipedia.org/wiki/CHIP-8)
 //! emulator able to run in any web browser capable of executing WASM.
 //!
+//! ## Core
+//!
+//! The emulation itself lives in the [`cpu`] module, which has no browser
+//! dependency and can be embedded in native tools by disabling the default
+//! `web` feature. The browser UI is a frontend that drives this core.
+//!
+//! Other pages can embed the emulator through the `Chip8` class of the
+//! [`bindings`] module, exported to JavaScript by the `bindings` feature
+//! without the browser UI.
 //!
 //! ## Rendering
 //!
@@ -12,27 +21,82 @@
 //! ## Features
 //!
 //! - `chiphuit` runs the ROM supplied by the user through the UI , and allows
-//! hotswapping the ROM at runtime.
+//!   hotswapping the ROM at runtime.
 //!
 //! - `chiphuit` also has a breakpoint feature that allows the user to pause
-//! the `Emulator` at any time.
+//!   the `Emulator` at any time, or on addresses and opcode patterns under
+//!   conditions, and to step into, over and out of instructions. Watchpoints
+//!   pause it when an instruction reads, writes or executes a range of memory.
 //!
 //! - `chiphuit` displays the `Emulator` variables next to the screen in order
-//! to see its state at runtime.
+//!   to see its state at runtime, along with its call stack, a disassembly of
+//!   the code around the program counter or a frame of the call stack, and a
+//!   hex editor of its memory, highlighting the bytes accessed during the last
+//!   frame. The variables can be edited while the `Emulator` is paused. Its
+//!   recent history is kept to step back, to rewind to the previous
+//!   breakpoint, or to rewind the game while `Backspace` is held, and can be
+//!   downloaded as a trace of the instructions. A movie of the user inputs
+//!   can be recorded from the start of the ROM, and replayed bit-for-bit.
+//!
+//! - `chiphuit` keeps the ROMs played and the quick save slots of each ROM in
+//!   the browser storage, so that a session can be resumed after reloading
+//!   the page. Save states and movies can also be downloaded.
+//!
+//! - `chiphuit` runs a configurable number of instructions per frame, at 60
+//!   frames per second whatever the refresh rate of the display, so that the
+//!   timers and the sound tick at 60 Hz. Drawing can be limited to one sprite
+//!   per frame with the `vblank_wait` quirk.
+//!
+//! - `chiphuit` mounts an emulator in every element of the page with a
+//!   `data-chiphuit` attribute, or in the whole page, so that several of them
+//!   can run side by side. With a `data-worker` attribute, the `Emulator`
+//!   runs in a Web Worker drawing on an `OffscreenCanvas`, off the main thread
+//!   of the page.
 //!
 //! - `chiphuit` provides 2 ways to handle user input: A player can click the
-//! virtual keypad on the UI to play, or use its own keyboard.
+//!   virtual keypad on the UI to play, or use its own keyboard.
 
+#[cfg(feature = "web")]
 use std::cell::RefCell;
+#[cfg(feature = "web")]
 use std::rc::Rc;
+#[cfg(feature = "web")]
 use wasm_bindgen::prelude::*;
+#[cfg(feature = "web")]
+use wasm_bindgen::JsCast;
 
+pub mod assembler;
+#[cfg(feature = "web")]
 mod audio;
-mod cpu;
+#[cfg(feature = "bindings")]
+pub mod bindings;
+pub mod breakpoint;
+pub mod cpu;
+#[cfg(feature = "web")]
 mod debugger;
+pub mod disassembler;
+pub mod error;
+#[cfg(feature = "web")]
+mod frontend;
+#[cfg(feature = "web")]
 mod graphics;
+#[cfg(feature = "web")]
 mod input;
+pub mod movie;
+pub mod quirks;
+pub mod random;
+pub mod rewind;
+#[cfg(feature = "web")]
+mod runner;
+pub mod savestate;
+pub mod scheduler;
+#[cfg(feature = "web")]
+mod storage;
+#[cfg(feature = "web")]
 mod utils;
+pub mod watchpoint;
+#[cfg(feature = "web")]
+mod worker;
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
 // allocator.
 #[cfg(feature = "wee_alloc")]
@@ -40,52 +104,136 @@ mod utils;
 
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
+#[cfg(feature = "web")]
 #[cfg_attr(target_arch = "wasm32", wasm_bindgen(start))]
-/// Main function that initializes the emulator, the keypad, and the screen
-/// before inserting the ROM in the Emulator to play.
+/// Main function that mounts an emulator in every element of the page with a
+/// `data-chiphuit` attribute, or in the body of the page if there are none.
+/// In a worker, runs the `Emulator` of the page instead.
 pub fn main_wasm() -> Result<(), JsValue> {
-    utils::set_document();
-    let canvas = graphics::set_canvas();
+    if let Ok(scope) = js_sys::global().dyn_into::<web_sys::DedicatedWorkerGlobalScope>() {
+        return worker::run(scope);
+    }
+    utils::set_stylesheet();
+    let containers = utils::document().query_selector_all("[data-chiphuit]")?;
+    if containers.length() == 0 {
+        utils::set_document();
+        return mount(utils::document().body().unwrap().into());
+    }
+    for index in 0..containers.length() {
+        let container = containers
+            .get(index)
+            .unwrap()
+            .dyn_into::<web_sys::Element>()?;
+        mount(container)?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "web")]
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+/// Initializes an emulator, its keypad, and its screen in `container`, before
+/// inserting the ROM in the Emulator to play. The keyboard plays the emulator
+/// whose `container` has the focus. The `Emulator` runs in a worker if the
+/// `container` has a `data-worker` attribute.
+pub fn mount(container: web_sys::Element) -> Result<(), JsValue> {
+    container.class_list().add_1("chiphuit")?;
+    if container.tag_name() != "BODY" {
+        container.set_attribute("tabindex", "0")?;
+    }
+    let canvas = graphics::set_canvas(&container);
     let audio_context = audio::FmOsc::new().unwrap();
 
-    let mut emulator = cpu::Emulator::new();
-    emulator.load_font();
+    let frontend = frontend::Frontend::new();
+
+    let debugger = debugger::Debugger::new(&container);
+    debugger.set_debugger(&frontend);
 
-    let debugger = debugger::Debugger::new();
-    debugger.set_debugger(&emulator);
+    input::set_keypad(&container, &frontend.keypad);
+    input::set_breakpoint(&container, &frontend.running);
+    input::set_file_upload_button(&container);
+    input::set_file_reader(
+        &container,
+        &frontend.rom_buffer,
+        &frontend.rom_name,
+        &debugger.symbols,
+    );
+    frontend.restore_last_rom();
+    input::set_debug(&container);
+    input::set_rewind_key(&container, &frontend.rewinding);
+    input::set_slot_keys(&container, &frontend.slot_request);
+
+    if let Some(script) = container.get_attribute("data-worker") {
+        let script = match script.as_str() {
+            "" => worker::DEFAULT_SCRIPT,
+            script => script,
+        };
+        match worker::Remote::spawn(script, canvas.clone()) {
+            Ok(remote) => {
+                run_in_worker(container, remote, frontend, debugger, audio_context);
+                return Ok(());
+            }
+            Err(_) => web_sys::console::log_1(&"Workers unavailable, running on the page.".into()),
+        }
+    }
+    run_on_page(container, canvas, frontend, debugger, audio_context);
+    Ok(())
+}
 
-    input::set_keypad(&emulator.keypad);
-    input::set_breakpoint(&emulator.running);
-    input::set_file_upload_button();
-    input::set_file_reader(&emulator.rom_buffer);
-    input::set_debug();
+#[cfg(feature = "web")]
+/// Runs the `Emulator` on the page, at each animation frame.
+fn run_on_page(
+    container: web_sys::Element,
+    canvas: web_sys::HtmlCanvasElement,
+    frontend: frontend::Frontend,
+    debugger: debugger::Debugger,
+    mut audio_context: audio::FmOsc,
+) {
+    let context = graphics::context(&canvas);
+    let mut runner = runner::Runner::new();
 
     let f = Rc::new(RefCell::new(None));
     let g = f.clone();
-    let t1 = Rc::new(RefCell::new(None));
-    *t1.borrow_mut() = Some(Closure::wrap(Box::new(move || {
-        graphics::request_animation_frame(f.borrow().as_ref().unwrap());
-    }) as Box<dyn FnMut()>));
 
     // EVENT LOOP
     *g.borrow_mut() = Some(Closure::wrap(Box::new(move || {
-        utils::set_timeout(t1.borrow().as_ref().unwrap(), 30);
-
-        if *emulator.running.borrow() {
-            for _ in 0..10 {
-                emulator.cycle();
-                emulator.update_emulator_state(&debugger.element.rows());
-                debugger.get_serialized_current_snapshot(&emulator);
-                debugger.handle_tracing(&emulator);
-                audio::sound(&mut emulator, &audio_context);
-            }
-            graphics::draw_screen(&canvas, emulator.screen);
-            emulator.handle_snapshot_hotswap();
-            emulator.handle_rom_hotswap();
+        graphics::request_animation_frame(f.borrow().as_ref().unwrap());
+        let moved = runner.tick(&frontend, js_sys::Date::now());
+        // Only the last state of the frame is painted.
+        debugger.update_emulator_state(&runner.emulator, &frontend);
+        if moved {
+            let emulator = &runner.emulator;
+            audio::sound(&audio::Buzzer::new(emulator), &mut audio_context);
+            graphics::draw_screen(
+                &context,
+                &emulator.screen,
+                emulator.width() as u32,
+                emulator.height() as u32,
+            );
         }
+        frontend.apply_effects(&container);
     }) as Box<dyn FnMut()>));
 
     graphics::request_animation_frame(g.borrow().as_ref().unwrap());
+}
 
-    Ok(())
+#[cfg(feature = "web")]
+/// Keeps the page in sync with the `Emulator` running in a worker, at each
+/// animation frame.
+fn run_in_worker(
+    container: web_sys::Element,
+    mut remote: worker::Remote,
+    frontend: frontend::Frontend,
+    debugger: debugger::Debugger,
+    mut audio_context: audio::FmOsc,
+) {
+    let f = Rc::new(RefCell::new(None));
+    let g = f.clone();
+
+    *g.borrow_mut() = Some(Closure::wrap(Box::new(move || {
+        graphics::request_animation_frame(f.borrow().as_ref().unwrap());
+        remote.sync(&frontend, &debugger, &mut audio_context);
+        frontend.apply_effects(&container);
+    }) as Box<dyn FnMut()>));
+
+    graphics::request_animation_frame(g.borrow().as_ref().unwrap());
 }
@@ -0,0 +1,207 @@
+//! # A module to record the inputs of a session, and to replay them.
+//!
+//! A `Movie` holds everything fed to the `Emulator` during a session besides
+//! its ROM: the seed of the random number generator of `CXNN`, the `Quirks`,
+//! the changes of the keypad and the vertical blank interrupts, each keyed by
+//! the number of cycles run before it. Replaying a `Movie` on the same ROM
+//! reproduces the session bit-for-bit.
+//!
+//! A `Recorder` is told about every cycle and vertical blank interrupt of the
+//! frontend, while a `Player` feeds them back to the `Emulator` in its place.
+//! Movies are saved as JSON.
+use crate::cpu::Emulator;
+use crate::quirks::Quirks;
+use serde::{Deserialize, Serialize};
+
+/// An input fed to the `Emulator` between two cycles.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Event {
+    /// The new state of the keypad, bit `n` being set when key `n` is
+    /// pressed.
+    Keypad(u16),
+    /// A call to `Emulator::vblank`.
+    VBlank,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Input {
+    /// Cycles run before the event.
+    pub cycle: u64,
+    pub event: Event,
+}
+
+/// The recorded inputs of a session.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct Movie {
+    /// Seed of the random number generator of `CXNN`.
+    pub seed: u64,
+    pub quirks: Quirks,
+    /// Cycles run during the session.
+    pub cycles: u64,
+    pub inputs: Vec<Input>,
+}
+
+/// Records a `Movie` of an `Emulator`.
+pub struct Recorder {
+    movie: Movie,
+    keypad: u16,
+}
+
+impl Recorder {
+    /// Starts recording `emulator`, which should have just loaded its ROM,
+    /// and seeds its random number generator with `seed`.
+    pub fn new(emulator: &mut Emulator, seed: u64) -> Recorder {
+        emulator.seed_rng(seed);
+        Recorder {
+            movie: Movie {
+                seed,
+                quirks: emulator.quirks(),
+                cycles: 0,
+                inputs: Vec::new(),
+            },
+            keypad: 0,
+        }
+    }
+
+    /// Records the keypad of `emulator` if it changed, to be called right
+    /// before each `Emulator::cycle`.
+    pub fn record(&mut self, emulator: &Emulator) {
+        let keypad = keypad_bits(&emulator.keypad);
+        if keypad != self.keypad {
+            self.keypad = keypad;
+            self.push(Event::Keypad(keypad));
+        }
+        self.movie.cycles += 1;
+    }
+
+    /// Records a vertical blank interrupt, to be called along with
+    /// `Emulator::vblank`.
+    pub fn vblank(&mut self) {
+        self.push(Event::VBlank);
+    }
+
+    /// Stops recording, and returns the `Movie`.
+    pub fn finish(self) -> Movie {
+        self.movie
+    }
+
+    fn push(&mut self, event: Event) {
+        self.movie.inputs.push(Input {
+            cycle: self.movie.cycles,
+            event,
+        });
+    }
+}
+
+/// Replays a `Movie` on an `Emulator`.
+pub struct Player {
+    movie: Movie,
+    cycle: u64,
+    next: usize,
+}
+
+impl Player {
+    /// Starts replaying `movie` on `emulator`, which should have just loaded
+    /// the ROM of the movie, and seeds its random number generator.
+    pub fn new(movie: Movie, emulator: &mut Emulator) -> Player {
+        emulator.seed_rng(movie.seed);
+        emulator.set_quirks(movie.quirks);
+        emulator.keypad = [false; 16];
+        Player {
+            movie,
+            cycle: 0,
+            next: 0,
+        }
+    }
+
+    /// Feeds the inputs due before the next cycle to `emulator`, to be called
+    /// right before each `Emulator::cycle`. Returns false once the movie is
+    /// over, and no cycle should run anymore.
+    pub fn play(&mut self, emulator: &mut Emulator) -> bool {
+        while let Some(input) = self.movie.inputs.get(self.next) {
+            if input.cycle > self.cycle {
+                break;
+            }
+            match input.event {
+                Event::Keypad(keypad) => {
+                    for (key, pressed) in emulator.keypad.iter_mut().enumerate() {
+                        *pressed = keypad >> key & 1 == 1;
+                    }
+                }
+                Event::VBlank => emulator.vblank(),
+            }
+            self.next += 1;
+        }
+
+        if self.cycle < self.movie.cycles {
+            self.cycle += 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+fn keypad_bits(keypad: &[bool; 16]) -> u16 {
+    keypad
+        .iter()
+        .enumerate()
+        .fold(0, |bits, (key, &pressed)| bits | (pressed as u16) << key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assembled_emulator;
+
+    /// A ROM drawing random sprites, counting the iterations key 2 is held
+    /// in `v3`, and reading the delay timer ticked by the vertical blank
+    /// interrupts.
+    const SOURCE: &str = "
+        : main
+            i := buffer
+            loop
+                v1 := random 0xFF
+                v2 := 2
+                if v2 key then v3 += 1
+                save v3
+                sprite v1 v3 1
+                vf := delay
+                if vf == 0 then vf := 3
+                delay := vf
+            again
+        : buffer
+            0 0 0 0
+    ";
+
+    #[test]
+    fn replays_a_recorded_session() {
+        let mut recorded = assembled_emulator(SOURCE);
+        let mut recorder = Recorder::new(&mut recorded, 42);
+        for cycle in 0..500 {
+            recorded.keypad[2] = cycle % 70 < 30;
+            recorder.record(&recorded);
+            recorded.cycle().unwrap();
+            if cycle % 10 == 9 {
+                recorded.vblank();
+                recorder.vblank();
+            }
+        }
+        let movie = recorder.finish();
+        assert_eq!(movie.cycles, 500);
+
+        let json = serde_json::to_string(&movie).unwrap();
+        let movie: Movie = serde_json::from_str(&json).unwrap();
+
+        let mut replayed = assembled_emulator(SOURCE);
+        let mut player = Player::new(movie, &mut replayed);
+        while player.play(&mut replayed) {
+            replayed.cycle().unwrap();
+        }
+
+        assert!(replayed.cpu_state() == recorded.cpu_state());
+        assert_eq!(replayed.memory(), recorded.memory());
+        assert_eq!(replayed.screen, recorded.screen);
+        assert_ne!(recorded.registers()[3], 0);
+    }
+}
@@ -0,0 +1,119 @@
+//! # A module to describe the behaviors that differ between chip8 interpreters.
+//!
+//! Some opcodes were implemented differently by the COSMAC VIP interpreter,
+//! CHIP-48 and SUPER-CHIP, and ROMs rely on the behavior of the interpreter
+//! they were written for. The `Emulator` consults its `Quirks` whenever it
+//! processes one of these ambiguous opcodes.
+use serde::{Deserialize, Serialize};
+
+/// How `FX55` and `FX65` modify the index register after accessing memory.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum IndexIncrement {
+    /// I is left unmodified.
+    None,
+    /// I is increased by X, as CHIP-48 did.
+    X,
+    /// I is increased by X + 1, as the COSMAC VIP did.
+    XPlusOne,
+}
+
+/// The set of ambiguous behaviors an `Emulator` should follow.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Quirks {
+    /// `8XY1`, `8XY2` and `8XY3` reset VF to 0.
+    pub logic_resets_vf: bool,
+    /// `8XY6` and `8XYE` shift VY and store the result in VX, instead of
+    /// shifting VX in place.
+    pub shift_uses_vy: bool,
+    /// How `FX55` and `FX65` modify I.
+    pub index_increment: IndexIncrement,
+    /// `BNNN` jumps to XNN plus VX instead of NNN plus V0.
+    pub jump_uses_vx: bool,
+    /// `DXYN` clips sprites at the screen edges instead of wrapping them
+    /// around.
+    pub clip_sprites: bool,
+    /// `DXYN` waits for the vertical blank interrupt before drawing, which
+    /// limits drawing to one sprite per frame.
+    pub vblank_wait: bool,
+}
+
+impl Quirks {
+    /// Behavior of the original COSMAC VIP interpreter.
+    pub const fn cosmac_vip() -> Quirks {
+        Quirks {
+            logic_resets_vf: true,
+            shift_uses_vy: true,
+            index_increment: IndexIncrement::XPlusOne,
+            jump_uses_vx: false,
+            clip_sprites: true,
+            vblank_wait: true,
+        }
+    }
+
+    /// Behavior of the CHIP-48 interpreter for HP-48 calculators.
+    pub const fn chip48() -> Quirks {
+        Quirks {
+            logic_resets_vf: false,
+            shift_uses_vy: false,
+            index_increment: IndexIncrement::X,
+            jump_uses_vx: true,
+            clip_sprites: true,
+            vblank_wait: false,
+        }
+    }
+
+    /// Behavior of the SUPER-CHIP 1.1 interpreter.
+    pub const fn superchip() -> Quirks {
+        Quirks {
+            logic_resets_vf: false,
+            shift_uses_vy: false,
+            index_increment: IndexIncrement::None,
+            jump_uses_vx: true,
+            clip_sprites: true,
+            vblank_wait: false,
+        }
+    }
+
+    /// Behavior of XO-CHIP, as implemented by Octo.
+    pub const fn xo_chip() -> Quirks {
+        Quirks {
+            logic_resets_vf: false,
+            shift_uses_vy: true,
+            index_increment: IndexIncrement::XPlusOne,
+            jump_uses_vx: false,
+            clip_sprites: false,
+            vblank_wait: false,
+        }
+    }
+
+    /// Behavior most modern ROMs and emulators expect.
+    pub const fn modern() -> Quirks {
+        Quirks {
+            logic_resets_vf: false,
+            shift_uses_vy: false,
+            index_increment: IndexIncrement::None,
+            jump_uses_vx: false,
+            clip_sprites: false,
+            vblank_wait: false,
+        }
+    }
+
+    /// Returns the preset named `name`, among `vip`, `chip48`, `schip`,
+    /// `xochip` and `modern`.
+    pub fn from_name(name: &str) -> Option<Quirks> {
+        match name {
+            "vip" => Some(Quirks::cosmac_vip()),
+            "chip48" => Some(Quirks::chip48()),
+            "schip" => Some(Quirks::superchip()),
+            "xochip" => Some(Quirks::xo_chip()),
+            "modern" => Some(Quirks::modern()),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks::modern()
+    }
+}
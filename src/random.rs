@@ -0,0 +1,37 @@
+//! # A module to generate the random numbers of `CXNN`.
+//!
+//! The `Emulator` holds its own `Random` generator instead of relying on the
+//! thread-local one of the system, so that a run can be reproduced from its
+//! seed, and so that the state of the generator is saved in snapshots.
+use serde::{Deserialize, Serialize};
+
+/// A SplitMix64 pseudorandom number generator, any seed being valid.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Random {
+    state: u64,
+}
+
+impl Random {
+    /// Returns a generator seeded with `seed`.
+    pub fn new(seed: u64) -> Random {
+        Random { state: seed }
+    }
+
+    /// Returns a generator seeded from the entropy of the system.
+    pub fn from_entropy() -> Random {
+        Random::new(rand::random())
+    }
+
+    /// Returns the next random byte.
+    pub fn next_u8(&mut self) -> u8 {
+        (self.next_u64() >> 56) as u8
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
@@ -0,0 +1,370 @@
+//! # A module to rewind the `Emulator` to its previous states.
+//!
+//! Instead of saving the whole `Emulator` after every instruction, `Rewind`
+//! keeps a bounded ring buffer of segments: a keyframe holding the complete
+//! state, followed by the deltas of up to `keyframe_interval` instructions.
+//! A delta only holds the small CPU state, the bytes of memory written, and
+//! the pixels changed by its instruction. Going back to a previous state
+//! restores the keyframe before it, then replays the deltas up to it.
+//!
+//! Once `max_keyframes` segments are recorded, the oldest one is dropped.
+use crate::cpu::{CpuState, Emulator, StepOutcome};
+use crate::error::EmulatorError;
+use std::collections::VecDeque;
+
+/// Instructions recorded after each keyframe.
+const KEYFRAME_INTERVAL: usize = 600;
+
+/// Keyframes kept, about 2 minutes of history at 10 instructions per frame.
+const MAX_KEYFRAMES: usize = 120;
+
+/// The complete state of the `Emulator` at the start of a segment.
+struct Keyframe {
+    cpu: CpuState,
+    memory: Vec<u8>,
+    screen: Vec<u8>,
+}
+
+/// Changes of the screen made by an instruction.
+enum ScreenDelta {
+    /// Indexes and new values of the changed pixels.
+    Pixels(Vec<(u16, u8)>),
+    /// The new screen, after a change of resolution.
+    Resized(Vec<u8>),
+}
+
+/// The changes made by an instruction.
+struct Delta {
+    cpu: CpuState,
+    /// Addresses and new values of the bytes written.
+    memory: Vec<(u16, u8)>,
+    screen: ScreenDelta,
+}
+
+struct Segment {
+    keyframe: Keyframe,
+    deltas: Vec<Delta>,
+}
+
+/// The recorded history of an `Emulator`.
+pub struct Rewind {
+    segments: VecDeque<Segment>,
+    keyframe_interval: usize,
+    max_keyframes: usize,
+}
+
+impl Default for Rewind {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Rewind {
+    /// Returns a `Rewind` keeping about 2 minutes of history.
+    pub fn new() -> Rewind {
+        Rewind::with_capacity(KEYFRAME_INTERVAL, MAX_KEYFRAMES)
+    }
+
+    /// Returns a `Rewind` saving a keyframe every `keyframe_interval`
+    /// instructions, and keeping at most `max_keyframes` of them.
+    pub fn with_capacity(keyframe_interval: usize, max_keyframes: usize) -> Rewind {
+        Rewind {
+            segments: VecDeque::new(),
+            keyframe_interval: keyframe_interval.max(1),
+            max_keyframes: max_keyframes.max(1),
+        }
+    }
+
+    /// Forgets the recorded history, when another ROM or snapshot is loaded.
+    pub fn clear(&mut self) {
+        self.segments.clear();
+    }
+
+    /// Returns the number of instructions that can be rewound.
+    pub fn len(&self) -> usize {
+        self.segments
+            .iter()
+            .map(|segment| segment.deltas.len())
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Runs `Emulator::cycle` and records the changes it made.
+    pub fn cycle(&mut self, emulator: &mut Emulator) -> Result<StepOutcome, EmulatorError> {
+        if emulator.has_exited() {
+            return emulator.cycle();
+        }
+
+        // The timers and the vblank flag also change between instructions.
+        let full = match self.segments.back_mut() {
+            Some(segment) => {
+                if let Some(delta) = segment.deltas.last_mut() {
+                    delta.cpu = emulator.cpu_state();
+                }
+                segment.deltas.len() >= self.keyframe_interval
+            }
+            None => true,
+        };
+        if full {
+            self.segments.push_back(Segment {
+                keyframe: Keyframe {
+                    cpu: emulator.cpu_state(),
+                    memory: emulator.memory().to_vec(),
+                    screen: emulator.screen.clone(),
+                },
+                deltas: Vec::new(),
+            });
+            if self.segments.len() > self.max_keyframes {
+                self.segments.pop_front();
+            }
+        }
+
+        let screen = emulator.screen.clone();
+        emulator.start_journal();
+        let outcome = emulator.cycle();
+
+        let memory = emulator
+            .take_journal()
+            .into_iter()
+            .flat_map(|(start, len)| start..start + len)
+            .map(|address| (address as u16, emulator.memory()[address]))
+            .collect();
+        let screen = match screen.len() == emulator.screen.len() {
+            true => ScreenDelta::Pixels(
+                screen
+                    .iter()
+                    .zip(emulator.screen.iter())
+                    .enumerate()
+                    .filter(|(_, (old, new))| old != new)
+                    .map(|(index, (_, &new))| (index as u16, new))
+                    .collect(),
+            ),
+            false => ScreenDelta::Resized(emulator.screen.clone()),
+        };
+
+        if let Some(segment) = self.segments.back_mut() {
+            segment.deltas.push(Delta {
+                cpu: emulator.cpu_state(),
+                memory,
+                screen,
+            });
+        }
+        outcome
+    }
+
+    /// Returns the recorded instructions as a text trace, oldest first: a
+    /// line per instruction with its address and opcode, followed by the
+    /// registers, `I`, the stack pointer and the timers after it.
+    pub fn trace(&self) -> String {
+        let registers = (0..16)
+            .map(|register| format!("V{:X}", register))
+            .collect::<Vec<String>>()
+            .join(" ");
+        let mut trace = format!("ADDR OP   {} I    SP DT ST\n", registers);
+        for segment in &self.segments {
+            let mut previous = &segment.keyframe.cpu;
+            for delta in &segment.deltas {
+                trace.push_str(&delta.cpu.trace_line(previous.program_counter()));
+                trace.push('\n');
+                previous = &delta.cpu;
+            }
+        }
+        trace
+    }
+
+    /// Restores the state of `emulator` before its last recorded instruction,
+    /// and returns whether there was one.
+    pub fn step_back(&mut self, emulator: &mut Emulator) -> bool {
+        let at_keyframe = match self.segments.back_mut() {
+            Some(segment) => segment.deltas.pop().is_none(),
+            None => return false,
+        };
+        if at_keyframe {
+            // The keyframe is the state after the last delta of the previous
+            // segment.
+            self.segments.pop_back();
+            match self.segments.back_mut() {
+                Some(segment) => segment.deltas.pop(),
+                None => return false,
+            };
+        }
+        self.restore(emulator);
+        true
+    }
+
+    /// Restores the latest recorded state of `emulator` before the current
+    /// one for which `predicate` holds, and returns whether there was one.
+    /// Otherwise, `emulator` is restored to its oldest recorded state.
+    pub fn rewind_until(
+        &mut self,
+        emulator: &mut Emulator,
+        mut predicate: impl FnMut(&Emulator) -> bool,
+    ) -> bool {
+        while !self.segments.is_empty() {
+            let oldest = self.segments.len() == 1;
+            let segment = match self.segments.back_mut() {
+                Some(segment) => segment,
+                None => break,
+            };
+            // The state after the last delta of a segment is either the
+            // current one, or the keyframe of the next segment, already
+            // checked.
+            let mut found = None;
+            restore_keyframe(emulator, &segment.keyframe);
+            for (index, delta) in segment.deltas.iter().enumerate() {
+                if predicate(emulator) {
+                    found = Some(index);
+                }
+                apply(emulator, delta);
+            }
+
+            match found {
+                Some(index) => {
+                    segment.deltas.truncate(index);
+                    self.restore(emulator);
+                    return true;
+                }
+                None if oldest => {
+                    segment.deltas.clear();
+                    self.restore(emulator);
+                    return false;
+                }
+                None => {
+                    self.segments.pop_back();
+                }
+            }
+        }
+        false
+    }
+
+    /// Restores the state after the last delta of the last segment.
+    fn restore(&self, emulator: &mut Emulator) {
+        if let Some(segment) = self.segments.back() {
+            restore_keyframe(emulator, &segment.keyframe);
+            for delta in &segment.deltas {
+                apply(emulator, delta);
+            }
+        }
+    }
+}
+
+fn restore_keyframe(emulator: &mut Emulator, keyframe: &Keyframe) {
+    emulator.set_cpu_state(&keyframe.cpu);
+    emulator.memory_mut().copy_from_slice(&keyframe.memory);
+    emulator.screen = keyframe.screen.clone();
+}
+
+fn apply(emulator: &mut Emulator, delta: &Delta) {
+    emulator.set_cpu_state(&delta.cpu);
+    let memory = emulator.memory_mut();
+    for &(address, value) in &delta.memory {
+        memory[address as usize] = value;
+    }
+    match &delta.screen {
+        ScreenDelta::Pixels(pixels) => {
+            for &(index, value) in pixels {
+                emulator.screen[index as usize] = value;
+            }
+        }
+        ScreenDelta::Resized(screen) => emulator.screen = screen.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assembled_emulator;
+
+    /// A ROM writing to memory and drawing at every iteration of its loop.
+    const SOURCE: &str = "
+        : main
+            i := buffer
+            loop
+                v0 += 1
+                save v0
+                sprite v0 v0 1
+            again
+        : buffer
+            0
+    ";
+
+    type State = (CpuState, Vec<u8>, Vec<u8>);
+
+    fn state(emulator: &Emulator) -> State {
+        (
+            emulator.cpu_state(),
+            emulator.memory().to_vec(),
+            emulator.screen.clone(),
+        )
+    }
+
+    #[test]
+    fn steps_back_across_keyframes() {
+        let mut emulator = assembled_emulator(SOURCE);
+        let mut rewind = Rewind::with_capacity(4, 100);
+        let mut states = Vec::new();
+        for _ in 0..10 {
+            states.push(state(&emulator));
+            rewind.cycle(&mut emulator).unwrap();
+        }
+        assert_eq!(rewind.len(), 10);
+
+        for expected in states.iter().rev() {
+            assert!(rewind.step_back(&mut emulator));
+            assert!(state(&emulator) == *expected);
+        }
+        assert!(!rewind.step_back(&mut emulator));
+        assert!(rewind.is_empty());
+    }
+
+    #[test]
+    fn rewinds_until_a_state() {
+        let mut emulator = assembled_emulator(SOURCE);
+        let mut rewind = Rewind::with_capacity(4, 100);
+        let mut states = Vec::new();
+        for _ in 0..10 {
+            states.push(state(&emulator));
+            rewind.cycle(&mut emulator).unwrap();
+        }
+
+        // The second iteration of the loop, drawing for `v0 == 2`.
+        assert!(rewind.rewind_until(&mut emulator, |emulator| {
+            emulator.registers()[0] == 2 && emulator.program_counter() == 0x206
+        }));
+        assert!(state(&emulator) == states[7]);
+        assert_eq!(rewind.len(), 7);
+    }
+
+    #[test]
+    fn traces_the_recorded_instructions() {
+        let mut emulator = assembled_emulator(SOURCE);
+        let mut rewind = Rewind::with_capacity(4, 100);
+        for _ in 0..6 {
+            rewind.cycle(&mut emulator).unwrap();
+        }
+        let trace = rewind.trace();
+        let lines: Vec<&str> = trace.lines().collect();
+        assert_eq!(lines.len(), 7);
+        assert!(lines[0].starts_with("ADDR OP   V0 V1"));
+        let addresses: Vec<&str> = lines[1..].iter().map(|line| &line[..4]).collect();
+        assert_eq!(addresses, ["0200", "0202", "0204", "0206", "0208", "0202"]);
+        // `v0 += 1` of the second iteration of the loop.
+        assert!(lines[6].starts_with("0202 7001 02 00"));
+    }
+
+    #[test]
+    fn forgets_the_oldest_keyframes() {
+        let mut emulator = assembled_emulator(SOURCE);
+        let mut rewind = Rewind::with_capacity(4, 2);
+        for _ in 0..10 {
+            rewind.cycle(&mut emulator).unwrap();
+        }
+        // The last segment holds the 2 latest instructions.
+        assert_eq!(rewind.len(), 6);
+        while rewind.step_back(&mut emulator) {}
+        assert_eq!(emulator.registers()[0], 1);
+    }
+}
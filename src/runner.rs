@@ -0,0 +1,90 @@
+//! # A module to run the `Emulator` at 60 frames per second from the browser.
+//!
+//! A `Runner` owns the `Emulator` and its history, and runs the frames due at
+//! each tick of an event loop, applying the requests of the `Frontend` in
+//! between. It drives the `Emulator` the same way on the page and in a
+//! worker.
+use crate::cpu::{Emulator, FaultPolicy};
+use crate::frontend::{Frontend, Session};
+use crate::rewind::Rewind;
+use crate::scheduler::Scheduler;
+
+pub struct Runner {
+    pub emulator: Emulator,
+    rewind: Rewind,
+    scheduler: Scheduler,
+    session: Option<Session>,
+}
+
+impl Runner {
+    /// Returns a `Runner` of an `Emulator` without ROM, pausing on faults and
+    /// tracing its memory accesses for the memory viewer.
+    pub fn new() -> Runner {
+        let mut emulator = Emulator::new();
+        emulator.load_font();
+        emulator.set_fault_policy(FaultPolicy::Break);
+        emulator.trace_memory(true);
+
+        Runner {
+            emulator,
+            rewind: Rewind::new(),
+            scheduler: Scheduler::default(),
+            session: None,
+        }
+    }
+
+    /// Applies the requests of `frontend`, then runs the frames due at `now`,
+    /// in milliseconds. Returns whether the `Emulator` rewound or ran, so that
+    /// its screen is redrawn.
+    pub fn tick(&mut self, frontend: &Frontend, now: f64) -> bool {
+        let frames = self.scheduler.frames(now);
+        self.scheduler.instructions_per_frame = *frontend.instructions_per_frame.borrow();
+        frontend.handle_step_request(&self.emulator);
+        frontend.handle_watchpoints(&mut self.emulator);
+        frontend.handle_movie(&mut self.emulator, &mut self.rewind, &mut self.session);
+        frontend.handle_quirks(&mut self.emulator, &self.session);
+        frontend.handle_slots(&mut self.emulator, &mut self.rewind, &mut self.session);
+        frontend.handle_download(&self.emulator);
+        frontend.handle_trace(&self.rewind);
+        frontend.handle_copy(&self.emulator);
+        if frontend.handle_edits(&mut self.emulator) {
+            // Neither the movie nor the history can reproduce the edits.
+            frontend.stop_movie(&mut self.session);
+            self.rewind.clear();
+        }
+        frontend.handle_memory_request(&self.emulator);
+
+        if frontend.handle_rewind(&mut self.emulator, &mut self.rewind) {
+            frontend.stop_movie(&mut self.session);
+            true
+        } else if *frontend.running.borrow() {
+            'frames: for _ in 0..frames {
+                for _ in 0..self.scheduler.instructions_per_frame {
+                    if !frontend.sync_keypad(&mut self.emulator, &mut self.session) {
+                        break 'frames;
+                    }
+                    let outcome = self.rewind.cycle(&mut self.emulator);
+
+                    frontend.handle_outcome(outcome);
+                    frontend.handle_breakpoints(&self.emulator);
+                    if !*frontend.running.borrow() {
+                        break;
+                    }
+                }
+                frontend.vblank(&mut self.emulator, &mut self.session);
+                if !*frontend.running.borrow() {
+                    break;
+                }
+            }
+            frontend.handle_snapshot_hotswap(
+                &mut self.emulator,
+                &mut self.rewind,
+                &mut self.session,
+            );
+            frontend.handle_rom_hotswap(&mut self.emulator, &mut self.rewind, &mut self.session);
+            true
+        } else {
+            false
+        }
+    }
+}
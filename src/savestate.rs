@@ -0,0 +1,271 @@
+//! # A module to save the state of the `Emulator` in a versioned binary format.
+//!
+//! A save state starts with a header: the `MAGIC` bytes, the `VERSION` of the
+//! format, the SHA-1 of the ROM it was saved from, the `Quirks`, and whether
+//! the state that follows is compressed. The state holds everything needed to
+//! resume the `Emulator` exactly, from its CPU and memory to its keypad and
+//! random number generator. It is encoded with bincode, then compressed with
+//! DEFLATE, the 64 KiB of memory being mostly empty.
+//!
+//! Loading a save state fails if it was saved from another ROM than the one
+//! running. The JSON snapshots of the debugger, which don't know their ROM,
+//! are converted by `migrate`. `Slots` hold numbered save states, for quick
+//! saves and quick loads.
+use crate::cpu::{CpuState, Emulator, MEMORY_SIZE};
+use crate::quirks::Quirks;
+use core::fmt;
+use serde::{Deserialize, Serialize};
+
+/// First bytes of every save state.
+pub const MAGIC: [u8; 4] = *b"CH8S";
+
+/// Version of the format written by `save`.
+pub const VERSION: u16 = 1;
+
+/// Numbered save states kept by `Slots`.
+pub const SLOTS: usize = 10;
+
+/// The decompressed state can't be much larger than the memory, which stops
+/// corrupted states from exhausting it.
+const MAX_STATE_SIZE: usize = 2 * MEMORY_SIZE;
+
+/// The header of a save state, following the `MAGIC` bytes.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Header {
+    pub version: u16,
+    /// SHA-1 of the ROM the state was saved from.
+    pub rom_hash: [u8; 20],
+    pub quirks: Quirks,
+    pub compressed: bool,
+}
+
+/// The state of the `Emulator` following the `Header`.
+#[derive(Serialize, Deserialize)]
+struct State {
+    cpu: CpuState,
+    memory: Vec<u8>,
+    screen: Vec<u8>,
+    keypad: [bool; 16],
+}
+
+/// Why a save state couldn't be loaded.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum SaveStateError {
+    /// The bytes don't start with `MAGIC`.
+    NotASaveState,
+    /// The state was saved in a newer version of the format.
+    UnsupportedVersion(u16),
+    /// The state was saved from another ROM than the one running.
+    RomMismatch,
+    /// The state is truncated or corrupted.
+    Corrupted,
+    /// The JSON snapshot to migrate is invalid.
+    InvalidSnapshot(String),
+    /// The slot is out of `0..SLOTS`, or holds no state.
+    EmptySlot(usize),
+}
+
+/// Display Trait to report a `SaveStateError` to the user.
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveStateError::NotASaveState => write!(f, "Not a save state."),
+            SaveStateError::UnsupportedVersion(version) => write!(
+                f,
+                "Save state version {} unsupported, at most {} is.",
+                version, VERSION
+            ),
+            SaveStateError::RomMismatch => {
+                write!(f, "Save state saved from another ROM than the one running.")
+            }
+            SaveStateError::Corrupted => write!(f, "Save state corrupted."),
+            SaveStateError::InvalidSnapshot(error) => write!(f, "Invalid snapshot: {}.", error),
+            SaveStateError::EmptySlot(slot) => write!(f, "No save state in slot {}.", slot),
+        }
+    }
+}
+
+impl std::error::Error for SaveStateError {}
+
+/// Returns the SHA-1 of `rom`, identifying it in save states.
+pub fn rom_hash(rom: &[u8]) -> [u8; 20] {
+    sha1_smol::Sha1::from(rom).digest().bytes()
+}
+
+/// Returns the save state of `emulator`, running `rom`.
+pub fn save(emulator: &Emulator, rom: &[u8]) -> Vec<u8> {
+    let header = Header {
+        version: VERSION,
+        rom_hash: rom_hash(rom),
+        quirks: emulator.quirks(),
+        compressed: true,
+    };
+    let state = State {
+        cpu: emulator.cpu_state(),
+        memory: emulator.memory().to_vec(),
+        screen: emulator.screen.clone(),
+        keypad: emulator.keypad,
+    };
+
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend(bincode::serialize(&header).unwrap());
+    let state = bincode::serialize(&state).unwrap();
+    bytes.extend(miniz_oxide::deflate::compress_to_vec(&state, 6));
+    bytes
+}
+
+/// Returns the `Header` of a save state.
+pub fn header(bytes: &[u8]) -> Result<Header, SaveStateError> {
+    split(bytes).map(|(header, _)| header)
+}
+
+/// Restores a save state of `rom` into `emulator`, along with its quirks.
+pub fn load(emulator: &mut Emulator, rom: &[u8], bytes: &[u8]) -> Result<(), SaveStateError> {
+    let (header, state) = split(bytes)?;
+    if header.rom_hash != rom_hash(rom) {
+        return Err(SaveStateError::RomMismatch);
+    }
+
+    let state = match header.compressed {
+        true => miniz_oxide::inflate::decompress_to_vec_with_limit(state, MAX_STATE_SIZE)
+            .map_err(|_| SaveStateError::Corrupted)?,
+        false => state.to_vec(),
+    };
+    let state: State = bincode::deserialize(&state).map_err(|_| SaveStateError::Corrupted)?;
+    if state.memory.len() != MEMORY_SIZE
+        || state.screen.len() != state.cpu.screen_size()
+        || !state.cpu.is_valid()
+    {
+        return Err(SaveStateError::Corrupted);
+    }
+
+    emulator.set_quirks(header.quirks);
+    emulator.set_cpu_state(&state.cpu);
+    emulator.memory_mut().copy_from_slice(&state.memory);
+    emulator.screen = state.screen;
+    emulator.keypad = state.keypad;
+    Ok(())
+}
+
+/// Returns the save state of a JSON snapshot, assumed to be taken from `rom`.
+/// Save states are returned unchanged.
+pub fn migrate(bytes: &[u8], rom: &[u8]) -> Result<Vec<u8>, SaveStateError> {
+    if bytes.starts_with(&MAGIC) {
+        return Ok(bytes.to_vec());
+    }
+    let snapshot: Emulator = serde_json::from_slice(bytes)
+        .map_err(|error| SaveStateError::InvalidSnapshot(error.to_string()))?;
+    Ok(save(&snapshot, rom))
+}
+
+/// Splits a save state into its header and its state.
+fn split(bytes: &[u8]) -> Result<(Header, &[u8]), SaveStateError> {
+    let mut rest = bytes
+        .strip_prefix(&MAGIC[..])
+        .ok_or(SaveStateError::NotASaveState)?;
+    // The version comes first, so that newer headers are reported as such.
+    let version = match rest {
+        [low, high, ..] => u16::from_le_bytes([*low, *high]),
+        _ => return Err(SaveStateError::Corrupted),
+    };
+    if version > VERSION {
+        return Err(SaveStateError::UnsupportedVersion(version));
+    }
+    let header: Header =
+        bincode::deserialize_from(&mut rest).map_err(|_| SaveStateError::Corrupted)?;
+    Ok((header, rest))
+}
+
+/// Numbered save states, for quick saves and quick loads.
+pub struct Slots {
+    slots: Vec<Option<Vec<u8>>>,
+}
+
+impl Default for Slots {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Slots {
+    /// Returns `SLOTS` empty slots.
+    pub fn new() -> Slots {
+        Slots {
+            slots: vec![None; SLOTS],
+        }
+    }
+
+    /// Returns the save state in `slot`, if any.
+    pub fn get(&self, slot: usize) -> Option<&[u8]> {
+        self.slots.get(slot)?.as_deref()
+    }
+
+    /// Saves the state of `emulator`, running `rom`, in `slot`.
+    pub fn save(&mut self, slot: usize, emulator: &Emulator, rom: &[u8]) {
+        if let Some(state) = self.slots.get_mut(slot) {
+            *state = Some(save(emulator, rom));
+        }
+    }
+
+    /// Restores the save state in `slot` into `emulator`.
+    pub fn load(
+        &self,
+        slot: usize,
+        emulator: &mut Emulator,
+        rom: &[u8],
+    ) -> Result<(), SaveStateError> {
+        let state = self.get(slot).ok_or(SaveStateError::EmptySlot(slot))?;
+        load(emulator, rom, state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROM: [u8; 4] = [0x60, 0x05, 0x12, 0x02];
+
+    fn emulator() -> Emulator {
+        let mut emulator = Emulator::new();
+        emulator.load_rom(&ROM).unwrap();
+        emulator.cycle().unwrap();
+        emulator
+    }
+
+    /// Returns the save state of a JSON snapshot of `emulator` with `field`
+    /// set to `value`.
+    fn tampered(emulator: &Emulator, field: &str, value: u64) -> Vec<u8> {
+        let mut snapshot = serde_json::to_value(emulator).unwrap();
+        snapshot[field] = value.into();
+        migrate(&serde_json::to_vec(&snapshot).unwrap(), &ROM).unwrap()
+    }
+
+    #[test]
+    fn loads_a_saved_state() {
+        let saved = emulator();
+        let mut loaded = Emulator::new();
+        load(&mut loaded, &ROM, &save(&saved, &ROM)).unwrap();
+        assert!(loaded.cpu_state() == saved.cpu_state());
+        assert_eq!(loaded.memory(), saved.memory());
+        assert_eq!(
+            load(&mut loaded, &[0x00, 0xE0], &save(&saved, &ROM)),
+            Err(SaveStateError::RomMismatch)
+        );
+    }
+
+    #[test]
+    fn rejects_states_out_of_range() {
+        let emulator = emulator();
+        for (field, value) in [("stack_pointer", 17), ("plane", 4)].iter() {
+            let state = tampered(&emulator, field, *value);
+            let mut loaded = Emulator::new();
+            assert_eq!(
+                load(&mut loaded, &ROM, &state),
+                Err(SaveStateError::Corrupted),
+                "{} = {}",
+                field,
+                value
+            );
+        }
+    }
+}
@@ -0,0 +1,59 @@
+//! # A module to pace the `Emulator` at 60 frames per second.
+//!
+//! Each frame, a frontend runs `instructions_per_frame` cycles of the
+//! `Emulator`, then signals the vertical blank interrupt which decrements its
+//! timers. The `Scheduler` tells how many frames are due from the time
+//! elapsed, so that the timers and the sound tick 60 times per second whatever
+//! the refresh rate of the frontend, and the game speed only depends on the
+//! instructions per frame.
+
+/// Frames emulated per second, the rate of the chip8 timers.
+pub const FRAME_RATE: f64 = 60.0;
+
+/// Frames emulated at most at once, when catching up after the frontend was
+/// suspended, such as a hidden browser tab.
+const MAX_FRAMES: usize = 4;
+
+/// Instructions per frame of most chip8 games.
+pub const DEFAULT_INSTRUCTIONS_PER_FRAME: usize = 10;
+
+pub struct Scheduler {
+    pub instructions_per_frame: usize,
+    /// Time of the last frame, in milliseconds.
+    last_frame: Option<f64>,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new(DEFAULT_INSTRUCTIONS_PER_FRAME)
+    }
+}
+
+impl Scheduler {
+    pub fn new(instructions_per_frame: usize) -> Scheduler {
+        Scheduler {
+            instructions_per_frame,
+            last_frame: None,
+        }
+    }
+
+    /// Returns the frames to emulate at `now`, a time in milliseconds, since
+    /// the previous call.
+    pub fn frames(&mut self, now: f64) -> usize {
+        let period = 1000.0 / FRAME_RATE;
+        let last_frame = match self.last_frame {
+            Some(last_frame) => last_frame,
+            None => {
+                self.last_frame = Some(now);
+                return 1;
+            }
+        };
+
+        let frames = ((now - last_frame) / period).max(0.0) as usize;
+        self.last_frame = Some(match frames > MAX_FRAMES {
+            true => now,
+            false => last_frame + frames as f64 * period,
+        });
+        frames.min(MAX_FRAMES)
+    }
+}
@@ -0,0 +1,86 @@
+//! # A module to persist ROMs and save states in the browser storage.
+//!
+//! The `localStorage` of the page holds a small library of the ROMs played,
+//! the name of the last one, and the save states of the quick save slots of
+//! each ROM, keyed by its SHA-1. Bytes are stored as binary strings, one
+//! character per byte, as read by `FileReader::read_as_binary_string`.
+use crate::savestate;
+use web_sys::{console, Storage};
+
+const ROM_PREFIX: &str = "chiphuit.rom.";
+const LAST_ROM: &str = "chiphuit.last_rom";
+const STATE_PREFIX: &str = "chiphuit.state.";
+
+/// Returns the `localStorage` of the page, unless the browser denies it.
+fn storage() -> Option<Storage> {
+    web_sys::window()?.local_storage().ok()?
+}
+
+fn get(key: &str) -> Option<Vec<u8>> {
+    let value = storage()?.get_item(key).ok()??;
+    Some(value.chars().map(|x| x as u8).collect())
+}
+
+/// Stores `bytes` under `key`, logging the failure when the storage is full.
+fn set(key: &str, bytes: &[u8]) {
+    let value: String = bytes.iter().map(|&x| x as char).collect();
+    if let Some(storage) = storage() {
+        if storage.set_item(key, &value).is_err() {
+            console::log_1(&"Browser storage full.".into());
+        }
+    }
+}
+
+/// Returns the names of the ROMs of the library, sorted.
+pub fn roms() -> Vec<String> {
+    let storage = match storage() {
+        Some(storage) => storage,
+        None => return Vec::new(),
+    };
+    let mut names: Vec<String> = (0..storage.length().unwrap_or(0))
+        .filter_map(|index| storage.key(index).ok()?)
+        .filter_map(|key| Some(key.strip_prefix(ROM_PREFIX)?.to_string()))
+        .collect();
+    names.sort();
+    names
+}
+
+pub fn rom(name: &str) -> Option<Vec<u8>> {
+    get(&format!("{}{}", ROM_PREFIX, name))
+}
+
+/// Adds a ROM to the library, replacing any ROM of the same name, and
+/// remembers it as the last one played.
+pub fn save_rom(name: &str, rom: &[u8]) {
+    set(&format!("{}{}", ROM_PREFIX, name), rom);
+    set(LAST_ROM, name.as_bytes());
+}
+
+pub fn remove_rom(name: &str) {
+    if let Some(storage) = storage() {
+        let _ = storage.remove_item(&format!("{}{}", ROM_PREFIX, name));
+    }
+}
+
+/// Returns the name of the last ROM played, if it is still in the library.
+pub fn last_rom() -> Option<String> {
+    let name = String::from_utf8(get(LAST_ROM)?).ok()?;
+    rom(&name).map(|_| name)
+}
+
+/// Returns the save state of `rom` in `slot`, if any.
+pub fn state(rom: &[u8], slot: usize) -> Option<Vec<u8>> {
+    get(&state_key(rom, slot))
+}
+
+pub fn save_state(rom: &[u8], slot: usize, state: &[u8]) {
+    set(&state_key(rom, slot), state);
+}
+
+fn state_key(rom: &[u8], slot: usize) -> String {
+    let hash: String = savestate::rom_hash(rom)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!("{}{}.{}", STATE_PREFIX, hash, slot)
+}
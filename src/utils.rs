@@ -4,40 +4,37 @@ use std::cell::RefCell;
 use std::rc::Rc;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
-use web_sys::{window, Element, Event, FileReader, HtmlElement, HtmlInputElement};
-
-pub const EMULATOR_VARIABLES: [&str; 9] = [
-    "current opcode",
-    "registers",
-    "index register",
-    "program counter",
-    "delay timer",
-    "sound timer",
-    "stack pointer",
-    "stack",
-    "running",
-];
-
-/// Util function to append a `web_sys::Node` to the body.
-pub fn append_to_body(element: &web_sys::Node) {
-    web_sys::window()
+use web_sys::{
+    window, Blob, Element, Event, FileReader, HtmlAnchorElement, HtmlElement, HtmlInputElement,
+    HtmlLabelElement, Url,
+};
+
+/// Util function to get the element of class `class` inside `root`, the
+/// container of an emulator instance.
+pub fn find(root: &Element, class: &str) -> Element {
+    root.query_selector(&format!(".{}", class))
         .unwrap()
-        .document()
+        .unwrap_or_else(|| panic!("should have a .{} element.", class))
+}
+
+/// Util function to create a file input wrapped in its label, so that the
+/// label opens it without referring to it by id.
+pub fn create_file_input(label_text: &str) -> HtmlLabelElement {
+    let fileinput: HtmlInputElement = document()
+        .create_element("input")
         .unwrap()
-        .body()
-        .expect("document should have a body")
-        .append_child(element)
+        .dyn_into::<HtmlInputElement>()
         .unwrap();
-}
+    fileinput.set_type("file");
 
-/// Util function to append a `web_sys::Node` to an HTML element selected by
-/// its id.
-pub fn append_element_to_another(element: &web_sys::Node, id: &str) {
-    document()
-        .get_element_by_id(id)
+    let label: HtmlLabelElement = document()
+        .create_element("label")
         .unwrap()
-        .append_child(element)
+        .dyn_into::<HtmlLabelElement>()
         .unwrap();
+    label.set_inner_text(label_text);
+    label.append_child(&fileinput).unwrap();
+    label
 }
 
 /// Util function to get a `web_sys::Document`.
@@ -51,6 +48,18 @@ pub fn document() -> web_sys::Document {
 /// Util function to set basic attributes of HTML page.
 pub fn set_document() {
     document().set_title("chip8 emulator");
+}
+
+/// Util function to link the stylesheet of the emulator in the page, unless
+/// the page already links it.
+pub fn set_stylesheet() {
+    let linked = document()
+        .query_selector("link[href$='chiphuit.css']")
+        .unwrap()
+        .is_some();
+    if linked {
+        return;
+    }
 
     let head = document().head().unwrap();
 
@@ -61,17 +70,6 @@ pub fn set_document() {
     head.append_child(&link).unwrap();
 }
 
-/// Util function for the event loop.
-pub fn set_timeout(f: &Closure<dyn FnMut()>, timeout_ms: i32) -> i32 {
-    web_sys::window()
-        .expect("should have a window.")
-        .set_timeout_with_callback_and_timeout_and_arguments_0(
-            f.as_ref().unchecked_ref(),
-            timeout_ms,
-        )
-        .expect("should register `setTimeout` OK")
-}
-
 #[allow(unused_must_use)]
 /// Util function to put a string in the clipboard
 pub fn to_clipboard(content: String) {
@@ -83,6 +81,23 @@ pub fn to_clipboard(content: String) {
         .write_text(&content);
 }
 
+/// Util function to download `content` as a file named `name`.
+pub fn download(name: &str, content: &[u8]) {
+    let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(content));
+    let blob = Blob::new_with_u8_array_sequence(&parts).unwrap();
+    let url = Url::create_object_url_with_blob(&blob).unwrap();
+
+    let link = document()
+        .create_element("a")
+        .unwrap()
+        .dyn_into::<HtmlAnchorElement>()
+        .unwrap();
+    link.set_href(&url);
+    link.set_download(name);
+    link.click();
+    Url::revoke_object_url(&url).unwrap();
+}
+
 /// Util function to set listeners and callbacks on buttons.
 /// Handles clicks on virtual keypad.
 pub fn set_callback_to_button(
@@ -124,9 +139,11 @@ pub fn set_callback_to_button(
     touch_callback.forget();
 }
 
-/// Util function to set listeners and callbacks on keyboard keys.
+/// Util function to set listeners and callbacks on keyboard keys, pressed
+/// while `target` has the focus.
 /// Handles user input done with the keyboard.
 pub fn set_callback_to_key(
+    target: &Element,
     press: bool,
     key: String,
     keypad: &Rc<RefCell<[bool; 16]>>,
@@ -139,8 +156,7 @@ pub fn set_callback_to_key(
         }
     }) as Box<dyn FnMut(_)>);
 
-    web_sys::window()
-        .unwrap()
+    target
         .add_event_listener_with_callback(
             match press {
                 true => "keydown",
@@ -152,37 +168,57 @@ pub fn set_callback_to_key(
     callback.forget();
 }
 
-/// Util Closure to switch from keypad view to debugger view and vice-versa.
-pub fn change_view() -> Closure<dyn FnMut(web_sys::MouseEvent)> {
-    Closure::wrap(Box::new(move |_event: web_sys::MouseEvent| {
-        let debugger_style = document()
-            .get_element_by_id("debugger")
-            .unwrap()
-            .dyn_into::<HtmlElement>()
-            .unwrap()
-            .style();
+/// Util function to keep the keys typed in `element` from reaching the
+/// keypad, rewind and quick save listeners of the emulator container.
+pub fn stop_key_propagation(element: &Element) {
+    for event in ["keydown", "keyup"].iter() {
+        let closure = Closure::wrap(Box::new(|event: web_sys::KeyboardEvent| {
+            event.stop_propagation();
+        }) as Box<dyn FnMut(_)>);
 
-        let keypad_style = document()
-            .get_element_by_id("keypad")
-            .unwrap()
-            .dyn_into::<HtmlElement>()
-            .unwrap()
-            .style();
+        element
+            .add_event_listener_with_callback(event, closure.as_ref().unchecked_ref())
+            .unwrap();
+        closure.forget();
+    }
+}
 
-        match debugger_style
-            .get_property_value("display")
-            .unwrap()
-            .as_str()
-        {
-            "none" => {
-                debugger_style.set_property("display", "flex").unwrap();
-                keypad_style.set_property("display", "none").unwrap()
-            }
-            _ => {
-                debugger_style.set_property("display", "none").unwrap();
-                keypad_style.set_property("display", "grid").unwrap()
-            }
+/// Util function to get the style of the element of class `class` inside
+/// `root`.
+fn style(root: &Element, class: &str) -> web_sys::CssStyleDeclaration {
+    find(root, class).dyn_into::<HtmlElement>().unwrap().style()
+}
+
+/// Util function to display the debugger view, or the keypad view, of the
+/// emulator instance in `root`.
+pub fn set_view(root: &Element, debugger: bool) {
+    let debugger_style = style(root, "debugger");
+    let keypad_style = style(root, "keypad-base");
+
+    match debugger {
+        true => {
+            debugger_style.set_property("display", "flex").unwrap();
+            keypad_style.set_property("display", "none").unwrap()
         }
+        false => {
+            debugger_style.set_property("display", "none").unwrap();
+            keypad_style.set_property("display", "grid").unwrap()
+        }
+    }
+}
+
+/// Util Closure to switch from keypad view to debugger view and vice-versa.
+pub fn change_view(root: &Element) -> Closure<dyn FnMut(web_sys::MouseEvent)> {
+    let root = root.clone();
+    Closure::wrap(Box::new(move |_event: web_sys::MouseEvent| {
+        set_view(
+            &root,
+            style(&root, "debugger")
+                .get_property_value("display")
+                .unwrap()
+                .as_str()
+                == "none",
+        )
     }) as Box<dyn FnMut(_)>)
 }
 
@@ -204,65 +240,3 @@ pub fn read_user_file(filereader: FileReader) -> Closure<dyn FnMut(Event)> {
         filereader.read_as_binary_string(&file).unwrap();
     }) as Box<dyn FnMut(_)>)
 }
-
-// Utils to allow JSON serialization of big arrays with serde
-// shouldn't be necessary anymore when serde 2.0 gets released. Code snippet
-// from  https://github.com/serde-rs/serde/issues/1937#issuecomment-812137971
-pub mod arrays {
-    use std::{convert::TryInto, marker::PhantomData};
-
-    use serde::{
-        de::{SeqAccess, Visitor},
-        ser::SerializeTuple,
-        Deserialize, Deserializer, Serialize, Serializer,
-    };
-    pub fn serialize<S: Serializer, T: Serialize, const N: usize>(
-        data: &[T; N],
-        ser: S,
-    ) -> Result<S::Ok, S::Error> {
-        let mut s = ser.serialize_tuple(N)?;
-        for item in data {
-            s.serialize_element(item)?;
-        }
-        s.end()
-    }
-
-    struct ArrayVisitor<T, const N: usize>(PhantomData<T>);
-
-    impl<'de, T, const N: usize> Visitor<'de> for ArrayVisitor<T, N>
-    where
-        T: Deserialize<'de>,
-    {
-        type Value = [T; N];
-
-        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-            formatter.write_str(&format!("an array of length {}", N))
-        }
-
-        #[inline]
-        fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
-        where
-            A: SeqAccess<'de>,
-        {
-            // can be optimized using MaybeUninit
-            let mut data = Vec::with_capacity(N);
-            for _ in 0..N {
-                match (seq.next_element())? {
-                    Some(val) => data.push(val),
-                    None => return Err(serde::de::Error::invalid_length(N, &self)),
-                }
-            }
-            match data.try_into() {
-                Ok(arr) => Ok(arr),
-                Err(_) => unreachable!(),
-            }
-        }
-    }
-    pub fn deserialize<'de, D, T, const N: usize>(deserializer: D) -> Result<[T; N], D::Error>
-    where
-        D: Deserializer<'de>,
-        T: Deserialize<'de>,
-    {
-        deserializer.deserialize_tuple(N, ArrayVisitor::<T, N>(PhantomData))
-    }
-}
@@ -0,0 +1,134 @@
+//! # A module to watch the accesses of the `Emulator` to ranges of its memory.
+//!
+//! Every read, write and instruction fetch of the `Emulator` goes through the
+//! same accessor, which checks the `Watchpoint`s set with
+//! `Emulator::set_watchpoints`. The instruction accessing a watched range runs
+//! to completion, then `Emulator::cycle` reports the access as a
+//! `StepOutcome::Watchpoint`.
+//!
+//! Watchpoints can be written as text, an address or an inclusive range of
+//! addresses followed by the watched accesses, reads and writes by default:
+//! - `0x300` watches the reads and writes of the address 0x300,
+//! - `0x000-0x04F w` watches the writes to the small font,
+//! - `0x200-0x2FF x` watches the instructions executed in this range.
+use crate::breakpoint::parse_value;
+use core::fmt;
+use core::str::FromStr;
+use serde::{Deserialize, Serialize};
+
+/// A kind of access to memory.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Access {
+    Read,
+    Write,
+    /// Fetch of an instruction to execute.
+    Execute,
+}
+
+/// A range of memory watched for some kinds of accesses.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Watchpoint {
+    pub start: u16,
+    /// Last address of the range, included.
+    pub end: u16,
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+}
+
+impl Watchpoint {
+    /// Returns the first watched address of the `len` bytes accessed from
+    /// `start`, if the watchpoint watches `access`.
+    pub fn watches(&self, access: Access, start: usize, len: usize) -> Option<u16> {
+        let watched = match access {
+            Access::Read => self.read,
+            Access::Write => self.write,
+            Access::Execute => self.execute,
+        };
+        let first = start.max(self.start as usize);
+        if watched && len > 0 && first <= self.end as usize && first < start + len {
+            Some(first as u16)
+        } else {
+            None
+        }
+    }
+}
+
+/// Parses a `Watchpoint` written as `start[-end] [rwx]`.
+impl FromStr for Watchpoint {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        let (range, accesses) = match words[..] {
+            [range] => (range, "rw"),
+            [range, accesses] => (range, accesses),
+            _ => return Err(format!("invalid watchpoint: {}", text)),
+        };
+
+        let (start, end) = match range.split_once('-') {
+            Some((start, end)) => (parse_value(start)?, parse_value(end)?),
+            None => (parse_value(range)?, parse_value(range)?),
+        };
+        if start > end {
+            return Err(format!("invalid range: {}", range));
+        }
+        if accesses.is_empty() || !accesses.chars().all(|access| "rwx".contains(access)) {
+            return Err(format!("invalid accesses: {}", accesses));
+        }
+
+        Ok(Watchpoint {
+            start,
+            end,
+            read: accesses.contains('r'),
+            write: accesses.contains('w'),
+            execute: accesses.contains('x'),
+        })
+    }
+}
+
+/// Display Trait to write a `Watchpoint` back in the syntax it is parsed from.
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#05X}", self.start)?;
+        if self.end != self.start {
+            write!(f, "-{:#05X}", self.end)?;
+        }
+        write!(f, " ")?;
+        for (watched, access) in [(self.read, 'r'), (self.write, 'w'), (self.execute, 'x')].iter() {
+            if *watched {
+                write!(f, "{}", access)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// An access of an instruction to a watched range of memory.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct WatchpointHit {
+    /// Index of the `Watchpoint` in the ones set on the `Emulator`.
+    pub watchpoint: usize,
+    pub access: Access,
+    /// First watched address accessed.
+    pub address: u16,
+    /// Address of the accessing instruction.
+    pub program_counter: u16,
+    pub opcode: u16,
+}
+
+/// Display Trait to report a `WatchpointHit` to the user.
+impl fmt::Display for WatchpointHit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let access = match self.access {
+            Access::Read => "read",
+            Access::Write => "write",
+            Access::Execute => "execution",
+        };
+        write!(
+            f,
+            "Watchpoint hit: {} of {:#05X} by {:04X} at {:#05X}.",
+            access, self.address, self.opcode, self.program_counter
+        )
+    }
+}
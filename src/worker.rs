@@ -0,0 +1,346 @@
+//! # A module to run the `Emulator` in a dedicated worker.
+//!
+//! When its container has a `data-worker` attribute, an `Emulator` runs in a
+//! worker loaded from the script the attribute names, `chiphuit-worker.js` by
+//! default, so that the DOM updates of the UI don't slow the emulation down.
+//! The worker draws the screen on the canvas of the page, transferred as an
+//! `OffscreenCanvas`, or posts the framebuffers to the page when the browser
+//! can't transfer it.
+//!
+//! At each animation frame, the page posts an `Input` with the state of its
+//! `Frontend`, and the worker posts an `Output` with the state of the
+//! `Emulator` after each of its ticks, both encoded with `bincode`.
+use crate::audio::{self, Buzzer, FmOsc};
+use crate::breakpoint::Breakpoint;
+use crate::debugger::{Debugger, View};
+use crate::disassembler::Syntax;
+use crate::frontend::{
+    Edit, Effect, Follow, Frontend, MemoryRequest, RewindRequest, SlotRequest, StepRequest,
+};
+use crate::graphics;
+use crate::movie::Movie;
+use crate::runner::Runner;
+use crate::scheduler::FRAME_RATE;
+use crate::watchpoint::Watchpoint;
+use js_sys::Uint8Array;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{
+    console, CanvasRenderingContext2d, DedicatedWorkerGlobalScope, HtmlCanvasElement, MessageEvent,
+    OffscreenCanvas, OffscreenCanvasRenderingContext2d, Worker,
+};
+
+/// Script of the worker when the `data-worker` attribute is empty.
+pub const DEFAULT_SCRIPT: &str = "chiphuit-worker.js";
+
+/// The state of the `Frontend` of the page, posted to the worker.
+#[derive(Serialize, Deserialize)]
+struct Input {
+    /// Increases with every `Input`, to know which ones the worker applied.
+    sequence: u32,
+    keypad: [bool; 16],
+    /// The `running` and `recording` states toggled by the user since the
+    /// last `Output`.
+    running: Option<bool>,
+    recording: Option<bool>,
+    rewinding: bool,
+    rom: Vec<u8>,
+    rom_name: Option<String>,
+    rewind_request: Option<RewindRequest>,
+    load_snapshot: Option<Vec<u8>>,
+    slot: usize,
+    slot_request: Option<SlotRequest>,
+    slot_state: Option<Vec<u8>>,
+    download_request: bool,
+    trace_request: bool,
+    copy_request: bool,
+    breakpoints: Vec<Breakpoint>,
+    step_request: Option<StepRequest>,
+    watchpoints: Option<Vec<Watchpoint>>,
+    seed: Option<u64>,
+    load_movie: Option<Movie>,
+    instructions_per_frame: usize,
+    vblank_wait: bool,
+    edits: Vec<Edit>,
+    memory_request: Option<MemoryRequest>,
+    memory_follow: Follow,
+    disassembly_focus: Option<(u16, u16)>,
+    syntax: Syntax,
+}
+
+/// The state of the `Emulator` in the worker, posted to the page.
+#[derive(Serialize, Deserialize)]
+struct Output {
+    /// The sequence of the last `Input` applied.
+    acknowledged: u32,
+    running: bool,
+    recording: bool,
+    view: View,
+    buzzer: Buzzer,
+    /// The screen and its size, unless the worker draws on the canvas.
+    screen: Option<(Vec<u8>, u32, u32)>,
+    effects: Vec<Effect>,
+}
+
+/// The page side of an `Emulator` running in a worker.
+pub struct Remote {
+    worker: Worker,
+    /// Set once the worker listens to the page.
+    ready: Rc<RefCell<bool>>,
+    outputs: Rc<RefCell<Vec<Output>>>,
+    /// The canvas of the page, until it is transferred to the worker.
+    canvas: Option<HtmlCanvasElement>,
+    /// The context to draw the framebuffers posted by the worker, if the
+    /// canvas couldn't be transferred.
+    context: Option<CanvasRenderingContext2d>,
+    sequence: u32,
+    /// The sequence of the last `Input` toggling `running` or `recording`,
+    /// before which the `Output`s of the worker are outdated.
+    toggled: u32,
+    /// The `running` and `recording` states of the worker.
+    running: bool,
+    recording: bool,
+}
+
+impl Remote {
+    /// Starts a worker from `script`, to run the `Emulator` drawn on
+    /// `canvas`.
+    pub fn spawn(script: &str, canvas: HtmlCanvasElement) -> Result<Remote, JsValue> {
+        let worker = Worker::new(script)?;
+        let ready = Rc::new(RefCell::new(false));
+        let outputs = Rc::new(RefCell::new(Vec::new()));
+
+        let (ready_clone, outputs_clone) = (Rc::clone(&ready), Rc::clone(&outputs));
+        let onmessage = Closure::wrap(Box::new(move |event: MessageEvent| {
+            match event.data().dyn_into::<Uint8Array>() {
+                Ok(bytes) => match bincode::deserialize(&bytes.to_vec()) {
+                    Ok(output) => outputs_clone.borrow_mut().push(output),
+                    Err(error) => console::log_1(&error.to_string().into()),
+                },
+                Err(_) => *ready_clone.borrow_mut() = true,
+            }
+        }) as Box<dyn FnMut(_)>);
+        worker.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+        onmessage.forget();
+
+        Ok(Remote {
+            worker,
+            ready,
+            outputs,
+            canvas: Some(canvas),
+            context: None,
+            sequence: 0,
+            toggled: 0,
+            running: false,
+            recording: false,
+        })
+    }
+
+    /// Applies the `Output`s of the worker to the page, then posts it the
+    /// state of `frontend`. To be called at each animation frame.
+    pub fn sync(&mut self, frontend: &Frontend, debugger: &Debugger, audio_context: &mut FmOsc) {
+        if !*self.ready.borrow() {
+            return;
+        }
+        if let Some(canvas) = self.canvas.take() {
+            self.transfer(canvas);
+        }
+
+        let mut last = None;
+        for output in self.outputs.take() {
+            if output.acknowledged >= self.toggled {
+                self.running = output.running;
+                self.recording = output.recording;
+                *frontend.running.borrow_mut() = output.running;
+                *frontend.recording.borrow_mut() = output.recording;
+            }
+            frontend.effects.borrow_mut().extend(output.effects);
+            if let (Some(context), Some((screen, width, height))) = (&self.context, &output.screen)
+            {
+                graphics::draw_screen(context, screen, *width, *height);
+            }
+            last = Some((output.view, output.buzzer));
+        }
+        if let Some((view, buzzer)) = last {
+            debugger.show(&view);
+            audio::sound(&buzzer, audio_context);
+        }
+
+        let input = self.input(frontend, *debugger.syntax.borrow());
+        let bytes = Uint8Array::from(&bincode::serialize(&input).unwrap()[..]);
+        self.worker.post_message(&bytes).unwrap();
+    }
+
+    /// Transfers `canvas` to the worker, or keeps drawing on it from the page
+    /// if the browser can't.
+    fn transfer(&mut self, canvas: HtmlCanvasElement) {
+        match canvas.transfer_control_to_offscreen() {
+            Ok(offscreen) => self
+                .worker
+                .post_message_with_transfer(&offscreen, &js_sys::Array::of1(&offscreen))
+                .unwrap(),
+            Err(_) => self.context = Some(graphics::context(&canvas)),
+        }
+    }
+
+    /// Takes the requests of `frontend` into an `Input`.
+    fn input(&mut self, frontend: &Frontend, syntax: Syntax) -> Input {
+        self.sequence += 1;
+        let running = *frontend.running.borrow();
+        let recording = *frontend.recording.borrow();
+        let toggled = running != self.running || recording != self.recording;
+        if toggled {
+            self.toggled = self.sequence;
+            self.running = running;
+            self.recording = recording;
+        }
+
+        Input {
+            sequence: self.sequence,
+            keypad: *frontend.keypad.borrow(),
+            running: Some(running).filter(|_| toggled),
+            recording: Some(recording).filter(|_| toggled),
+            rewinding: *frontend.rewinding.borrow(),
+            rom: frontend.rom_buffer.take(),
+            rom_name: frontend.rom_name.take(),
+            rewind_request: frontend.rewind_request.take(),
+            load_snapshot: frontend.load_snapshot.take(),
+            slot: *frontend.slot.borrow(),
+            slot_request: frontend.slot_request.take(),
+            slot_state: frontend.slot_state.take(),
+            download_request: frontend.download_request.replace(false),
+            trace_request: frontend.trace_request.replace(false),
+            copy_request: frontend.copy_request.replace(false),
+            breakpoints: frontend.breakpoints.borrow().breakpoints().to_vec(),
+            step_request: frontend.step_request.take(),
+            watchpoints: frontend.watchpoints.take(),
+            seed: *frontend.seed.borrow(),
+            load_movie: frontend.load_movie.take(),
+            instructions_per_frame: *frontend.instructions_per_frame.borrow(),
+            vblank_wait: *frontend.vblank_wait.borrow(),
+            edits: frontend.edits.take(),
+            memory_request: frontend.memory_request.take(),
+            memory_follow: *frontend.memory_follow.borrow(),
+            disassembly_focus: *frontend.disassembly_focus.borrow(),
+            syntax,
+        }
+    }
+}
+
+/// Applies an `Input` of the page to the `Frontend` of the worker, keeping
+/// the requests not handled yet.
+fn apply(frontend: &Frontend, input: Input) {
+    *frontend.keypad.borrow_mut() = input.keypad;
+    if let Some(running) = input.running {
+        *frontend.running.borrow_mut() = running;
+    }
+    if let Some(recording) = input.recording {
+        *frontend.recording.borrow_mut() = recording;
+    }
+    *frontend.rewinding.borrow_mut() = input.rewinding;
+    if !input.rom.is_empty() {
+        *frontend.rom_buffer.borrow_mut() = input.rom;
+        *frontend.rom_name.borrow_mut() = input.rom_name;
+    }
+    keep(&frontend.rewind_request, input.rewind_request);
+    keep(&frontend.load_snapshot, input.load_snapshot);
+    *frontend.slot.borrow_mut() = input.slot;
+    keep(&frontend.slot_request, input.slot_request);
+    keep(&frontend.slot_state, input.slot_state);
+    *frontend.download_request.borrow_mut() |= input.download_request;
+    *frontend.trace_request.borrow_mut() |= input.trace_request;
+    *frontend.copy_request.borrow_mut() |= input.copy_request;
+    frontend.breakpoints.borrow_mut().set(&input.breakpoints);
+    keep(&frontend.step_request, input.step_request);
+    keep(&frontend.watchpoints, input.watchpoints);
+    *frontend.seed.borrow_mut() = input.seed;
+    keep(&frontend.load_movie, input.load_movie);
+    *frontend.instructions_per_frame.borrow_mut() = input.instructions_per_frame;
+    *frontend.vblank_wait.borrow_mut() = input.vblank_wait;
+    frontend.edits.borrow_mut().extend(input.edits);
+    keep(&frontend.memory_request, input.memory_request);
+    *frontend.memory_follow.borrow_mut() = input.memory_follow;
+    *frontend.disassembly_focus.borrow_mut() = input.disassembly_focus;
+}
+
+/// Sets `handle` to `value`, unless it is `None`.
+fn keep<T>(handle: &Rc<RefCell<Option<T>>>, value: Option<T>) {
+    if value.is_some() {
+        *handle.borrow_mut() = value;
+    }
+}
+
+/// Runs the `Emulator` in the worker of `scope`, driven by the `Input`s of
+/// the page, at 60 frames per second.
+pub fn run(scope: DedicatedWorkerGlobalScope) -> Result<(), JsValue> {
+    let frontend = Rc::new(Frontend::new());
+    let context: Rc<RefCell<Option<OffscreenCanvasRenderingContext2d>>> =
+        Rc::new(RefCell::new(None));
+    let acknowledged = Rc::new(RefCell::new(0));
+    let syntax = Rc::new(RefCell::new(Syntax::Classic));
+
+    let (frontend_clone, context_clone) = (Rc::clone(&frontend), Rc::clone(&context));
+    let (acknowledged_clone, syntax_clone) = (Rc::clone(&acknowledged), Rc::clone(&syntax));
+    let onmessage = Closure::wrap(Box::new(move |event: MessageEvent| {
+        let data = event.data();
+        if let Some(canvas) = data.dyn_ref::<OffscreenCanvas>() {
+            *context_clone.borrow_mut() = canvas
+                .get_context("2d")
+                .ok()
+                .flatten()
+                .and_then(|context| context.dyn_into().ok());
+            return;
+        }
+        let input: Input = match data
+            .dyn_into::<Uint8Array>()
+            .map(|bytes| bincode::deserialize(&bytes.to_vec()))
+        {
+            Ok(Ok(input)) => input,
+            _ => return console::log_1(&"Invalid message from the page.".into()),
+        };
+        *acknowledged_clone.borrow_mut() = input.sequence;
+        *syntax_clone.borrow_mut() = input.syntax;
+        apply(&frontend_clone, input);
+    }) as Box<dyn FnMut(_)>);
+    scope.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+    onmessage.forget();
+
+    let mut runner = Runner::new();
+    let scope_clone = scope.clone();
+    let tick = Closure::wrap(Box::new(move || {
+        let moved = runner.tick(&frontend, js_sys::Date::now());
+
+        let emulator = &runner.emulator;
+        let (width, height) = (emulator.width() as u32, emulator.height() as u32);
+        let screen = match &*context.borrow() {
+            Some(context) if moved => {
+                graphics::draw_offscreen(context, &emulator.screen, width, height);
+                None
+            }
+            None if moved => Some((emulator.screen.clone(), width, height)),
+            _ => None,
+        };
+        let output = Output {
+            acknowledged: *acknowledged.borrow(),
+            running: *frontend.running.borrow(),
+            recording: *frontend.recording.borrow(),
+            view: View::new(emulator, &frontend, *syntax.borrow()),
+            buzzer: Buzzer::new(emulator),
+            screen,
+            effects: frontend.effects.take(),
+        };
+        let bytes = Uint8Array::from(&bincode::serialize(&output).unwrap()[..]);
+        scope_clone.post_message(&bytes).unwrap();
+    }) as Box<dyn FnMut()>);
+    scope.set_interval_with_callback_and_timeout_and_arguments_0(
+        tick.as_ref().unchecked_ref(),
+        (1000.0 / FRAME_RATE) as i32,
+    )?;
+    tick.forget();
+
+    // Tells the page that the worker listens.
+    scope.post_message(&JsValue::NULL)
+}